@@ -0,0 +1,51 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use glam::Vec2;
+use map_to_3D::edge::Edge;
+use map_to_3D::sectorpolygonbuilder as spb;
+use std::f32::consts::TAU;
+
+/// A big round outdoor area with a grid of square pillars in it, like the
+/// kind of sector which has thousands of lines.
+fn big_sector(outline_vertices: usize, pillars_across: usize) -> (Vec<Vec2>, Vec<Edge>) {
+    let radius = 8192.;
+    let mut verts: Vec<Vec2> = (0..outline_vertices).map(|i| {
+        let angle = i as f32 / outline_vertices as f32 * TAU;
+        Vec2::new(angle.cos(), angle.sin()) * radius
+    }).collect();
+    let mut edges: Vec<Edge> = (0..outline_vertices)
+        .map(|i| Edge::new(i, (i + 1) % outline_vertices))
+        .collect();
+    // Keep the pillars inside of the circle
+    let spacing = radius * 1.2 / pillars_across as f32;
+    let start = -spacing * pillars_across as f32 / 2.;
+    for x in 0..pillars_across {
+        for y in 0..pillars_across {
+            let corner = Vec2::new(
+                start + x as f32 * spacing, start + y as f32 * spacing);
+            let first = verts.len();
+            verts.extend([
+                corner,
+                corner + Vec2::new(0., 32.),
+                corner + Vec2::new(32., 32.),
+                corner + Vec2::new(32., 0.),
+            ]);
+            edges.extend((0..4).map(|i| Edge::new(first + i, first + (i + 1) % 4)));
+        }
+    }
+    (verts, edges)
+}
+
+fn build_big_sector(c: &mut Criterion) {
+    // 2000 outline edges, and 1600 pillar edges
+    let (verts, edges) = big_sector(2000, 20);
+    c.bench_function("build_polygons 3600 edges", |b| {
+        b.iter(|| spb::build_polygons(black_box(&edges), black_box(&verts)))
+    });
+    let (verts, edges) = big_sector(500, 10);
+    c.bench_function("build_polygons 900 edges", |b| {
+        b.iter(|| spb::build_polygons(black_box(&edges), black_box(&verts)))
+    });
+}
+
+criterion_group!(benches, build_big_sector);
+criterion_main!(benches);
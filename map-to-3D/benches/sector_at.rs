@@ -0,0 +1,56 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use glam::Vec2;
+use map_to_3D::edge::Edge;
+use map_to_3D::sectorindex::SectorIndex;
+use map_to_3D::sectorpolygonbuilder::{self as spb, PolygonBuildResult};
+
+/// A grid of square sectors, each with a square hole in the middle, like a
+/// big map with lots of small sectors.
+fn sector_grid(across: usize) -> (Vec<Vec2>, Vec<PolygonBuildResult>) {
+    let size = 128.;
+    let mut verts = Vec::new();
+    let sectors: Vec<Vec<Edge>> = (0..across * across).map(|sector| {
+        let corner = Vec2::new((sector % across) as f32, (sector / across) as f32) * size;
+        [(0., size), (32., size - 64.)].into_iter().flat_map(|(offset, width)| {
+            let first = verts.len();
+            verts.extend([(0., 0.), (0., 1.), (1., 1.), (1., 0.)]
+                .map(|(x, y)| corner + Vec2::splat(offset) + Vec2::new(x, y) * width));
+            (0..4).map(move |i| Edge::new(first + i, first + (i + 1) % 4))
+        }).collect()
+    }).collect();
+    let polygons = sectors.iter()
+        .map(|edges| spb::build_polygons_detailed(edges, &verts))
+        .collect();
+    (verts, polygons)
+}
+
+/// Find the sector by testing every polygon of every sector
+fn brute_force(polygons: &[PolygonBuildResult], verts: &[Vec2], point: Vec2) -> Option<usize> {
+    polygons.iter().position(|result| {
+        result.polygons.iter()
+            .filter(|polygon| polygon.contains(point, verts))
+            .count() % 2 == 1
+    })
+}
+
+fn sector_at(c: &mut Criterion) {
+    // 2500 sectors, with 5000 polygons
+    let (verts, polygons) = sector_grid(50);
+    let index = SectorIndex::from_polygons(&polygons, &verts);
+    let points: Vec<Vec2> = (0..100)
+        .map(|i| Vec2::new(i as f32 * 61. + 3., i as f32 * 37. + 5.))
+        .collect();
+    c.bench_function("sector_at 100 points, every polygon", |b| {
+        b.iter(|| points.iter()
+            .filter_map(|&point| brute_force(black_box(&polygons), &verts, point))
+            .count())
+    });
+    c.bench_function("sector_at 100 points, SectorIndex", |b| {
+        b.iter(|| points.iter()
+            .filter_map(|&point| black_box(&index).sector_at(point))
+            .count())
+    });
+}
+
+criterion_group!(benches, sector_at);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+#![warn(clippy::all)]
+//! Converts a UDMF TEXTMAP to a binary glTF file, so that it can be viewed in
+//! Blender, or any other program which can open glTF files.
+//!
+//! Usage: `cargo run --example gltf -- TEXTMAP map.glb [--portals]`
+//!
+//! With `--portals`, the geometry seen through portals is copied to where it
+//! is seen.
+use std::{env, fs, str::FromStr};
+use std::io::BufWriter;
+use glam::Vec2;
+use parsers::udmf::input::UDMFMap;
+use map_to_3D::export::{self, gltf};
+use map_to_3D::level;
+use map_to_3D::portal::PortalGraph;
+use map_to_3D::wall::WallOptions;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let (Some(textmap), Some(output)) = (args.next(), args.next()) else {
+        println!("Usage: gltf TEXTMAP OUTPUT.glb [--portals]");
+        return;
+    };
+    let textmap = fs::read_to_string(textmap).expect("Failed to read the TEXTMAP");
+    let map = UDMFMap::from_str(&textmap).expect("Failed to parse the TEXTMAP");
+    let planes = level::sector_planes(&map);
+    let options = WallOptions::default();
+    // The textures aren't loaded, so assume they are all 64x64
+    let mut meshes = export::map_meshes(
+        &map, &planes, &options, |_| Vec2::splat(64.));
+    if args.next().is_some_and(|arg| arg == "--portals") {
+        let portals = PortalGraph::for_map(&map, &options);
+        meshes.extend(export::stitch_portals(&map, &meshes, &portals, 4));
+    }
+    // The sky uses the preview image from the assets folder
+    let image_uri = |name: &str| if name == options.sky_texture {
+        String::from("sky.png")
+    } else {
+        format!("textures/{name}.png")
+    };
+    let file = fs::File::create(&output).expect("Failed to create the output file");
+    match gltf::write_glb(&meshes, image_uri, BufWriter::new(file)) {
+        Ok(()) => { println!("{:?}", fs::canonicalize(&output)); }
+        Err(e) => { println!("Failed to write {output}!\n{e}"); }
+    }
+}
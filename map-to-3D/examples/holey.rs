@@ -1,9 +1,11 @@
 #![warn(clippy::all)]
-use glam::Vec2;
+use glam::{Vec2, Vec3};
 use map_to_3D::edge::Edge;
 use map_to_3D::sectorpolygonbuilder as spb;
 use map_to_3D::plane::Plane;
+use map_to_3D::export::stl;
 use std::fs;
+use std::io::BufWriter;
 
 // See tests/data/holey.png for an illustration
 fn test_case() -> (Vec<Vec2>, Vec<Edge>) {
@@ -47,30 +49,19 @@ fn main() {
     let triangulated = spb::auto_triangulate(&polys, &verts);
 
     let secplane = Plane::Flat(0.);
-    let normal = secplane.normal(false);
-    let normal: String = (0usize..3).map(|co| format!("{} ", normal[co])).collect();
-    let mut stl: String = String::from("solid holey");
-    triangulated.iter().filter(|tp| tp.is_some()).for_each(|tp| {
-        let tp = tp.as_ref().unwrap();
-        tp.chunks_exact(3).for_each(|tri| {
-            stl.push_str("\nfacet normal ");
-            stl.push_str(normal.trim());
-            stl.push_str("\n\touter loop");
-            let a = verts[tri[0]];
-            let a = format!("{} {} {}", a.x, a.y, secplane.z_at(a));
-            let b = verts[tri[1]];
-            let b = format!("{} {} {}", b.x, b.y, secplane.z_at(b));
-            let c = verts[tri[2]];
-            let c = format!("{} {} {}", c.x, c.y, secplane.z_at(c));
-            stl.push_str("\n\t\tvertex "); stl.push_str(&a);
-            stl.push_str("\n\t\tvertex "); stl.push_str(&b);
-            stl.push_str("\n\t\tvertex "); stl.push_str(&c);
-            stl.push_str("\n\tendloop");
-            stl.push_str("\nendfacet");
-        });
-    });
-    stl.push_str("\nendsolid holey");
-    match fs::write("holey.stl", stl) {
+    let triangles: Vec<[Vec3; 3]> = triangulated.iter().flatten()
+        .flat_map(|tp| tp.chunks_exact(3))
+        .map(|tri| {
+            let [a, b, c] = [0, 1, 2].map(|i| {
+                let v = verts[tri[i]];
+                v.extend(secplane.z_at(v))
+            });
+            // The floor should face up
+            if (b - a).cross(c - a).z < 0. { [a, c, b] } else { [a, b, c] }
+        }).collect();
+    let written = fs::File::create("holey.stl")
+        .and_then(|file| stl::write_stl(&triangles, BufWriter::new(file)));
+    match written {
         Ok(()) => { println!("{:?}", fs::canonicalize("holey.stl")); }
         Err(e) => { println!("Failed to write holey.stl!\n{}", e); }
     };
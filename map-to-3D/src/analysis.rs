@@ -0,0 +1,745 @@
+//! # Map analysis
+//!
+//! Finds problems in a map which would make it look or play wrong in the
+//! game, like Doom Builder's map analysis mode. Many of the problems can be
+//! fixed automatically.
+//!
+//! Fixing some problems can reveal others (e.g. removing a zero-length line
+//! can leave its vertices unused), so the map should be analysed again after
+//! applying fixes.
+use glam::Vec2;
+use parsers::udmf::input::UDMFMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use ahash::RandomState;
+use crate::edit::{self, VERTEX_MERGE_DISTANCE};
+use crate::level::{self, LineSide, WallPart};
+use crate::segment::{Segment, Intersection};
+use crate::sectorpolygonbuilder::{self as spb, SectorPolygon};
+
+/// How bad a problem is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+    /// The map works, but probably not the way it was meant to
+    Warning,
+    /// The map will look or play wrong, or may not load at all
+    Error,
+}
+
+/// A problem found in a map
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProblemKind {
+    /// The line uses a vertex which doesn't exist
+    MissingVertex { line: usize },
+    /// The outline of the sector is not closed. The vertices are the ones
+    /// along the parts of the outline which could not be closed.
+    UnclosedSector { sector: usize, vertices: Vec<usize> },
+    /// The lines overlap or cross each other without sharing a vertex
+    OverlappingLines { first: usize, second: usize },
+    /// Both of the line's vertices are at the same position
+    ZeroLengthLine { line: usize },
+    /// The line's front sidedef doesn't exist
+    NoFrontSide { line: usize },
+    /// The sidedef refers to a sector which doesn't exist
+    MissingSector { sidedef: usize, sector: usize },
+    /// A part of the sidedef uses a texture which is not known
+    UnknownWallTexture { sidedef: usize, part: WallPart, texture: String },
+    /// The floor or ceiling of the sector uses a texture which is not known
+    UnknownFlat { sector: usize, ceiling: bool, texture: String },
+    /// The thing is stuck in an impassable line
+    StuckThing { thing: usize, line: usize },
+    /// The thing is not inside any sector
+    ThingOutsideMap { thing: usize },
+    /// The vertex is not used by any line
+    UnusedVertex { vertex: usize },
+    /// The sidedef is not used by any line
+    UnusedSidedef { sidedef: usize },
+    /// The sector is not used by any sidedef
+    UnusedSector { sector: usize },
+    /// The line's "twosided" flag doesn't match whether it has a back side
+    TwoSidedFlagMismatch { line: usize, two_sided: bool },
+}
+
+impl fmt::Display for ProblemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ProblemKind::*;
+        match self {
+            MissingVertex { line } =>
+                write!(f, "Line {line} uses a vertex which doesn't exist"),
+            UnclosedSector { sector, .. } =>
+                write!(f, "Sector {sector} is not closed"),
+            OverlappingLines { first, second } =>
+                write!(f, "Lines {first} and {second} overlap"),
+            ZeroLengthLine { line } => write!(f, "Line {line} has no length"),
+            NoFrontSide { line } => write!(f, "Line {line} has no front side"),
+            MissingSector { sidedef, sector } =>
+                write!(f, "Sidedef {sidedef} refers to sector {sector}, which doesn't exist"),
+            UnknownWallTexture { sidedef, part, texture } =>
+                write!(f, "Sidedef {sidedef} uses unknown {part:?} texture {texture}"),
+            UnknownFlat { sector, ceiling, texture } => {
+                let plane = if *ceiling { "ceiling" } else { "floor" };
+                write!(f, "Sector {sector} uses unknown {plane} texture {texture}")
+            },
+            StuckThing { thing, line } =>
+                write!(f, "Thing {thing} is stuck in line {line}"),
+            ThingOutsideMap { thing } =>
+                write!(f, "Thing {thing} is outside of the map"),
+            UnusedVertex { vertex } => write!(f, "Vertex {vertex} is unused"),
+            UnusedSidedef { sidedef } => write!(f, "Sidedef {sidedef} is unused"),
+            UnusedSector { sector } => write!(f, "Sector {sector} is unused"),
+            TwoSidedFlagMismatch { line, two_sided: true } =>
+                write!(f, "Line {line} has a back side, but is not flagged as two-sided"),
+            TwoSidedFlagMismatch { line, two_sided: false } =>
+                write!(f, "Line {line} is flagged as two-sided, but has no back side"),
+        }
+    }
+}
+
+/// An automatic fix for a problem. The indices are the ones from the map
+/// which was analysed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fix {
+    RemoveLine(usize),
+    RemoveVertex(usize),
+    RemoveSidedef(usize),
+    RemoveSector(usize),
+    RemoveThing(usize),
+    /// Flip the line, so that its back side becomes its front side
+    FlipBackToFront(usize),
+    /// Set or clear the line's "twosided" flag
+    SetTwoSided { line: usize, two_sided: bool },
+}
+
+/// A problem found in a map, and how to fix it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub severity: Severity,
+    /// Where the problem is, if it has a position in the map
+    pub position: Option<Vec2>,
+    /// How to fix the problem automatically, if it can be fixed
+    pub fix: Option<Fix>,
+}
+
+/// Extra information used to find problems in a map
+#[derive(Debug, Clone, Default)]
+pub struct AnalysisOptions {
+    /// The names of the textures and flats which can be used by the map, in
+    /// upper case. If this is `None`, textures are not checked.
+    pub textures: Option<HashSet<String, RandomState>>,
+    /// The radius of each type of thing, by editor number. Things with no
+    /// known radius are not checked for being stuck.
+    pub thing_radii: HashMap<u32, f32, RandomState>,
+}
+
+/// Analyse the map, and return the problems found in it, sorted by severity,
+/// with the worst problems first.
+///
+/// # Example
+///
+/// ```
+/// use glam::Vec2;
+/// use map_to_3D::analysis::{analyse, apply_fixes, AnalysisOptions, ProblemKind};
+/// use map_to_3D::edit::{draw_sector, EditDefaults};
+/// use parsers::udmf::input::{UDMFMap, UDMFVertex};
+///
+/// let mut map = UDMFMap::default();
+/// let room = [Vec2::new(0., 0.), Vec2::new(0., 64.), Vec2::new(64., 0.)];
+/// draw_sector(&mut map, &room, &EditDefaults::default()).unwrap();
+/// map.vertices.push(UDMFVertex { x: 128., y: 128., props: Default::default() });
+/// let problems = analyse(&map, &AnalysisOptions::default());
+/// assert_eq!(problems.len(), 1);
+/// assert_eq!(problems[0].kind, ProblemKind::UnusedVertex { vertex: 3 });
+/// apply_fixes(&mut map, problems.iter().filter_map(|p| p.fix.as_ref()));
+/// assert_eq!(map.vertices.len(), 3);
+/// ```
+pub fn analyse(map: &UDMFMap, options: &AnalysisOptions) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let valid_lines = check_line_vertices(map, &mut problems);
+    check_lines(map, &valid_lines, &mut problems);
+    check_overlapping_lines(map, &valid_lines, &mut problems);
+    check_sidedefs(map, options, &mut problems);
+    let sectors = check_sectors(map, options, &mut problems);
+    check_things(map, options, &valid_lines, &sectors, &mut problems);
+    check_unused_vertices(map, &mut problems);
+    problems.sort_by_key(|problem| std::cmp::Reverse(problem.severity));
+    problems
+}
+
+/// Apply the given fixes, which should come from analysing the same map.
+///
+/// Vertices which are still used by a line after the lines are removed are
+/// kept, so the vertex fixes can be applied without the line fixes.
+pub fn apply_fixes<'a>(map: &mut UDMFMap, fixes: impl IntoIterator<Item = &'a Fix>) {
+    let mut lines = Vec::new();
+    let mut vertices = Vec::new();
+    let mut sidedefs = Vec::new();
+    let mut sectors = Vec::new();
+    let mut things = Vec::new();
+    fixes.into_iter().for_each(|&fix| match fix {
+        Fix::RemoveLine(line) => lines.push(line),
+        Fix::RemoveVertex(vertex) => vertices.push(vertex),
+        Fix::RemoveSidedef(sidedef) => sidedefs.push(sidedef),
+        Fix::RemoveSector(sector) => sectors.push(sector),
+        Fix::RemoveThing(thing) => things.push(thing),
+        Fix::FlipBackToFront(line) => {
+            let l = &mut map.linedefs[line];
+            if let Some(back) = level::line_sidedef(l, LineSide::Back) {
+                std::mem::swap(&mut l.v1, &mut l.v2);
+                level::set_line_sidedef(l, LineSide::Front, Some(back));
+                level::set_line_sidedef(l, LineSide::Back, None);
+                l.props.remove("twosided");
+            }
+        },
+        Fix::SetTwoSided { line, two_sided } => {
+            let props = &mut map.linedefs[line].props;
+            if two_sided {
+                props.insert(String::from("twosided"), String::from("true"));
+            } else {
+                props.remove("twosided");
+            }
+        },
+    });
+    // Everything else refers to sidedefs by index, so they need to be removed
+    // before the lines, which also removes the sidedefs the lines used.
+    edit::remove_sidedefs(map, &sidedefs);
+    edit::remove_lines(map, &lines);
+    vertices.retain(|&vertex| !map.linedefs.iter().any(|l| {
+        l.v1 as usize == vertex || l.v2 as usize == vertex
+    }));
+    edit::remove_vertices(map, &vertices);
+    edit::remove_sectors(map, &sectors);
+    let mut index = 0;
+    map.things.retain(|_| {
+        index += 1;
+        !things.contains(&(index - 1))
+    });
+}
+
+fn line_middle(map: &UDMFMap, line: usize) -> Vec2 {
+    let Segment(a, b) = level::line_segment(map, line);
+    (a + b) / 2.
+}
+
+/// Check that the lines use vertices which exist, and return the lines which
+/// do.
+fn check_line_vertices(map: &UDMFMap, problems: &mut Vec<Problem>) -> Vec<usize> {
+    let vertex_count = map.vertices.len() as u32;
+    (0..map.linedefs.len()).filter(|&line| {
+        let l = &map.linedefs[line];
+        let valid = l.v1 < vertex_count && l.v2 < vertex_count;
+        if !valid {
+            problems.push(Problem {
+                kind: ProblemKind::MissingVertex { line },
+                severity: Severity::Error,
+                position: None,
+                fix: Some(Fix::RemoveLine(line)),
+            });
+        }
+        valid
+    }).collect()
+}
+
+fn check_lines(map: &UDMFMap, lines: &[usize], problems: &mut Vec<Problem>) {
+    lines.iter().copied().for_each(|line| {
+        let l = &map.linedefs[line];
+        let position = Some(line_middle(map, line));
+        let Segment(a, b) = level::line_segment(map, line);
+        if a.distance(b) < VERTEX_MERGE_DISTANCE {
+            problems.push(Problem {
+                kind: ProblemKind::ZeroLengthLine { line },
+                severity: Severity::Error,
+                position,
+                fix: Some(Fix::RemoveLine(line)),
+            });
+        }
+        let back = level::line_sidedef(l, LineSide::Back)
+            .filter(|&s| s < map.sidedefs.len());
+        if l.sidefront as usize >= map.sidedefs.len() {
+            problems.push(Problem {
+                kind: ProblemKind::NoFrontSide { line },
+                severity: Severity::Error,
+                position,
+                fix: Some(match back {
+                    Some(_) => Fix::FlipBackToFront(line),
+                    None => Fix::RemoveLine(line),
+                }),
+            });
+        }
+        let flagged = level::prop_flag(&l.props, "twosided");
+        // Lines with no front side are fixed by flipping or removing them, so
+        // their flags don't matter yet.
+        if (l.sidefront as usize) < map.sidedefs.len() && flagged != back.is_some() {
+            problems.push(Problem {
+                kind: ProblemKind::TwoSidedFlagMismatch {
+                    line, two_sided: back.is_some()
+                },
+                severity: Severity::Warning,
+                position,
+                fix: Some(Fix::SetTwoSided { line, two_sided: back.is_some() }),
+            });
+        }
+    });
+}
+
+fn check_overlapping_lines(
+    map: &UDMFMap,
+    lines: &[usize],
+    problems: &mut Vec<Problem>
+) {
+    // Sort the lines from left to right, so that only the lines which overlap
+    // horizontally need to be compared.
+    let segments: Vec<(usize, Segment)> = lines.iter()
+        .map(|&line| (line, level::line_segment(map, line)))
+        .filter(|(_, Segment(a, b))| a.distance(*b) >= VERTEX_MERGE_DISTANCE)
+        .collect();
+    let mut order: Vec<usize> = (0..segments.len()).collect();
+    let left = |index: usize| segments[index].1.0.x.min(segments[index].1.1.x);
+    let right = |index: usize| segments[index].1.0.x.max(segments[index].1.1.x);
+    order.sort_by(|&a, &b| left(a).total_cmp(&left(b)));
+    order.iter().enumerate().for_each(|(position, &index)| {
+        let (first, a) = segments[index];
+        order[position + 1..].iter()
+            .take_while(|&&other| left(other) <= right(index))
+            .for_each(|&other| {
+                let (second, b) = segments[other];
+                let (first, second) = (first.min(second), first.max(second));
+                // One line ends in the middle of the other
+                let touching =
+                    [a.0, a.1].into_iter().any(|p| edit::on_segment_interior(p, b)) ||
+                    [b.0, b.1].into_iter().any(|p| edit::on_segment_interior(p, a));
+                let fix = match a.intersection(b) {
+                    // Removing one of the lines would leave a sector which
+                    // only the removed line was on unclosed
+                    Some(Intersection::Same) => (side_sectors(map, first) ==
+                        side_sectors(map, second)).then_some(Fix::RemoveLine(second)),
+                    Some(Intersection::Collinear) => None,
+                    Some(Intersection::Normal(point)) if
+                        edit::on_segment_interior(point, a) ||
+                        edit::on_segment_interior(point, b) => None,
+                    _ if touching => None,
+                    _ => return,
+                };
+                problems.push(Problem {
+                    kind: ProblemKind::OverlappingLines { first, second },
+                    severity: Severity::Error,
+                    position: Some(line_middle(map, first)),
+                    fix,
+                });
+            });
+    });
+}
+
+/// Get the sectors on either side of the line, in order, without repeats
+fn side_sectors(map: &UDMFMap, line: usize) -> Vec<usize> {
+    let mut sectors: Vec<usize> = [LineSide::Front, LineSide::Back].into_iter()
+        .filter_map(|side| level::side_sector(map, line, side)).collect();
+    sectors.sort_unstable();
+    sectors.dedup();
+    sectors
+}
+
+fn check_sidedefs(
+    map: &UDMFMap,
+    options: &AnalysisOptions,
+    problems: &mut Vec<Problem>
+) {
+    // The line each sidedef is used by, for finding where the sidedef is
+    let mut used_by: Vec<Option<usize>> = vec![None; map.sidedefs.len()];
+    let vertex_count = map.vertices.len() as u32;
+    map.linedefs.iter().enumerate()
+        .filter(|(_, l)| l.v1 < vertex_count && l.v2 < vertex_count)
+        .for_each(|(line, l)| {
+        [LineSide::Front, LineSide::Back].into_iter()
+            .filter_map(|side| level::line_sidedef(l, side))
+            .for_each(|sidedef| {
+                if let Some(used_by) = used_by.get_mut(sidedef) {
+                    used_by.get_or_insert(line);
+                }
+            });
+    });
+    map.sidedefs.iter().enumerate().for_each(|(index, sidedef)| {
+        let Some(line) = used_by[index] else {
+            problems.push(Problem {
+                kind: ProblemKind::UnusedSidedef { sidedef: index },
+                severity: Severity::Warning,
+                position: None,
+                fix: Some(Fix::RemoveSidedef(index)),
+            });
+            return;
+        };
+        let position = Some(line_middle(map, line));
+        if sidedef.sector as usize >= map.sectors.len() {
+            problems.push(Problem {
+                kind: ProblemKind::MissingSector {
+                    sidedef: index, sector: sidedef.sector as usize
+                },
+                severity: Severity::Error,
+                position,
+                fix: None,
+            });
+        }
+        let Some(textures) = &options.textures else { return; };
+        WallPart::ALL.into_iter().for_each(|part| {
+            let texture = &part.texture(sidedef).0;
+            if texture != "-" && !textures.contains(&texture.to_ascii_uppercase()) {
+                problems.push(Problem {
+                    kind: ProblemKind::UnknownWallTexture {
+                        sidedef: index, part, texture: texture.clone()
+                    },
+                    severity: Severity::Warning,
+                    position,
+                    fix: None,
+                });
+            }
+        });
+    });
+}
+
+/// The polygons of a sector, for finding out whether points are inside it
+struct SectorShape(Vec<SectorPolygon>);
+
+impl SectorShape {
+    fn contains(&self, point: Vec2, vertices: &[Vec2]) -> bool {
+        let polygons = &self.0;
+        polygons.iter().enumerate().any(|(index, polygon)| {
+            polygon.hole_of.is_none() &&
+            polygon.contains(point, vertices) &&
+            !polygons.iter().any(|hole| {
+                hole.hole_of == Some(index) && hole.contains(point, vertices)
+            })
+        })
+    }
+}
+
+fn check_sectors(
+    map: &UDMFMap,
+    options: &AnalysisOptions,
+    problems: &mut Vec<Problem>
+) -> Vec<SectorShape> {
+    let vertices = level::vertex_positions(map);
+    let sector_edges = level::sector_edges(map);
+    let mut used = vec![false; map.sectors.len()];
+    map.sidedefs.iter().for_each(|sidedef| {
+        if let Some(used) = used.get_mut(sidedef.sector as usize) {
+            *used = true;
+        }
+    });
+    map.sectors.iter().enumerate().map(|(index, sector)| {
+        if !used[index] {
+            problems.push(Problem {
+                kind: ProblemKind::UnusedSector { sector: index },
+                severity: Severity::Warning,
+                position: None,
+                fix: Some(Fix::RemoveSector(index)),
+            });
+            return SectorShape(Vec::new());
+        }
+        let result = spb::build_polygons_detailed(&sector_edges[index], &vertices);
+        let mut open: Vec<usize> = Vec::new();
+        result.incomplete.iter().flat_map(|chain| chain.vertices.iter())
+            .for_each(|&vertex| {
+                if !open.contains(&vertex) {
+                    open.push(vertex);
+                }
+            });
+        if let Some(chain) = result.incomplete.first() {
+            problems.push(Problem {
+                position: Some(vertices[chain.stopped_at]),
+                kind: ProblemKind::UnclosedSector { sector: index, vertices: open },
+                severity: Severity::Error,
+                fix: None,
+            });
+        }
+        let polygons = result.polygons;
+        if let Some(textures) = &options.textures {
+            let position = polygons.first()
+                .and_then(|p| p.vertices.first())
+                .map(|&v| vertices[v]);
+            [(&sector.texturefloor, false), (&sector.textureceiling, true)]
+                .into_iter().for_each(|(texture, ceiling)| {
+                if texture != "-" && !textures.contains(&texture.to_ascii_uppercase()) {
+                    problems.push(Problem {
+                        kind: ProblemKind::UnknownFlat {
+                            sector: index, ceiling, texture: texture.clone()
+                        },
+                        severity: Severity::Warning,
+                        position,
+                        fix: None,
+                    });
+                }
+            });
+        }
+        SectorShape(polygons)
+    }).collect()
+}
+
+/// Does the segment go through the square with the given center and half
+/// width?
+fn segment_in_square(segment: Segment, center: Vec2, radius: f32) -> bool {
+    let Segment(a, b) = segment;
+    let (min, max) = (center - radius, center + radius);
+    if a.max(b).cmplt(min).any() || a.min(b).cmpgt(max).any() {
+        return false;
+    }
+    // The segment goes through the square if the square's corners are not
+    // all on the same side of it.
+    let direction = b - a;
+    let sides = [
+        Vec2::new(min.x, min.y), Vec2::new(min.x, max.y),
+        Vec2::new(max.x, min.y), Vec2::new(max.x, max.y),
+    ].map(|corner| direction.perp_dot(corner - a));
+    !(sides.iter().all(|&s| s > 0.) || sides.iter().all(|&s| s < 0.))
+}
+
+fn check_things(
+    map: &UDMFMap,
+    options: &AnalysisOptions,
+    lines: &[usize],
+    sectors: &[SectorShape],
+    problems: &mut Vec<Problem>
+) {
+    let vertices = level::vertex_positions(map);
+    let blocking: Vec<usize> = lines.iter().copied().filter(|&line| {
+        let l = &map.linedefs[line];
+        level::line_sidedef(l, LineSide::Back).is_none() ||
+        level::prop_flag(&l.props, "blocking")
+    }).collect();
+    map.things.iter().enumerate().for_each(|(thing, t)| {
+        let position = Vec2::new(t.x, t.y);
+        if !sectors.iter().any(|sector| sector.contains(position, &vertices)) {
+            problems.push(Problem {
+                kind: ProblemKind::ThingOutsideMap { thing },
+                severity: Severity::Warning,
+                position: Some(position),
+                fix: Some(Fix::RemoveThing(thing)),
+            });
+            return;
+        }
+        let Some(&radius) = options.thing_radii.get(&t.ednum) else { return; };
+        if let Some(&line) = blocking.iter().find(|&&line| {
+            segment_in_square(level::line_segment(map, line), position, radius)
+        }) {
+            problems.push(Problem {
+                kind: ProblemKind::StuckThing { thing, line },
+                severity: Severity::Warning,
+                position: Some(position),
+                fix: None,
+            });
+        }
+    });
+}
+
+fn check_unused_vertices(map: &UDMFMap, problems: &mut Vec<Problem>) {
+    let mut used = vec![false; map.vertices.len()];
+    map.linedefs.iter().flat_map(|l| [l.v1, l.v2]).for_each(|vertex| {
+        if let Some(used) = used.get_mut(vertex as usize) {
+            *used = true;
+        }
+    });
+    used.into_iter().enumerate().filter(|&(_, used)| !used)
+        .for_each(|(vertex, _)| {
+        problems.push(Problem {
+            kind: ProblemKind::UnusedVertex { vertex },
+            severity: Severity::Warning,
+            position: Some(level::vertex_position(map, vertex)),
+            fix: Some(Fix::RemoveVertex(vertex)),
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit::testmap::*;
+    use crate::edit::{EditDefaults, new_sidedef};
+    use parsers::udmf::input::{UDMFThing, PropMap};
+
+    fn kinds(problems: &[Problem]) -> Vec<&ProblemKind> {
+        problems.iter().map(|p| &p.kind).collect()
+    }
+
+    fn thing(x: f32, y: f32) -> UDMFThing {
+        UDMFThing {
+            x, y,
+            height: 0.,
+            angle: 0,
+            ednum: 1,
+            id: 0,
+            props: PropMap::default(),
+        }
+    }
+
+    #[test]
+    fn clean_map() {
+        let mut map = square();
+        map.things.push(thing(64., 64.));
+        assert_eq!(analyse(&map, &AnalysisOptions::default()), vec![]);
+    }
+
+    #[test]
+    fn unclosed_sector() {
+        let mut map = square();
+        map.linedefs.pop();
+        map.sidedefs.pop();
+        let problems = analyse(&map, &AnalysisOptions::default());
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].severity, Severity::Error);
+        let ProblemKind::UnclosedSector { sector, vertices } = &problems[0].kind else {
+            panic!("Expected an unclosed sector, got {:?}", problems[0].kind);
+        };
+        assert_eq!(*sector, 0);
+        assert_eq!(vertices.len(), 4);
+    }
+
+    #[test]
+    fn overlapping_lines() {
+        let map = make_map(
+            &[(0., 0.), (0., 128.), (128., 128.), (128., 0.), (64., 0.),
+              (64., -64.)],
+            &[(0, 1, 0, None), (1, 2, 0, None), (2, 3, 0, None), (3, 0, 0, None),
+              (1, 0, 0, None), (5, 4, 0, None)]
+        );
+        let problems = analyse(&map, &AnalysisOptions::default());
+        let overlaps: Vec<&Problem> = problems.iter()
+            .filter(|p| matches!(p.kind, ProblemKind::OverlappingLines { .. }))
+            .collect();
+        assert_eq!(overlaps.len(), 2);
+        assert!(overlaps.iter().any(|p| {
+            p.kind == ProblemKind::OverlappingLines { first: 0, second: 4 } &&
+            p.fix == Some(Fix::RemoveLine(4))
+        }));
+        // The line touching the middle of the bottom line
+        assert!(overlaps.iter().any(|p| {
+            p.kind == ProblemKind::OverlappingLines { first: 3, second: 5 } &&
+            p.fix.is_none()
+        }));
+    }
+
+    #[test]
+    fn back_to_back_lines() {
+        // Two squares, which are only joined by two one-sided lines between
+        // them, facing into each square
+        let map = make_map(
+            &[(0., 0.), (0., 64.), (64., 64.), (64., 0.), (128., 64.), (128., 0.)],
+            &[(0, 1, 0, None), (1, 2, 0, None), (2, 3, 0, None), (3, 0, 0, None),
+              (2, 4, 1, None), (4, 5, 1, None), (5, 3, 1, None), (3, 2, 1, None)]
+        );
+        let problems = analyse(&map, &AnalysisOptions::default());
+        let overlap = problems.iter().find(|p| {
+            p.kind == ProblemKind::OverlappingLines { first: 2, second: 7 }
+        }).unwrap();
+        // Removing either line would leave one of the squares unclosed
+        assert_eq!(overlap.fix, None);
+    }
+
+    #[test]
+    fn broken_lines() {
+        let mut map = square();
+        map.vertices.push(map.vertices[0].clone());
+        map.linedefs.push(map.linedefs[0].clone());
+        // Zero-length line
+        map.linedefs[4].v2 = 4;
+        // No front side, but a back side
+        map.linedefs[1].sidefront = 100;
+        map.linedefs[1].sideback = parsers::udmf::input::SidedefIndex(1);
+        map.linedefs[1].props.insert(String::from("twosided"), String::from("true"));
+        map.linedefs[2].props.insert(String::from("twosided"), String::from("true"));
+        let problems = analyse(&map, &AnalysisOptions::default());
+        let kinds = kinds(&problems);
+        assert!(kinds.contains(&&ProblemKind::ZeroLengthLine { line: 4 }));
+        assert!(kinds.contains(&&ProblemKind::NoFrontSide { line: 1 }));
+        assert!(kinds.contains(
+            &&ProblemKind::TwoSidedFlagMismatch { line: 2, two_sided: false }));
+        let fixes: Vec<Fix> = problems.iter().filter_map(|p| p.fix).collect();
+        assert!(fixes.contains(&Fix::RemoveLine(4)));
+        assert!(fixes.contains(&Fix::FlipBackToFront(1)));
+        apply_fixes(&mut map, &fixes);
+        assert_eq!(map.linedefs.len(), 4);
+        assert_eq!(map.linedefs[1].sidefront, 1);
+        assert!(!map.linedefs[1].props.contains_key("twosided"));
+        assert!(!map.linedefs[2].props.contains_key("twosided"));
+    }
+
+    #[test]
+    fn unused_and_missing() {
+        let mut map = square();
+        map.sectors.push(EditDefaults::default().sector());
+        map.sidedefs.push(new_sidedef(0));
+        map.sidedefs[3].sector = 7;
+        let problems = analyse(&map, &AnalysisOptions::default());
+        let kinds = kinds(&problems);
+        assert!(kinds.contains(&&ProblemKind::UnusedSector { sector: 1 }));
+        assert!(kinds.contains(&&ProblemKind::UnusedSidedef { sidedef: 4 }));
+        assert!(kinds.contains(&&ProblemKind::MissingSector { sidedef: 3, sector: 7 }));
+        // Errors come first
+        assert_eq!(problems[0].severity, Severity::Error);
+        let fixes: Vec<Fix> = problems.iter().filter_map(|p| p.fix).collect();
+        apply_fixes(&mut map, &fixes);
+        assert_eq!(map.sectors.len(), 1);
+        assert_eq!(map.sidedefs.len(), 4);
+    }
+
+    #[test]
+    fn only_vertex_fixes() {
+        let mut map = square();
+        map.vertices.push(map.vertices[0].clone());
+        map.vertices.push(map.vertices[0].clone());
+        // A line with a missing vertex, whose other vertex looks unused
+        map.linedefs.push(map.linedefs[0].clone());
+        map.linedefs[4].v1 = 5;
+        map.linedefs[4].v2 = 9;
+        let problems = analyse(&map, &AnalysisOptions::default());
+        let fixes: Vec<Fix> = problems.iter().filter_map(|p| p.fix)
+            .filter(|fix| matches!(fix, Fix::RemoveVertex(_))).collect();
+        assert_eq!(fixes, vec![Fix::RemoveVertex(4)]);
+        apply_fixes(&mut map, &fixes);
+        assert_eq!(map.vertices.len(), 5);
+        assert_eq!((map.linedefs[4].v1, map.linedefs[4].v2), (4, 9));
+        // Fixes for vertices which are used again are skipped
+        map.linedefs[4].v2 = 0;
+        apply_fixes(&mut map, &[Fix::RemoveVertex(4)]);
+        assert_eq!(map.vertices.len(), 5);
+    }
+
+    #[test]
+    fn unknown_textures() {
+        let mut map = square();
+        map.sidedefs[0].texturemiddle.0 = String::from("startan2");
+        map.sidedefs[1].texturemiddle.0 = String::from("NOTHERE");
+        let options = AnalysisOptions {
+            textures: Some(["STARTAN2", "FLOOR0_1"].into_iter()
+                .map(String::from).collect()),
+            ..Default::default()
+        };
+        let problems = analyse(&map, &options);
+        assert_eq!(kinds(&problems), vec![
+            &ProblemKind::UnknownWallTexture {
+                sidedef: 1, part: WallPart::Middle, texture: String::from("NOTHERE")
+            },
+            &ProblemKind::UnknownFlat {
+                sector: 0, ceiling: true, texture: String::from("CEIL1_1")
+            },
+        ]);
+    }
+
+    #[test]
+    fn things() {
+        let mut map = square();
+        map.things.push(thing(-64., 64.));
+        map.things.push(thing(8., 64.));
+        map.things.push(thing(64., 64.));
+        let options = AnalysisOptions {
+            thing_radii: [(1, 16.)].into_iter().collect(),
+            ..Default::default()
+        };
+        let problems = analyse(&map, &options);
+        assert_eq!(kinds(&problems), vec![
+            &ProblemKind::ThingOutsideMap { thing: 0 },
+            &ProblemKind::StuckThing { thing: 1, line: 0 },
+        ]);
+        apply_fixes(&mut map, problems.iter().filter_map(|p| p.fix.as_ref()));
+        assert_eq!(map.things.len(), 2);
+        assert_eq!(map.things[0].x, 8.);
+    }
+}
@@ -1,10 +1,54 @@
+//! # Vertex attributes
+//!
+//! The vertices of walls, floors, ceilings and things, with everything a
+//! renderer needs to draw them.
 use glam::{Vec2, Vec3, Vec4};
 
 pub trait VertexAttributes {
-    type IndexType;
     fn position(&self) -> Vec3;
-    fn index(&self) -> Box<[Self::IndexType]>;
     fn colour(&self) -> Vec3;
     fn fog(&self) -> Vec4; // `w` component is distance
     fn uv(&self) -> Vec2;
 }
+
+macro_rules! vertex_types {
+    ($($(#[$meta:meta])* $name:ident { $(#[$source_meta:meta])* $source:ident })*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Debug, Clone, Copy, PartialEq, Default)]
+            pub struct $name {
+                pub position: Vec3,
+                pub colour: Vec3,
+                pub fog: Vec4,
+                pub uv: Vec2,
+                $(#[$source_meta])*
+                pub $source: usize,
+            }
+
+            impl VertexAttributes for $name {
+                fn position(&self) -> Vec3 { self.position }
+                fn colour(&self) -> Vec3 { self.colour }
+                fn fog(&self) -> Vec4 { self.fog }
+                fn uv(&self) -> Vec2 { self.uv }
+            }
+        )*
+    };
+}
+
+vertex_types! {
+    /// A vertex of a wall
+    WallVertex {
+        /// The index of the line the wall belongs to
+        line
+    }
+    /// A vertex of a floor or ceiling
+    FlatVertex {
+        /// The index of the sector the floor or ceiling belongs to
+        sector
+    }
+    /// A vertex of a thing's sprite
+    ThingVertex {
+        /// The index of the thing
+        thing
+    }
+}
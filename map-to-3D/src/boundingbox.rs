@@ -1,6 +1,21 @@
-use glam::Vec2;
+//! # Bounding boxes
+//!
+//! Axis-aligned boxes around parts of a map, for quickly ruling things out
+//! before doing more expensive tests, like picking, culling, and finding
+//! which polygons a point could be in.
+//!
+//! `BoundingBox` is a box on the map, and `BoundingBox3D` adds the heights
+//! of the floor and ceiling to it. `sector_bounds` gets the 3D box around
+//! each sector in a map.
+use glam::{Vec2, Vec3};
+use parsers::udmf::input::UDMFMap;
+use crate::level::{self, LineSide};
+use crate::plane::SectorPlanes;
+use crate::segment::Segment;
 
-#[derive(Debug, Clone, Default)]
+/// A box on the map. The top is the side with the biggest Y coordinate, and
+/// the right is the side with the biggest X coordinate.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct BoundingBox {
     pub top: f32,
     pub left: f32,
@@ -9,6 +24,58 @@ pub struct BoundingBox {
 }
 
 impl BoundingBox {
+    /// Get the box with the given opposite corners, which can be in any order
+    pub fn from_corners(a: Vec2, b: Vec2) -> BoundingBox {
+        let (min, max) = (a.min(b), a.max(b));
+        BoundingBox { top: max.y, left: min.x, right: max.x, bottom: min.y }
+    }
+
+    /// Get the smallest box which all of the points are inside of, or `None`
+    /// if there are no points
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use glam::Vec2;
+    /// use map_to_3D::boundingbox::BoundingBox;
+    ///
+    /// let bb = BoundingBox::from_points([
+    ///     Vec2::new(3., -2.),
+    ///     Vec2::new(-1., 4.),
+    ///     Vec2::new(2., 1.),
+    /// ]).unwrap();
+    /// assert_eq!(bb.min(), Vec2::new(-1., -2.));
+    /// assert_eq!(bb.max(), Vec2::new(3., 4.));
+    /// ```
+    pub fn from_points(points: impl IntoIterator<Item = Vec2>) -> Option<BoundingBox> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let (min, max) = points.fold((first, first), |(min, max), point| {
+            (min.min(point), max.max(point))
+        });
+        Some(BoundingBox::from_corners(min, max))
+    }
+
+    /// The bottom left corner
+    pub fn min(&self) -> Vec2 {
+        Vec2::new(self.left, self.bottom)
+    }
+
+    /// The top right corner
+    pub fn max(&self) -> Vec2 {
+        Vec2::new(self.right, self.top)
+    }
+
+    /// The width and height of the box
+    pub fn size(&self) -> Vec2 {
+        self.max() - self.min()
+    }
+
+    pub fn centre(&self) -> Vec2 {
+        (self.min() + self.max()) / 2.
+    }
+
+    /// Is the point inside of the box, or on its edge?
     pub fn is_inside(&self, vector: Vec2) -> bool {
         let x = vector.x;
         let y = vector.y;
@@ -17,11 +84,257 @@ impl BoundingBox {
         x <= self.right &&
         y >= self.bottom
     }
+
+    /// Is the other box completely inside of this one?
+    pub fn contains(&self, other: &BoundingBox) -> bool {
+        self.is_inside(other.min()) && self.is_inside(other.max())
+    }
+
+    /// Do the boxes overlap? Boxes which only touch each other overlap.
+    pub fn overlaps(&self, other: &BoundingBox) -> bool {
+        self.left <= other.right && other.left <= self.right &&
+        self.bottom <= other.top && other.bottom <= self.top
+    }
+
+    /// Get the smallest box which both boxes are inside of
+    pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+        BoundingBox::from_corners(self.min().min(other.min()), self.max().max(other.max()))
+    }
+
+    /// Get the box where both boxes overlap, or `None` if they don't overlap
+    pub fn intersection(&self, other: &BoundingBox) -> Option<BoundingBox> {
+        self.overlaps(other).then(|| BoundingBox::from_corners(
+            self.min().max(other.min()), self.max().min(other.max())))
+    }
+
+    /// Get the smallest box which this box and the point are inside of
+    pub fn with_point(&self, point: Vec2) -> BoundingBox {
+        BoundingBox::from_corners(self.min().min(point), self.max().max(point))
+    }
+
+    /// Move each side of the box outwards by the given amount. Negative
+    /// amounts move the sides inwards, but the box won't be made smaller
+    /// than its centre.
+    pub fn padded(&self, amount: f32) -> BoundingBox {
+        let centre = self.centre();
+        BoundingBox::from_corners(
+            (self.min() - amount).min(centre),
+            (self.max() + amount).max(centre))
+    }
+
+    /// Get the part of the segment which is inside of the box, or `None` if
+    /// the segment misses the box. The part goes the same way as the
+    /// segment.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use glam::Vec2;
+    /// use map_to_3D::boundingbox::BoundingBox;
+    /// use map_to_3D::segment::Segment;
+    ///
+    /// let bb = BoundingBox::from_corners(Vec2::ZERO, Vec2::splat(64.));
+    /// let segment = Segment(Vec2::new(-32., 32.), Vec2::new(96., 32.));
+    /// let Segment(a, b) = bb.clip_segment(segment).unwrap();
+    /// assert_eq!((a, b), (Vec2::new(0., 32.), Vec2::new(64., 32.)));
+    /// ```
+    pub fn clip_segment(&self, segment: Segment) -> Option<Segment> {
+        let Segment(a, b) = segment;
+        let direction = b - a;
+        let (enter, exit) = clip_line(
+            [a.x, a.y], [direction.x, direction.y],
+            [self.left, self.bottom], [self.right, self.top], 1.)?;
+        Some(Segment(a + direction * enter, a + direction * exit))
+    }
+
+    /// Get how far along the ray it enters the box, in multiples of
+    /// `direction`, or `None` if it misses the box. Rays which start inside
+    /// of the box enter it at 0.
+    pub fn ray_hit(&self, origin: Vec2, direction: Vec2) -> Option<f32> {
+        clip_line(
+            [origin.x, origin.y], [direction.x, direction.y],
+            [self.left, self.bottom], [self.right, self.top], f32::INFINITY)
+            .map(|(enter, _)| enter)
+    }
+}
+
+/// A box on the map, with the heights of the lowest floor and highest
+/// ceiling inside of it.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BoundingBox3D {
+    pub area: BoundingBox,
+    pub floor: f32,
+    pub ceiling: f32,
+}
+
+impl BoundingBox3D {
+    /// Get the box with the given opposite corners, which can be in any order
+    pub fn from_corners(a: Vec3, b: Vec3) -> BoundingBox3D {
+        BoundingBox3D {
+            area: BoundingBox::from_corners(a.truncate(), b.truncate()),
+            floor: a.z.min(b.z),
+            ceiling: a.z.max(b.z),
+        }
+    }
+
+    /// Get the smallest box which all of the points are inside of, or `None`
+    /// if there are no points
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<BoundingBox3D> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let (min, max) = points.fold((first, first), |(min, max), point| {
+            (min.min(point), max.max(point))
+        });
+        Some(BoundingBox3D::from_corners(min, max))
+    }
+
+    /// The bottom left corner, at the height of the floor
+    pub fn min(&self) -> Vec3 {
+        self.area.min().extend(self.floor)
+    }
+
+    /// The top right corner, at the height of the ceiling
+    pub fn max(&self) -> Vec3 {
+        self.area.max().extend(self.ceiling)
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max() - self.min()
+    }
+
+    pub fn centre(&self) -> Vec3 {
+        (self.min() + self.max()) / 2.
+    }
+
+    /// Is the point inside of the box, or on its surface?
+    pub fn is_inside(&self, point: Vec3) -> bool {
+        self.area.is_inside(point.truncate()) &&
+        point.z >= self.floor &&
+        point.z <= self.ceiling
+    }
+
+    /// Is the other box completely inside of this one?
+    pub fn contains(&self, other: &BoundingBox3D) -> bool {
+        self.is_inside(other.min()) && self.is_inside(other.max())
+    }
+
+    /// Do the boxes overlap? Boxes which only touch each other overlap.
+    pub fn overlaps(&self, other: &BoundingBox3D) -> bool {
+        self.area.overlaps(&other.area) &&
+        self.floor <= other.ceiling && other.floor <= self.ceiling
+    }
+
+    /// Get the smallest box which both boxes are inside of
+    pub fn union(&self, other: &BoundingBox3D) -> BoundingBox3D {
+        BoundingBox3D::from_corners(self.min().min(other.min()), self.max().max(other.max()))
+    }
+
+    /// Get the box where both boxes overlap, or `None` if they don't overlap
+    pub fn intersection(&self, other: &BoundingBox3D) -> Option<BoundingBox3D> {
+        self.overlaps(other).then(|| BoundingBox3D::from_corners(
+            self.min().max(other.min()), self.max().min(other.max())))
+    }
+
+    /// Get the smallest box which this box and the point are inside of
+    pub fn with_point(&self, point: Vec3) -> BoundingBox3D {
+        BoundingBox3D::from_corners(self.min().min(point), self.max().max(point))
+    }
+
+    /// Move each side of the box outwards by the given amount. Negative
+    /// amounts move the sides inwards, but the box won't be made smaller
+    /// than its centre.
+    pub fn padded(&self, amount: f32) -> BoundingBox3D {
+        let centre = self.centre();
+        BoundingBox3D::from_corners(
+            (self.min() - amount).min(centre),
+            (self.max() + amount).max(centre))
+    }
+
+    /// Get the part of the segment from `a` to `b` which is inside of the
+    /// box, or `None` if the segment misses the box.
+    pub fn clip_segment(&self, a: Vec3, b: Vec3) -> Option<(Vec3, Vec3)> {
+        let direction = b - a;
+        let (enter, exit) = clip_line(
+            a.to_array(), direction.to_array(),
+            self.min().to_array(), self.max().to_array(), 1.)?;
+        Some((a + direction * enter, a + direction * exit))
+    }
+
+    /// Get how far along the ray it enters the box, in multiples of
+    /// `direction`, or `None` if it misses the box. Rays which start inside
+    /// of the box enter it at 0. This is useful for picking sectors with the
+    /// mouse.
+    pub fn ray_hit(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        clip_line(
+            origin.to_array(), direction.to_array(),
+            self.min().to_array(), self.max().to_array(), f32::INFINITY)
+            .map(|(enter, _)| enter)
+    }
+}
+
+/// Clip the part of a line from `origin` to `origin + direction * end` to a
+/// box, using the slab method. Returns how far along the line it enters and
+/// exits the box, in multiples of `direction`.
+fn clip_line<const N: usize>(
+    origin: [f32; N],
+    direction: [f32; N],
+    min: [f32; N],
+    max: [f32; N],
+    end: f32
+) -> Option<(f32, f32)> {
+    (0..N).try_fold((0_f32, end), |(enter, exit), axis| {
+        if direction[axis] == 0. {
+            // Parallel to the sides on this axis, so it is either always or
+            // never between them
+            return (origin[axis] >= min[axis] && origin[axis] <= max[axis])
+                .then_some((enter, exit));
+        }
+        let a = (min[axis] - origin[axis]) / direction[axis];
+        let b = (max[axis] - origin[axis]) / direction[axis];
+        let (enter, exit) = (enter.max(a.min(b)), exit.min(a.max(b)));
+        (enter <= exit).then_some((enter, exit))
+    })
+}
+
+/// Get the box around each sector in the map, from the floor at its lowest
+/// to the ceiling at its highest, indexed by sector. Sectors without any
+/// lines have no box.
+///
+/// `planes` are the floor and ceiling planes of each sector, as returned by
+/// `level::sector_planes`. Since planes are flat, the lowest and highest
+/// points of a sector are at its vertices.
+pub fn sector_bounds(map: &UDMFMap, planes: &[SectorPlanes]) -> Vec<Option<BoundingBox3D>> {
+    let mut bounds: Vec<Option<BoundingBox3D>> = vec![None; map.sectors.len()];
+    let positions = level::vertex_positions(map);
+    map.linedefs.iter().enumerate().for_each(|(line, linedef)| {
+        let ends = [linedef.v1, linedef.v2]
+            .map(|vertex| positions.get(vertex as usize).copied());
+        let [Some(v1), Some(v2)] = ends else {
+            return;
+        };
+        [LineSide::Front, LineSide::Back].into_iter().for_each(|side| {
+            let Some(sector) = level::side_sector(map, line, side) else {
+                return;
+            };
+            let SectorPlanes { floor, ceiling } = planes[sector];
+            [v1, v2].into_iter().for_each(|position| {
+                let low = position.extend(floor.z_at(position));
+                let high = position.extend(ceiling.z_at(position));
+                let sector_bounds = &mut bounds[sector];
+                *sector_bounds = Some(match sector_bounds {
+                    Some(bounds) => bounds.with_point(low).with_point(high),
+                    None => BoundingBox3D::from_corners(low, high),
+                });
+            });
+        });
+    });
+    bounds
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::edit::testmap::make_map;
     #[test]
     fn inside() {
         let bb = BoundingBox {
@@ -41,4 +354,108 @@ mod tests {
         assert_eq!(bb.is_inside(vd), false);
         assert_eq!(bb.is_inside(ve), true);
     }
+
+    #[test]
+    fn from_points() {
+        let bb = BoundingBox::from_points([
+            Vec2::new(3., -2.),
+            Vec2::new(-1., 4.),
+            Vec2::new(2., 1.),
+        ]).unwrap();
+        assert_eq!((bb.left, bb.top, bb.right, bb.bottom), (-1., 4., 3., -2.));
+        assert!(BoundingBox::from_points([]).is_none());
+        assert_eq!(bb.size(), Vec2::new(4., 6.));
+        assert_eq!(bb.centre(), Vec2::new(1., 1.));
+    }
+
+    #[test]
+    fn combining() {
+        let a = BoundingBox::from_corners(Vec2::ZERO, Vec2::splat(64.));
+        let b = BoundingBox::from_corners(Vec2::new(32., 48.), Vec2::new(128., 16.));
+        let c = BoundingBox::from_corners(Vec2::new(64., 96.), Vec2::new(96., 128.));
+        assert!(a.overlaps(&b));
+        assert!(!a.overlaps(&c));
+        assert_eq!(a.union(&c), BoundingBox::from_corners(Vec2::ZERO, Vec2::new(96., 128.)));
+        assert_eq!(a.intersection(&b),
+            Some(BoundingBox::from_corners(Vec2::new(32., 16.), Vec2::new(64., 48.))));
+        assert_eq!(a.intersection(&c), None);
+        // Touching boxes overlap along the edge they share
+        let touching = BoundingBox::from_corners(Vec2::new(64., 0.), Vec2::new(96., 8.));
+        assert_eq!(a.intersection(&touching).unwrap().size(), Vec2::new(0., 8.));
+        assert!(a.union(&b).contains(&a));
+        assert!(!a.union(&c).contains(&b));
+        assert_eq!(a.with_point(Vec2::new(-8., 16.)).min(), Vec2::new(-8., 0.));
+    }
+
+    #[test]
+    fn padding() {
+        let bb = BoundingBox::from_corners(Vec2::ZERO, Vec2::new(64., 16.));
+        assert_eq!(bb.padded(8.), BoundingBox::from_corners(Vec2::splat(-8.), Vec2::new(72., 24.)));
+        // Shrinking stops at the centre
+        assert_eq!(bb.padded(-16.), BoundingBox::from_corners(Vec2::new(16., 8.), Vec2::new(48., 8.)));
+    }
+
+    #[test]
+    fn segments_and_rays() {
+        let bb = BoundingBox::from_corners(Vec2::ZERO, Vec2::splat(64.));
+        // Diagonal through a corner
+        let Segment(a, b) = bb.clip_segment(
+            Segment(Vec2::new(-32., 32.), Vec2::new(32., 96.))).unwrap();
+        assert_eq!((a, b), (Vec2::new(0., 64.), Vec2::new(0., 64.)));
+        // Completely inside
+        let Segment(a, b) = bb.clip_segment(
+            Segment(Vec2::new(48., 8.), Vec2::new(8., 48.))).unwrap();
+        assert_eq!((a, b), (Vec2::new(48., 8.), Vec2::new(8., 48.)));
+        // Stops before it gets to the box
+        assert!(bb.clip_segment(Segment(Vec2::new(-32., 32.), Vec2::new(-8., 32.))).is_none());
+        // Parallel to a side, and outside of the box
+        assert!(bb.clip_segment(Segment(Vec2::new(-8., 96.), Vec2::new(96., 96.))).is_none());
+        assert_eq!(bb.ray_hit(Vec2::new(-32., 32.), Vec2::X * 2.), Some(16.));
+        assert_eq!(bb.ray_hit(Vec2::new(32., 32.), Vec2::NEG_Y), Some(0.));
+        assert_eq!(bb.ray_hit(Vec2::new(-32., 32.), Vec2::NEG_X), None);
+    }
+
+    #[test]
+    fn boxes_in_3d() {
+        let a = BoundingBox3D::from_points([
+            Vec3::new(0., 0., 0.),
+            Vec3::new(64., 64., 128.),
+        ]).unwrap();
+        let b = BoundingBox3D::from_corners(Vec3::new(32., 32., 96.), Vec3::new(96., 96., 192.));
+        assert!(a.is_inside(Vec3::new(32., 32., 64.)));
+        assert!(!a.is_inside(Vec3::new(32., 32., 129.)));
+        assert_eq!(a.intersection(&b),
+            Some(BoundingBox3D::from_corners(Vec3::new(32., 32., 96.), Vec3::new(64., 64., 128.))));
+        assert_eq!(a.union(&b).max(), Vec3::new(96., 96., 192.));
+        // Above the first box
+        let c = BoundingBox3D::from_corners(Vec3::new(0., 0., 160.), Vec3::splat(192.));
+        assert!(!a.overlaps(&c));
+        assert!(a.union(&b).contains(&a));
+        assert_eq!(a.padded(8.).size(), Vec3::new(80., 80., 144.));
+        // Looking down from above
+        assert_eq!(a.ray_hit(Vec3::new(32., 32., 256.), Vec3::NEG_Z), Some(128.));
+        assert_eq!(a.ray_hit(Vec3::new(32., 128., 256.), Vec3::NEG_Z), None);
+        let (enter, exit) = a.clip_segment(
+            Vec3::new(-64., 32., 64.), Vec3::new(128., 32., 64.)).unwrap();
+        assert_eq!((enter, exit), (Vec3::new(0., 32., 64.), Vec3::new(64., 32., 64.)));
+    }
+
+    #[test]
+    fn sectors() {
+        // Two 64x64 sectors side by side, and a sector without any lines
+        let mut map = make_map(
+            &[(0., 0.), (0., 64.), (64., 64.), (64., 0.), (128., 64.), (128., 0.)],
+            &[(0, 1, 0, None), (1, 2, 0, None), (2, 3, 0, Some(1)), (3, 0, 0, None),
+                (2, 4, 1, None), (4, 5, 1, None), (5, 3, 1, None)]
+        );
+        map.sectors[1].heightfloor = 32;
+        map.sectors[1].heightceiling = 96;
+        map.sectors.push(map.sectors[0].clone());
+        let bounds = sector_bounds(&map, &level::sector_planes(&map));
+        assert_eq!(bounds, vec![
+            Some(BoundingBox3D::from_corners(Vec3::ZERO, Vec3::new(64., 64., 128.))),
+            Some(BoundingBox3D::from_corners(Vec3::new(64., 0., 32.), Vec3::new(128., 64., 96.))),
+            None,
+        ]);
+    }
 }
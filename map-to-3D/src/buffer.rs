@@ -0,0 +1,483 @@
+//! # Vertex buffers
+//!
+//! Packs the geometry of a map into one interleaved vertex buffer and one
+//! index buffer, with the triangles grouped by material, so that a renderer
+//! can draw the map with one draw call for each material.
+//!
+//! Triangles go clockwise when seen from the side they face.
+use std::collections::BTreeMap;
+use glam::{Vec2, Vec3, Vec3Swizzles};
+use parsers::udmf::input::UDMFMap;
+use crate::attributes::{VertexAttributes, WallVertex, FlatVertex};
+use crate::floor3d::Floor3DGeometry;
+use crate::level;
+use crate::light;
+use crate::plane::{Plane, SectorPlanes};
+use crate::sectorpolygonbuilder as spb;
+use crate::sky;
+use crate::thing::{PlacedThing, QUAD_INDICES};
+use crate::uv;
+use crate::wall::{LineQuad, LineQuads, WallOptions};
+
+/// The number of bytes used for each vertex
+pub const STRIDE: usize = 48;
+/// Where the position (3 `f32`s) is in each vertex, in bytes
+pub const POSITION_OFFSET: usize = 0;
+/// Where the colour (3 `f32`s) is in each vertex, in bytes
+pub const COLOUR_OFFSET: usize = 12;
+/// Where the fog colour and distance (4 `f32`s) are in each vertex, in bytes
+pub const FOG_OFFSET: usize = 24;
+/// Where the texture coordinates (2 `f32`s) are in each vertex, in bytes
+pub const UV_OFFSET: usize = 40;
+
+/// How triangles are blended with what is behind them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub enum Blend {
+    #[default]
+    Opaque,
+    /// Mixed with what is behind them, using the given opacity, from 0 to
+    /// 255
+    Translucent(u8),
+    /// Multiplied by the given opacity, from 0 to 255, and added to what is
+    /// behind them
+    Additive(u8),
+}
+
+impl Blend {
+    /// The opacity, from 0 to 1
+    pub fn alpha(&self) -> f32 {
+        match self {
+            Blend::Opaque => 1.,
+            Blend::Translucent(alpha) | Blend::Additive(alpha) => *alpha as f32 / 255.,
+        }
+    }
+}
+
+/// A range of the index buffer which uses the same material
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrawRange {
+    /// The name of the texture or flat
+    pub material: String,
+    /// The position of the first index in the index buffer
+    pub first_index: u32,
+    /// How many indices there are
+    pub index_count: u32,
+    /// Whether the triangles show the sky, in which case the material is
+    /// the sky texture. See the `sky` module.
+    pub sky: bool,
+    /// How the triangles are blended with what is behind them
+    pub blend: Blend,
+}
+
+/// The vertex and index buffers for some geometry
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VertexBuffers {
+    /// The vertices, interleaved, in little-endian byte order. See `STRIDE`
+    /// and the offset constants for the layout.
+    pub vertices: Vec<u8>,
+    /// The indices of the vertices of each triangle
+    pub indices: Vec<u32>,
+    /// Which materials each part of the index buffer uses
+    pub draws: Vec<DrawRange>,
+}
+
+impl VertexBuffers {
+    /// How many vertices there are
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / STRIDE
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Group {
+    vertices: Vec<[f32; STRIDE / 4]>,
+    indices: Vec<u32>,
+}
+
+/// Collects geometry, grouped by material, to be packed into a
+/// `VertexBuffers`
+#[derive(Debug, Clone, Default)]
+pub struct BufferBuilder {
+    /// The groups, by how they are blended, whether they show the sky, and
+    /// by material. The sky comes after the rest of the opaque groups, and
+    /// translucent groups come last, so that they are drawn over everything
+    /// behind them.
+    groups: BTreeMap<(Blend, bool, String), Group>,
+}
+
+impl BufferBuilder {
+    pub fn new() -> BufferBuilder {
+        BufferBuilder::default()
+    }
+
+    /// Add some triangles which use the given material. `indices` are
+    /// indices into `vertices`.
+    pub fn add<V: VertexAttributes>(
+        &mut self,
+        material: &str,
+        vertices: &[V],
+        indices: &[u32]
+    ) {
+        self.add_to_group(Blend::Opaque, false, material, vertices, indices);
+    }
+
+    /// Add some triangles which use the given material, and are blended
+    /// with what is behind them
+    pub fn add_blended<V: VertexAttributes>(
+        &mut self,
+        material: &str,
+        blend: Blend,
+        vertices: &[V],
+        indices: &[u32]
+    ) {
+        self.add_to_group(blend, false, material, vertices, indices);
+    }
+
+    /// Add some triangles which show the sky, using the given sky texture
+    pub fn add_sky<V: VertexAttributes>(
+        &mut self,
+        texture: &str,
+        vertices: &[V],
+        indices: &[u32]
+    ) {
+        self.add_to_group(Blend::Opaque, true, texture, vertices, indices);
+    }
+
+    fn add_to_group<V: VertexAttributes>(
+        &mut self,
+        blend: Blend,
+        sky: bool,
+        material: &str,
+        vertices: &[V],
+        indices: &[u32]
+    ) {
+        let group = self.groups.entry((blend, sky, String::from(material))).or_default();
+        let first = group.vertices.len() as u32;
+        group.vertices.extend(vertices.iter().map(|vertex| {
+            let mut data = [0.; STRIDE / 4];
+            data[0..3].copy_from_slice(&vertex.position().to_array());
+            data[3..6].copy_from_slice(&vertex.colour().to_array());
+            data[6..10].copy_from_slice(&vertex.fog().to_array());
+            data[10..12].copy_from_slice(&vertex.uv().to_array());
+            data
+        }));
+        group.indices.extend(indices.iter().map(|index| index + first));
+    }
+
+    /// Add the walls, textured using their sidedefs. Middle walls on
+    /// two-sided lines are clipped to their textures. `walls` is the map from
+    /// `polyobject::walls_map` which `quads` were built from with
+    /// `LineQuads::for_map`. `texture_size` gets the size of a texture, in
+    /// pixels, from its name.
+    pub fn add_walls(
+        &mut self,
+        walls: &UDMFMap,
+        quads: &LineQuads,
+        planes: &[SectorPlanes],
+        texture_size: impl Fn(&str) -> Vec2
+    ) {
+        quads.iter().for_each(|quad| {
+            if let Some((texture, vertices, indices)) = wall_geometry(
+                walls, quad, planes, &texture_size) {
+                self.add(&texture, &vertices, &indices);
+            }
+        });
+    }
+
+    /// Add the floors and ceilings of every sector in the map. Those which
+    /// show the sky are added using `add_sky`. `flat_size` gets the size of
+    /// a flat or the sky texture, in pixels, from its name.
+    pub fn add_flats(
+        &mut self,
+        map: &UDMFMap,
+        planes: &[SectorPlanes],
+        options: &WallOptions,
+        flat_size: impl Fn(&str) -> Vec2
+    ) {
+        let positions = level::vertex_positions(map);
+        level::build_sector_polygons(map).iter().enumerate()
+            .for_each(|(sector, result)| {
+            let triangles: Vec<usize> = spb::auto_triangulate(
+                &result.polygons, &positions).into_iter().flatten().flatten()
+                .collect();
+            [false, true].into_iter().for_each(|ceiling| {
+                if sky::is_sky(map, sector, ceiling, options) {
+                    let (vertices, indices) = sky::sky_geometry(
+                        sector, ceiling, &triangles, &positions, planes,
+                        flat_size(&options.sky_texture));
+                    self.add_sky(&options.sky_texture, &vertices, &indices);
+                    return;
+                }
+                let (flat, vertices, indices) = flat_geometry(
+                    map, sector, ceiling, &triangles, &positions, planes,
+                    &flat_size);
+                self.add(&flat, &vertices, &indices);
+            });
+        });
+    }
+
+    /// Add the tops, bottoms and sides of 3D floors, which are built by
+    /// `floor3d::build_3d_floors`. Translucent 3D floors are blended with
+    /// what is behind them.
+    pub fn add_3d_floors(&mut self, geometry: &Floor3DGeometry) {
+        geometry.flats.iter().for_each(|flat| {
+            self.add_blended(&flat.material, flat.blend, &flat.vertices, &flat.indices);
+        });
+        geometry.sides.iter().for_each(|side| {
+            self.add_blended(&side.material, side.blend, &side.vertices, &side.indices);
+        });
+    }
+
+    /// Add the things as billboards facing the viewer, who is looking in the
+    /// direction of `view`. Each billboard uses its thing's sprite as its
+    /// material.
+    pub fn add_billboards(&mut self, map: &UDMFMap, things: &[PlacedThing], view: Vec3) {
+        things.iter().for_each(|thing| {
+            self.add(&thing.def.sprite, &thing.billboard(map, view), &QUAD_INDICES);
+        });
+    }
+
+    /// Add the things as flat markers, which are turned the way the things
+    /// face. Each marker uses its thing's sprite as its material.
+    pub fn add_markers(&mut self, map: &UDMFMap, things: &[PlacedThing]) {
+        things.iter().for_each(|thing| {
+            self.add(&thing.def.sprite, &thing.marker(map), &QUAD_INDICES);
+        });
+    }
+
+    /// Pack the geometry into vertex and index buffers
+    pub fn build(self) -> VertexBuffers {
+        let mut buffers = VertexBuffers::default();
+        self.groups.into_iter().for_each(|((blend, sky, material), group)| {
+            let first_vertex = buffers.vertex_count() as u32;
+            buffers.draws.push(DrawRange {
+                material,
+                first_index: buffers.indices.len() as u32,
+                index_count: group.indices.len() as u32,
+                sky,
+                blend,
+            });
+            buffers.vertices.extend(group.vertices.iter().flatten()
+                .flat_map(|value| value.to_le_bytes()));
+            buffers.indices.extend(group.indices.iter()
+                .map(|index| index + first_vertex));
+        });
+        buffers
+    }
+}
+
+/// Get the vertices and triangles of a wall, and the name of its texture.
+/// Middle walls on two-sided lines are clipped to their textures.
+///
+/// Returns `None` if the wall doesn't have a sidedef or a sector, or if
+/// there is nothing left of it after it is clipped.
+pub fn wall_geometry(
+    map: &UDMFMap,
+    quad: &LineQuad,
+    planes: &[SectorPlanes],
+    texture_size: impl Fn(&str) -> Vec2
+) -> Option<(String, Vec<WallVertex>, Vec<u32>)> {
+    let sidedef = level::line_sidedef(&map.linedefs[quad.line], quad.side)
+        .and_then(|sidedef| map.sidedefs.get(sidedef))?;
+    let texture = &quad.part.texture(sidedef).0;
+    let size = texture_size(texture);
+    let sector = level::side_sector(map, quad.line, quad.side)?;
+    let fog = light::sector_fog(&map.sectors[sector]);
+    let mut vertices: Vec<WallVertex> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    for quad in uv::clip_middle_wall(map, quad, planes, size) {
+        let uvs = uv::wall_uvs(map, &quad, size)?;
+        let first = vertices.len() as u32;
+        indices.extend(quad.triangles().flatten().map(|index| first + index as u32));
+        vertices.extend(quad.vertices.iter().zip(uvs).map(|(&position, uv)| WallVertex {
+            position,
+            colour: light::wall_colour(
+                map, quad.line, quad.side, planes, position)
+                .unwrap_or(Vec3::ONE),
+            fog,
+            uv,
+            line: quad.line,
+        }));
+    }
+    if vertices.is_empty() {
+        return None;
+    }
+    Some((texture.clone(), vertices, indices))
+}
+
+/// Get the vertices and triangles of the floor or ceiling of a sector, and
+/// the name of its flat. `triangles` are the triangulated polygons of the
+/// sector, as indices into `positions`, which are the positions of the
+/// vertices of the map.
+pub fn flat_geometry(
+    map: &UDMFMap,
+    sector: usize,
+    ceiling: bool,
+    triangles: &[usize],
+    positions: &[Vec2],
+    planes: &[SectorPlanes],
+    flat_size: impl Fn(&str) -> Vec2
+) -> (String, Vec<FlatVertex>, Vec<u32>) {
+    let data = &map.sectors[sector];
+    let (flat, plane) = if ceiling {
+        (&data.textureceiling, planes[sector].ceiling)
+    } else {
+        (&data.texturefloor, planes[sector].floor)
+    };
+    let texturing = uv::FlatTexturing::new(data, ceiling, flat_size(flat));
+    let (vertices, indices) = flat_vertices(FlatVertex {
+        colour: light::flat_colour(data, ceiling),
+        fog: light::sector_fog(data),
+        sector,
+        ..Default::default()
+    }, triangles, positions, plane, ceiling, &texturing);
+    (flat.clone(), vertices, indices)
+}
+
+/// Get the vertices and triangles of a flat surface on the given plane.
+/// `template` has the colour, fog and sector of every vertex, and `down` is
+/// whether the surface faces down, like a ceiling.
+pub(crate) fn flat_vertices(
+    template: FlatVertex,
+    triangles: &[usize],
+    positions: &[Vec2],
+    plane: Plane,
+    down: bool,
+    texturing: &uv::FlatTexturing
+) -> (Vec<FlatVertex>, Vec<u32>) {
+    let vertices: Vec<FlatVertex> = triangles.iter().map(|&index| {
+        let point = positions[index];
+        FlatVertex {
+            position: point.extend(plane.z_at(point)),
+            uv: texturing.uv(point),
+            ..template
+        }
+    }).collect();
+    let indices: Vec<u32> = vertices.chunks_exact(3).enumerate()
+        .flat_map(|(triangle, corners)| {
+        let [a, b, c] = [0, 1, 2].map(|i| corners[i].position.xy());
+        // Floors face up, and ceilings face down
+        let clockwise = (b - a).perp_dot(c - a) < 0.;
+        let first = triangle as u32 * 3;
+        if clockwise != down {
+            [first, first + 1, first + 2]
+        } else {
+            [first, first + 2, first + 1]
+        }
+    }).collect();
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const TEXTMAP: &str = r#"namespace = "zdoom";
+vertex { x = 0.0; y = 0.0; }
+vertex { x = 0.0; y = 64.0; }
+vertex { x = 64.0; y = 64.0; }
+vertex { x = 64.0; y = 0.0; }
+linedef { v1 = 0; v2 = 1; sidefront = 0; }
+linedef { v1 = 1; v2 = 2; sidefront = 1; }
+linedef { v1 = 2; v2 = 3; sidefront = 0; }
+linedef { v1 = 3; v2 = 0; sidefront = 1; }
+sidedef { sector = 0; texturemiddle = "STARTAN2"; }
+sidedef { sector = 0; texturemiddle = "BROWN1"; }
+sector { texturefloor = "FLAT1"; textureceiling = "CEIL1_1";
+    heightceiling = 128; }
+"#;
+
+    fn read_f32(buffers: &VertexBuffers, vertex: usize, offset: usize) -> f32 {
+        let start = vertex * STRIDE + offset;
+        f32::from_le_bytes(buffers.vertices[start..start + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn interleaved() {
+        let mut builder = BufferBuilder::new();
+        let vertices = [
+            WallVertex { position: Vec3::new(1., 2., 3.), uv: Vec2::new(4., 5.), ..Default::default() },
+            WallVertex::default(),
+            WallVertex::default(),
+        ];
+        builder.add("B", &vertices, &[0, 1, 2]);
+        builder.add("A", &vertices, &[2, 1, 0]);
+        builder.add("B", &vertices, &[0, 2, 1]);
+        // The sky comes after everything else, even if it has the same name
+        builder.add_sky("A", &vertices, &[0, 1, 2]);
+        let buffers = builder.build();
+        assert_eq!(buffers.vertex_count(), 12);
+        assert_eq!(buffers.draws, vec![
+            DrawRange { material: String::from("A"), first_index: 0, index_count: 3, sky: false,
+                blend: Blend::Opaque },
+            DrawRange { material: String::from("B"), first_index: 3, index_count: 6, sky: false,
+                blend: Blend::Opaque },
+            DrawRange { material: String::from("A"), first_index: 9, index_count: 3, sky: true,
+                blend: Blend::Opaque },
+        ]);
+        assert_eq!(buffers.indices, vec![2, 1, 0, 3, 4, 5, 6, 8, 7, 9, 10, 11]);
+        assert_eq!(read_f32(&buffers, 3, POSITION_OFFSET + 8), 3.);
+        assert_eq!(read_f32(&buffers, 3, UV_OFFSET + 4), 5.);
+    }
+
+    #[test]
+    fn map_geometry() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let planes = level::sector_planes(&map);
+        let quads = LineQuads::for_map(&map, &planes, &WallOptions::default());
+        let mut builder = BufferBuilder::new();
+        builder.add_walls(&map, &quads, &planes, |_| Vec2::new(64., 64.));
+        builder.add_flats(&map, &planes, &WallOptions::default(),
+            |_| Vec2::new(64., 64.));
+        let buffers = builder.build();
+        let materials: Vec<(&str, u32)> = buffers.draws.iter()
+            .map(|draw| (draw.material.as_str(), draw.index_count)).collect();
+        assert_eq!(materials, vec![
+            ("BROWN1", 12), ("CEIL1_1", 6), ("FLAT1", 6), ("STARTAN2", 12)
+        ]);
+        // The floor faces up
+        let floor = &buffers.draws[2];
+        let corner = |index: u32| Vec3::new(
+            read_f32(&buffers, index as usize, POSITION_OFFSET),
+            read_f32(&buffers, index as usize, POSITION_OFFSET + 4),
+            read_f32(&buffers, index as usize, POSITION_OFFSET + 8),
+        );
+        let first = floor.first_index as usize;
+        let [a, b, c] = [0, 1, 2].map(|i| corner(buffers.indices[first + i]));
+        assert!((b - a).cross(c - a).z < 0.);
+        assert_eq!(a.z, 0.);
+        // Lit using the sector's light level
+        let light = 160. / 255.;
+        assert_eq!(read_f32(&buffers, buffers.indices[first] as usize,
+            COLOUR_OFFSET), light);
+        let wall = buffers.indices[buffers.draws[0].first_index as usize];
+        // The wall from (0, 64) to (64, 64) is darker, because of fake
+        // contrast
+        assert_eq!(read_f32(&buffers, wall as usize, COLOUR_OFFSET),
+            144. / 255.);
+        assert_eq!(read_f32(&buffers, wall as usize, FOG_OFFSET + 12), 0.);
+    }
+
+    #[test]
+    fn polyobject_walls() {
+        let map = UDMFMap::from_str(crate::polyobject::tests::TEXTMAP).unwrap();
+        let planes = level::sector_planes(&map);
+        let walls = crate::polyobject::walls_map(&map);
+        let quads = LineQuads::for_map(&walls, &planes, &WallOptions::default());
+        let mut builder = BufferBuilder::new();
+        builder.add_walls(&walls, &quads, &planes, |_| Vec2::new(64., 64.));
+        let buffers = builder.build();
+        let door = buffers.draws.iter().find(|draw| draw.material == "DOOR1").unwrap();
+        let first = door.first_index as usize;
+        let corners: Vec<Vec3> = buffers.indices[first..first + door.index_count as usize]
+            .iter().map(|&index| Vec3::from_array([0, 4, 8].map(|offset| {
+                read_f32(&buffers, index as usize, POSITION_OFFSET + offset)
+            }))).collect();
+        // Moved to the middle of the room, and as tall as the room
+        assert!(corners.iter().all(|corner| {
+            (120. ..=136.).contains(&corner.x) && (120. ..=136.).contains(&corner.y)
+        }), "{corners:?}");
+        assert_eq!(corners.iter().map(|corner| corner.z).fold(0., f32::max), 128.);
+    }
+}
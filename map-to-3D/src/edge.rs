@@ -59,6 +59,19 @@ impl Edge {
         // Ensures deterministic order for edges
         sort_edge(Edge(a, b))
     }
+    /// Create a new `Edge`, or return `None` if `a == b`, instead of
+    /// panicking like `Edge::new` does.
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// use map_to_3D::edge::Edge;
+    /// assert_eq!(Edge::try_new(4, 1), Some(Edge::new(1, 4)));
+    /// assert_eq!(Edge::try_new(2, 2), None);
+    /// ```
+    pub fn try_new(a: EdgeVertexIndex, b: EdgeVertexIndex) -> Option<Edge> {
+        (a != b).then(|| sort_edge(Edge(a, b)))
+    }
     /// Does this edge use the given vertex index?
     /// 
     /// # Examples
@@ -0,0 +1,691 @@
+//! # Map editing operations
+//!
+//! Geometry editing operations that work directly on a parsed `UDMFMap`,
+//! similar to the ones in Doom Builder's drawing and editing modes.
+//!
+//! Every operation checks its input before it changes anything (operations
+//! which can only find problems part of the way through work on a copy of the
+//! map). If an operation returns an error, the map is left untouched, so an
+//! undo system only has to keep a copy of the map from before each successful
+//! operation.
+use glam::Vec2;
+use parsers::udmf::input::{
+    UDMFMap, UDMFSector, UDMFSidedef, SidedefTexture, LightLevel,
+    MultiplicativeColour, PropMap
+};
+use thiserror::Error;
+use crate::edge::Edge;
+use crate::level::{self, LineSide};
+use crate::segment::{Segment, Intersection};
+use crate::sectorpolygonbuilder::{self as spb, SectorPolygon};
+
+mod draw;
+mod lines;
+mod sectors;
+
+pub use draw::{draw_sector, DrawResult};
+pub use lines::{
+    split_line, merge_vertices, merge_coincident_vertices, join_lines,
+    flip_line
+};
+pub use sectors::{join_sectors, merge_sectors, split_sector};
+
+/// Vertices closer together than this are considered to be the same vertex
+pub const VERTEX_MERGE_DISTANCE: f32 = 1. / 128.;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum EditError {
+    /// Not enough distinct points were given to make the requested shape
+    #[error("At least {needed} distinct points are needed, but {got} were given")]
+    NotEnoughPoints { needed: usize, got: usize },
+    /// The drawn shape does not enclose any area
+    #[error("The drawn shape does not enclose any area")]
+    NoArea,
+    /// The point is not on the line, or is on one of the line's vertices
+    #[error("The point is not between the vertices of line {line}")]
+    PointNotOnLine { line: usize },
+    /// The lines do not share a vertex
+    #[error("Lines {first} and {second} are not connected")]
+    LinesNotConnected { first: usize, second: usize },
+    /// The lines do not go in the same direction
+    #[error("Lines {first} and {second} are not collinear")]
+    LinesNotCollinear { first: usize, second: usize },
+    /// The lines have different sectors on the same side
+    #[error("Lines {first} and {second} have different sectors on their sides")]
+    DifferentSectors { first: usize, second: usize },
+    /// The vertex is not in the map
+    #[error("There is no vertex {vertex}")]
+    NoSuchVertex { vertex: usize },
+    /// The line is not in the map
+    #[error("There is no line {line}")]
+    NoSuchLine { line: usize },
+    /// The sector is not in the map
+    #[error("There is no sector {sector}")]
+    NoSuchSector { sector: usize },
+    /// No sectors were given to the operation
+    #[error("No sectors were given")]
+    NoSectors,
+    /// The line only has a front side, so its sides can't be swapped
+    #[error("Line {line} is one-sided")]
+    OneSided { line: usize },
+    /// The drawn lines do not divide the sector into separate areas
+    #[error("Sector {sector} was not split into separate areas")]
+    SectorNotSplit { sector: usize },
+}
+
+/// Properties given to new sectors and sides, when they can't be copied from
+/// the surrounding geometry
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditDefaults {
+    pub texturefloor: String,
+    pub textureceiling: String,
+    pub heightfloor: i32,
+    pub heightceiling: i32,
+    pub lightlevel: LightLevel,
+    /// Texture used for new walls
+    pub texturewall: String,
+}
+
+impl Default for EditDefaults {
+    fn default() -> Self {
+        // Same as Doom Builder's defaults
+        EditDefaults {
+            texturefloor: String::from("FLOOR0_1"),
+            textureceiling: String::from("CEIL1_1"),
+            heightfloor: 0,
+            heightceiling: 128,
+            lightlevel: LightLevel(160),
+            texturewall: String::from("STARTAN2"),
+        }
+    }
+}
+
+impl EditDefaults {
+    /// Create a new sector using these defaults
+    pub fn sector(&self) -> UDMFSector {
+        UDMFSector {
+            texturefloor: self.texturefloor.clone(),
+            textureceiling: self.textureceiling.clone(),
+            heightfloor: self.heightfloor,
+            heightceiling: self.heightceiling,
+            lightlevel: self.lightlevel,
+            special: 0,
+            id: 0,
+            color_sprites: MultiplicativeColour::default(),
+            color_walltop: MultiplicativeColour::default(),
+            color_ceiling: MultiplicativeColour::default(),
+            color_floor: MultiplicativeColour::default(),
+            color_wallbottom: MultiplicativeColour::default(),
+            props: PropMap::default(),
+        }
+    }
+}
+
+/// Create a new untextured sidedef facing the given sector
+pub(crate) fn new_sidedef(sector: usize) -> UDMFSidedef {
+    UDMFSidedef {
+        sector: sector as u32,
+        offsetx: 0,
+        offsety: 0,
+        texturetop: SidedefTexture::default(),
+        texturemiddle: SidedefTexture::default(),
+        texturebottom: SidedefTexture::default(),
+        props: PropMap::default(),
+    }
+}
+
+/// Find a vertex at (or very close to) the given position
+pub(crate) fn find_vertex(map: &UDMFMap, pos: Vec2) -> Option<usize> {
+    map.vertices.iter().position(|v| {
+        Vec2::new(v.x, v.y).distance(pos) < VERTEX_MERGE_DISTANCE
+    })
+}
+
+/// Find a vertex at the given position, or add a new one if there is none.
+pub(crate) fn find_or_add_vertex(map: &mut UDMFMap, pos: Vec2) -> usize {
+    use parsers::udmf::input::UDMFVertex;
+    find_vertex(map, pos).unwrap_or_else(|| {
+        map.vertices.push(UDMFVertex {
+            x: pos.x,
+            y: pos.y,
+            props: PropMap::default()
+        });
+        map.vertices.len() - 1
+    })
+}
+
+/// Is the point on the segment, but not on, or very close to, either end?
+pub(crate) fn on_segment_interior(point: Vec2, segment: Segment) -> bool {
+    let Segment(a, b) = segment;
+    let ab = b - a;
+    let length = ab.length();
+    if length <= VERTEX_MERGE_DISTANCE * 2. {
+        return false;
+    }
+    let along = (point - a).dot(ab) / length;
+    let across = (point - a).perp_dot(ab) / length;
+    across.abs() < VERTEX_MERGE_DISTANCE &&
+    along > VERTEX_MERGE_DISTANCE &&
+    along < length - VERTEX_MERGE_DISTANCE
+}
+
+/// Get the points where the two segments cross or overlap each other, and
+/// where they need to be split.
+pub(crate) fn split_points(a: Segment, b: Segment) -> Vec<Vec2> {
+    match a.intersection(b) {
+        Some(intersection @ (Intersection::Normal(_) | Intersection::Collinear)) => {
+            intersection.split(a, b).into_iter()
+                .flat_map(|Segment(p, q)| [p, q])
+                .filter(|&p| on_segment_interior(p, a) || on_segment_interior(p, b))
+                .collect()
+        },
+        _ => Vec::new()
+    }
+}
+
+/// Split the line at the given vertex.
+///
+/// The line will go from its first vertex to the given vertex, and a new line
+/// with the same properties will go from the given vertex to the second
+/// vertex. The sidedefs are duplicated, and their X offsets are adjusted so
+/// that the textures stay aligned. Returns the index of the new line.
+pub(crate) fn split_line_at(map: &mut UDMFMap, line: usize, vertex: usize) -> usize {
+    let Segment(start, end) = level::line_segment(map, line);
+    let middle = level::vertex_position(map, vertex);
+    let first_length = start.distance(middle).round() as i32;
+    let second_length = middle.distance(end).round() as i32;
+    let mut new_line = map.linedefs[line].clone();
+    new_line.v1 = vertex as u32;
+    map.linedefs[line].v2 = vertex as u32;
+    // The front side of the new line continues where the front side of the
+    // original line ends. The back side goes the other way, so it's the back
+    // side of the original line which needs to be offset.
+    if let Some(front) = level::line_sidedef(&new_line, LineSide::Front)
+        .filter(|&s| s < map.sidedefs.len()) {
+        let mut sidedef = map.sidedefs[front].clone();
+        sidedef.offsetx += first_length;
+        map.sidedefs.push(sidedef);
+        level::set_line_sidedef(
+            &mut new_line, LineSide::Front, Some(map.sidedefs.len() - 1));
+    }
+    if let Some(back) = level::line_sidedef(&map.linedefs[line], LineSide::Back)
+        .filter(|&s| s < map.sidedefs.len()) {
+        let mut sidedef = map.sidedefs[back].clone();
+        sidedef.offsetx += second_length;
+        map.sidedefs.push(sidedef);
+        level::set_line_sidedef(
+            &mut map.linedefs[line], LineSide::Back, Some(map.sidedefs.len() - 1));
+    }
+    map.linedefs.push(new_line);
+    map.linedefs.len() - 1
+}
+
+/// Sort the points by their distance from the start of the segment
+fn sort_along(points: &mut [Vec2], segment: Segment) {
+    let start = segment.0;
+    points.sort_by(|a, b| {
+        a.distance_squared(start).total_cmp(&b.distance_squared(start))
+    });
+}
+
+/// Split the given lines where the drawn segments cross them, or where the
+/// ends of the segments are on them.
+///
+/// The drawn segments are split as well, where they cross the lines, each
+/// other, or go through existing vertices. Vertices are added for all of the
+/// split points and segment ends, and the vertices along the drawn segments
+/// are returned in order, without any vertex repeated twice in a row. No lines
+/// are added for the drawn segments.
+pub(crate) fn split_lines_along(
+    map: &mut UDMFMap,
+    lines: &[usize],
+    drawn: &[Segment]
+) -> Vec<usize> {
+    // Find out where the lines and the drawn segments need to be split
+    let mut line_splits: Vec<Vec<Vec2>> = vec![Vec::new(); lines.len()];
+    let mut drawn_splits: Vec<Vec<Vec2>> = vec![Vec::new(); drawn.len()];
+    for (index, &segment) in drawn.iter().enumerate() {
+        for (&line, splits) in lines.iter().zip(line_splits.iter_mut()) {
+            let line_segment = level::line_segment(map, line);
+            if line_segment.0 == line_segment.1 { continue; }
+            split_points(segment, line_segment).into_iter()
+                .chain([segment.0, segment.1])
+                .for_each(|point| {
+                    if on_segment_interior(point, line_segment) {
+                        splits.push(point);
+                    }
+                    if on_segment_interior(point, segment) {
+                        drawn_splits[index].push(point);
+                    }
+                });
+        }
+        for (other, &other_segment) in drawn.iter().enumerate().skip(index + 1) {
+            split_points(segment, other_segment).into_iter().for_each(|point| {
+                if on_segment_interior(point, segment) {
+                    drawn_splits[index].push(point);
+                }
+                if on_segment_interior(point, other_segment) {
+                    drawn_splits[other].push(point);
+                }
+            });
+        }
+        map.vertices.iter().for_each(|vertex| {
+            let point = Vec2::new(vertex.x, vertex.y);
+            if on_segment_interior(point, segment) {
+                drawn_splits[index].push(point);
+            }
+        });
+    }
+
+    // Split the lines
+    lines.iter().zip(line_splits.iter_mut()).for_each(|(&line, points)| {
+        sort_along(points, level::line_segment(map, line));
+        let mut current = line;
+        points.iter().for_each(|&point| {
+            let vertex = find_or_add_vertex(map, point);
+            let l = &map.linedefs[current];
+            if l.v1 as usize != vertex && l.v2 as usize != vertex {
+                current = split_line_at(map, current, vertex);
+            }
+        });
+    });
+
+    // Get the vertices along the drawn segments
+    let mut chain: Vec<usize> = Vec::new();
+    drawn.iter().zip(drawn_splits.iter_mut()).for_each(|(&segment, points)| {
+        sort_along(points, segment);
+        std::iter::once(segment.0).chain(points.iter().copied())
+            .chain(std::iter::once(segment.1))
+            .for_each(|point| {
+                let vertex = find_or_add_vertex(map, point);
+                if chain.last() != Some(&vertex) {
+                    chain.push(vertex);
+                }
+            });
+    });
+    chain
+}
+
+/// Get a point slightly away from the middle of the line, on the given side.
+pub(crate) fn point_beside(map: &UDMFMap, line: usize, side: LineSide) -> Vec2 {
+    let Segment(a, b) = level::line_segment(map, line);
+    // The front side is on the right
+    let right = (b - a).perp().normalize_or_zero() * -1.;
+    let distance = (a.distance(b) / 4.).min(0.5);
+    let middle = (a + b) / 2.;
+    match side {
+        LineSide::Front => middle + right * distance,
+        LineSide::Back => middle - right * distance,
+    }
+}
+
+/// The front sidedef index of a line whose sides haven't been given a sector
+/// yet.
+const NO_SIDE_YET: u32 = u32::MAX;
+
+/// Add a line with no sides to the map. The sides should be added using
+/// `set_side_sector`, and then `finish_unsided_lines` should be used to make
+/// sure the line has a front side.
+pub(crate) fn add_unsided_line(map: &mut UDMFMap, v1: usize, v2: usize) -> usize {
+    use parsers::udmf::input::{UDMFLinedef, SidedefIndex};
+    map.linedefs.push(UDMFLinedef {
+        v1: v1 as u32,
+        v2: v2 as u32,
+        id: 0,
+        sidefront: NO_SIDE_YET,
+        sideback: SidedefIndex(-1),
+        props: PropMap::default(),
+    });
+    map.linedefs.len() - 1
+}
+
+/// Flip the lines, starting from `first_line`, which were added using
+/// `add_unsided_line`, but only got a back side. Returns false if there is a
+/// line which got no sides at all.
+pub(crate) fn finish_unsided_lines(map: &mut UDMFMap, first_line: usize) -> bool {
+    map.linedefs.iter_mut().skip(first_line).all(|l| {
+        if l.sidefront != NO_SIDE_YET {
+            return true;
+        }
+        let Some(back) = level::line_sidedef(l, LineSide::Back) else {
+            return false;
+        };
+        std::mem::swap(&mut l.v1, &mut l.v2);
+        level::set_line_sidedef(l, LineSide::Front, Some(back));
+        level::set_line_sidedef(l, LineSide::Back, None);
+        true
+    })
+}
+
+/// Make the given side of the line face the given sector, adding a new
+/// sidedef if there isn't one already. If the sidedef on that side is shared
+/// with other lines, it will be duplicated, so that the other lines are not
+/// affected.
+pub(crate) fn set_side_sector(
+    map: &mut UDMFMap,
+    line: usize,
+    side: LineSide,
+    sector: usize
+) {
+    let sidedef = level::line_sidedef(&map.linedefs[line], side)
+        .filter(|&s| s < map.sidedefs.len());
+    match sidedef {
+        Some(sidedef) => {
+            let shared = map.linedefs.iter().enumerate()
+                .filter(|&(index, _)| index != line)
+                .flat_map(|(_, l)| [
+                    level::line_sidedef(l, LineSide::Front),
+                    level::line_sidedef(l, LineSide::Back)
+                ]).chain(std::iter::once(level::line_sidedef(
+                    &map.linedefs[line], side.opposite())))
+                .any(|other| other == Some(sidedef));
+            if shared {
+                let mut copy = map.sidedefs[sidedef].clone();
+                copy.sector = sector as u32;
+                map.sidedefs.push(copy);
+                level::set_line_sidedef(
+                    &mut map.linedefs[line], side, Some(map.sidedefs.len() - 1));
+            } else {
+                map.sidedefs[sidedef].sector = sector as u32;
+            }
+        },
+        None => {
+            map.sidedefs.push(new_sidedef(sector));
+            level::set_line_sidedef(
+                &mut map.linedefs[line], side, Some(map.sidedefs.len() - 1));
+        }
+    }
+}
+
+/// Update the flags and textures of a line which has become one-sided or
+/// two-sided, the same way Doom Builder does.
+///
+/// Two-sided lines are made passable, and any middle textures are moved to
+/// the upper and lower parts of the wall. One-sided lines are made impassable,
+/// and get a middle texture.
+pub(crate) fn update_line_sidedness(
+    map: &mut UDMFMap,
+    line: usize,
+    defaults: &EditDefaults
+) {
+    let no_texture = SidedefTexture::default();
+    let wall = SidedefTexture(defaults.texturewall.clone());
+    let two_sided = level::line_sidedef(&map.linedefs[line], LineSide::Back)
+        .is_some();
+    let props = &mut map.linedefs[line].props;
+    if two_sided {
+        props.insert(String::from("twosided"), String::from("true"));
+        props.remove("blocking");
+    } else {
+        props.insert(String::from("blocking"), String::from("true"));
+        props.remove("twosided");
+    }
+    for side in [LineSide::Front, LineSide::Back] {
+        let Some(sidedef) = level::line_sidedef(&map.linedefs[line], side)
+            .and_then(|s| map.sidedefs.get_mut(s)) else {
+            continue;
+        };
+        if two_sided {
+            let middle = std::mem::take(&mut sidedef.texturemiddle);
+            let middle = if middle == no_texture { wall.clone() } else { middle };
+            if sidedef.texturetop == no_texture {
+                sidedef.texturetop = middle.clone();
+            }
+            if sidedef.texturebottom == no_texture {
+                sidedef.texturebottom = middle;
+            }
+        } else if sidedef.texturemiddle == no_texture {
+            sidedef.texturemiddle = if sidedef.texturetop != no_texture {
+                sidedef.texturetop.clone()
+            } else {
+                wall.clone()
+            };
+        }
+    }
+}
+
+/// Remove the items for which `keep` returns false, and return a table which
+/// maps the old indices to the new ones.
+fn compact<T>(items: &mut Vec<T>, keep: impl Fn(usize) -> bool) -> Vec<Option<usize>> {
+    let mut new_index = 0;
+    let table: Vec<Option<usize>> = (0..items.len()).map(|index| {
+        keep(index).then(|| {
+            new_index += 1;
+            new_index - 1
+        })
+    }).collect();
+    let mut index = 0;
+    items.retain(|_| {
+        index += 1;
+        table[index - 1].is_some()
+    });
+    table
+}
+
+/// Remove the given lines from the map. Sidedefs which are no longer used by
+/// any line are removed as well.
+pub(crate) fn remove_lines(map: &mut UDMFMap, lines: &[usize]) {
+    compact(&mut map.linedefs, |index| !lines.contains(&index));
+    let mut used = vec![false; map.sidedefs.len()];
+    map.linedefs.iter().flat_map(|l| [
+        level::line_sidedef(l, LineSide::Front),
+        level::line_sidedef(l, LineSide::Back)
+    ]).flatten().for_each(|sidedef| {
+        if let Some(used) = used.get_mut(sidedef) {
+            *used = true;
+        }
+    });
+    let table = compact(&mut map.sidedefs, |index| used[index]);
+    map.linedefs.iter_mut().for_each(|l| {
+        [LineSide::Front, LineSide::Back].into_iter().for_each(|side| {
+            if let Some(sidedef) = level::line_sidedef(l, side) {
+                if let Some(&Some(new_index)) = table.get(sidedef) {
+                    level::set_line_sidedef(l, side, Some(new_index));
+                }
+            }
+        });
+    });
+}
+
+/// Remove the given vertices, which should not be used by any line, from the
+/// map. Lines using vertices which don't exist are left as they are.
+pub(crate) fn remove_vertices(map: &mut UDMFMap, vertices: &[usize]) {
+    let table = compact(&mut map.vertices, |index| !vertices.contains(&index));
+    map.linedefs.iter_mut().for_each(|l| {
+        [&mut l.v1, &mut l.v2].into_iter().for_each(|vertex| {
+            if let Some(&new_index) = table.get(*vertex as usize) {
+                *vertex = new_index.expect("Removed vertex is still used") as u32;
+            }
+        });
+    });
+}
+
+/// Remove the given sidedefs, which should not be used by any line, from the
+/// map.
+pub(crate) fn remove_sidedefs(map: &mut UDMFMap, sidedefs: &[usize]) {
+    let table = compact(&mut map.sidedefs, |index| !sidedefs.contains(&index));
+    map.linedefs.iter_mut().for_each(|l| {
+        [LineSide::Front, LineSide::Back].into_iter().for_each(|side| {
+            if let Some(&Some(new_index)) = level::line_sidedef(l, side)
+                .and_then(|sidedef| table.get(sidedef)) {
+                level::set_line_sidedef(l, side, Some(new_index));
+            }
+        });
+    });
+}
+
+/// Remove the given sectors, which should not be used by any sidedef, from
+/// the map.
+pub(crate) fn remove_sectors(map: &mut UDMFMap, sectors: &[usize]) {
+    let table = compact(&mut map.sectors, |index| !sectors.contains(&index));
+    map.sidedefs.iter_mut().for_each(|sidedef| {
+        if let Some(&new_index) = table.get(sidedef.sector as usize) {
+            sidedef.sector = new_index
+                .expect("Removed sector is still used") as u32;
+        }
+    });
+}
+
+/// Build the polygons for the given sector.
+///
+/// Lines which have the given sector on both sides are left out, since they
+/// are not part of the sector's outline.
+pub fn sector_polygons(map: &UDMFMap, sector: usize) -> Vec<SectorPolygon> {
+    let vertices = level::vertex_positions(map);
+    let edges = level::sector_edges(map).into_iter().nth(sector).unwrap_or_default();
+    spb::build_polygons(&edges, &vertices)
+}
+
+/// Find the sector which contains the given point, by building the polygons
+/// of each sector.
+///
+/// This is slow, since it rebuilds the polygons of every sector in the map.
+/// Use `sectorindex::SectorIndex` to find the sectors of lots of points.
+pub fn sector_at(map: &UDMFMap, point: Vec2) -> Option<usize> {
+    let vertices = level::vertex_positions(map);
+    (0..map.sectors.len()).find(|&sector| {
+        polygon_at(&sector_polygons(map, sector), &vertices, point).is_some()
+    })
+}
+
+/// Find the outer polygon which the point is inside of, but not inside of any
+/// of its holes. Returns the index of the polygon.
+pub(crate) fn polygon_at(
+    polygons: &[SectorPolygon],
+    vertices: &[Vec2],
+    point: Vec2
+) -> Option<usize> {
+    polygons.iter().enumerate().find(|&(index, polygon)| {
+        polygon.hole_of.is_none() &&
+        polygon.contains(point, vertices) &&
+        !polygons.iter().any(|hole| {
+            hole.hole_of == Some(index) && hole.contains(point, vertices)
+        })
+    }).map(|(index, _)| index)
+}
+
+#[cfg(test)]
+pub(crate) mod testmap {
+    //! Helpers for making small maps for tests
+    use super::*;
+    use parsers::udmf::input::{UDMFLinedef, UDMFVertex, SidedefIndex};
+
+    /// Make a map from vertex positions, and lines given as the two vertex
+    /// indices, and the sectors on the front and back sides. Each sector
+    /// referenced by the lines is created using the default properties.
+    pub(crate) fn make_map(
+        vertices: &[(f32, f32)],
+        lines: &[(usize, usize, usize, Option<usize>)]
+    ) -> UDMFMap {
+        let mut map = UDMFMap {
+            namespace: String::from("zdoom"),
+            ..Default::default()
+        };
+        map.vertices = vertices.iter().map(|&(x, y)| UDMFVertex {
+            x, y, props: PropMap::default()
+        }).collect();
+        let sector_count = lines.iter()
+            .map(|&(_, _, front, back)| front.max(back.unwrap_or(0)) + 1)
+            .max().unwrap_or(0);
+        map.sectors = (0..sector_count)
+            .map(|_| EditDefaults::default().sector()).collect();
+        lines.iter().for_each(|&(v1, v2, front, back)| {
+            map.sidedefs.push(new_sidedef(front));
+            let sidefront = map.sidedefs.len() as u32 - 1;
+            let sideback = back.map_or(SidedefIndex(-1), |back| {
+                map.sidedefs.push(new_sidedef(back));
+                SidedefIndex(map.sidedefs.len() as i32 - 1)
+            });
+            map.linedefs.push(UDMFLinedef {
+                v1: v1 as u32,
+                v2: v2 as u32,
+                id: 0,
+                sidefront,
+                sideback,
+                props: PropMap::default(),
+            });
+        });
+        map
+    }
+
+    /// A 128x128 square sector, with its bottom left corner at the origin
+    pub(crate) fn square() -> UDMFMap {
+        make_map(
+            &[(0., 0.), (0., 128.), (128., 128.), (128., 0.)],
+            &[(0, 1, 0, None), (1, 2, 0, None), (2, 3, 0, None), (3, 0, 0, None)]
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::testmap::*;
+
+    #[test]
+    fn split_line_keeps_texture_alignment() {
+        let mut map = make_map(
+            &[(0., 0.), (96., 0.), (32., 0.)],
+            &[(0, 1, 0, Some(1))]
+        );
+        let new_line = split_line_at(&mut map, 0, 2);
+        assert_eq!(new_line, 1);
+        assert_eq!((map.linedefs[0].v1, map.linedefs[0].v2), (0, 2));
+        assert_eq!((map.linedefs[1].v1, map.linedefs[1].v2), (2, 1));
+        let offset = |line: usize, side| {
+            let sidedef = level::line_sidedef(&map.linedefs[line], side).unwrap();
+            map.sidedefs[sidedef].offsetx
+        };
+        assert_eq!(offset(0, LineSide::Front), 0);
+        assert_eq!(offset(1, LineSide::Front), 32);
+        assert_eq!(offset(0, LineSide::Back), 64);
+        assert_eq!(offset(1, LineSide::Back), 0);
+    }
+
+    #[test]
+    fn shared_sidedef_is_duplicated() {
+        let mut map = square();
+        // Make all of the lines share the first sidedef
+        map.linedefs.iter_mut().for_each(|l| l.sidefront = 0);
+        map.sectors.push(EditDefaults::default().sector());
+        set_side_sector(&mut map, 2, LineSide::Front, 1);
+        assert_eq!(level::side_sector(&map, 2, LineSide::Front), Some(1));
+        assert_eq!(level::side_sector(&map, 0, LineSide::Front), Some(0));
+    }
+
+    #[test]
+    fn remove_line_and_sides() {
+        let mut map = make_map(
+            &[(0., 0.), (64., 0.), (64., 64.)],
+            &[(0, 1, 0, Some(1)), (1, 2, 1, None), (2, 0, 1, None)]
+        );
+        remove_lines(&mut map, &[0]);
+        assert_eq!(map.linedefs.len(), 2);
+        assert_eq!(map.sidedefs.len(), 2);
+        assert_eq!(map.linedefs[0].sidefront, 0);
+        assert_eq!(map.linedefs[1].sidefront, 1);
+        map.linedefs[1].v2 = 1;
+        remove_vertices(&mut map, &[0]);
+        assert_eq!(map.vertices.len(), 2);
+        assert_eq!((map.linedefs[0].v1, map.linedefs[0].v2), (0, 1));
+        assert_eq!((map.linedefs[1].v1, map.linedefs[1].v2), (1, 0));
+    }
+
+    #[test]
+    fn point_in_sector() {
+        let map = square();
+        assert_eq!(sector_at(&map, Vec2::new(64., 64.)), Some(0));
+        assert_eq!(sector_at(&map, Vec2::new(-64., 64.)), None);
+    }
+
+    #[test]
+    fn points_to_split_at() {
+        let a = Segment(Vec2::new(0., 0.), Vec2::new(64., 0.));
+        let b = Segment(Vec2::new(32., -32.), Vec2::new(32., 32.));
+        assert_eq!(split_points(a, b), vec![Vec2::new(32., 0.); 4]);
+        let c = Segment(Vec2::new(32., 0.), Vec2::new(96., 0.));
+        let points = split_points(a, c);
+        assert!(points.contains(&Vec2::new(32., 0.)));
+        assert!(points.contains(&Vec2::new(64., 0.)));
+    }
+}
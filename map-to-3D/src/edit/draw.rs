@@ -0,0 +1,335 @@
+//! # Sector drawing
+//!
+//! Draws new sectors from closed shapes, like Doom Builder's drawing mode.
+use glam::Vec2;
+use parsers::udmf::input::UDMFMap;
+use std::collections::{HashMap, HashSet};
+use ahash::RandomState;
+use crate::sectorindex::SectorIndex;
+use super::*;
+
+/// The lines and sectors affected by drawing a sector
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DrawResult {
+    /// The lines which make up the drawn shape, in the order they were drawn.
+    /// Existing lines which are part of the shape are included.
+    pub lines: Vec<usize>,
+    /// The sectors which were created
+    pub sectors: Vec<usize>,
+}
+
+/// An area inside of a drawn shape, identified by the sector it used to be in
+/// (or `None` for the void), and the index of its outer polygon
+type Area = (Option<usize>, usize);
+
+/// Remove points which are the same as the point before them.
+pub(super) fn clean_path(points: &[Vec2]) -> Vec<Vec2> {
+    let mut path: Vec<Vec2> = Vec::with_capacity(points.len());
+    points.iter().for_each(|&point| {
+        if path.last().is_none_or(|&last| {
+            last.distance(point) >= VERTEX_MERGE_DISTANCE
+        }) {
+            path.push(point);
+        }
+    });
+    path
+}
+
+/// Remove points which are the same as the point before them, including the
+/// last point if the shape is closed by repeating the first point.
+fn clean_shape(points: &[Vec2]) -> Vec<Vec2> {
+    let mut shape = clean_path(points);
+    while shape.len() > 1 &&
+        shape[0].distance(shape[shape.len() - 1]) < VERTEX_MERGE_DISTANCE {
+        shape.pop();
+    }
+    shape
+}
+
+/// Draw a new sector from a closed shape.
+///
+/// The shape is given as a list of points, where the last point is connected
+/// to the first. Existing lines crossed by the shape are split where they
+/// cross it, and existing vertices and lines are reused where the shape goes
+/// through them. Every area enclosed by the shape gets a new sector, which is
+/// a copy of the sector that was there before, or a new sector made from the
+/// `defaults` if the area was in the void. Areas outside of the shape keep
+/// their sectors.
+///
+/// # Example
+///
+/// ```
+/// use glam::Vec2;
+/// use map_to_3D::edit::{draw_sector, EditDefaults};
+/// use parsers::udmf::input::UDMFMap;
+///
+/// let mut map = UDMFMap::default();
+/// let room = [
+///     Vec2::new(0., 0.),
+///     Vec2::new(0., 128.),
+///     Vec2::new(128., 128.),
+///     Vec2::new(128., 0.),
+/// ];
+/// let result = draw_sector(&mut map, &room, &EditDefaults::default()).unwrap();
+/// assert_eq!(result.lines.len(), 4);
+/// assert_eq!(result.sectors, vec![0]);
+/// assert_eq!(map.sidedefs.len(), 4);
+/// ```
+pub fn draw_sector(
+    map: &mut UDMFMap,
+    points: &[Vec2],
+    defaults: &EditDefaults
+) -> Result<DrawResult, EditError> {
+    let shape = clean_shape(points);
+    if shape.len() < 3 {
+        return Err(EditError::NotEnoughPoints { needed: 3, got: shape.len() });
+    }
+    let area = shape.iter().zip(shape.iter().cycle().skip(1))
+        .map(|(a, b)| a.perp_dot(*b)).sum::<f32>();
+    if area.abs() < VERTEX_MERGE_DISTANCE {
+        return Err(EditError::NoArea);
+    }
+    let mut work = map.clone();
+    let old_line_count = work.linedefs.len();
+    let drawn: Vec<Segment> = shape.iter().zip(shape.iter().cycle().skip(1))
+        .map(|(&a, &b)| Segment(a, b)).collect();
+
+    // Split the existing lines and the drawn shape where they cross
+    let old_lines: Vec<usize> = (0..old_line_count).collect();
+    let mut chain = split_lines_along(&mut work, &old_lines, &drawn);
+
+    // Add the drawn lines, reusing existing lines where possible
+    let mut existing: HashMap<Edge, usize, RandomState> = HashMap::default();
+    work.linedefs.iter().enumerate().for_each(|(index, l)| {
+        if l.v1 != l.v2 {
+            existing.insert(Edge::new(l.v1 as usize, l.v2 as usize), index);
+        }
+    });
+    if chain.len() > 1 && chain.first() == chain.last() {
+        chain.pop();
+    }
+    let first_new_line = work.linedefs.len();
+    let mut result = DrawResult::default();
+    chain.iter().zip(chain.iter().cycle().skip(1)).for_each(|(&a, &b)| {
+        let edge = Edge::new(a, b);
+        let line = *existing.entry(edge)
+            .or_insert_with(|| add_unsided_line(&mut work, a, b));
+        if !result.lines.contains(&line) {
+            result.lines.push(line);
+        }
+    });
+
+    // Find out which sector each side of each line was in before the shape
+    // was drawn, and whether it's inside of the shape
+    let outline = SectorPolygon {
+        vertices: (0..shape.len()).collect(),
+        hole_of: None
+    };
+    let index = SectorIndex::new(map);
+    let before: Vec<[(Option<usize>, bool); 2]> = (0..work.linedefs.len()).map(|line| {
+        [LineSide::Front, LineSide::Back].map(|side| {
+            let point = point_beside(&work, line, side);
+            let previous = if line < first_new_line {
+                level::side_sector(&work, line, side)
+            } else {
+                index.sector_at(point)
+            };
+            (previous, outline.contains(point, &shape))
+        })
+    }).collect();
+
+    // The areas inside of the shape are built separately for each sector
+    // they used to be in, from the lines with exactly one side inside of the
+    // shape and in that sector.
+    let vertices = level::vertex_positions(&work);
+    let mut polygons: HashMap<Option<usize>, Vec<SectorPolygon>, RandomState> =
+        HashMap::default();
+    let areas: Vec<[Option<Area>; 2]> = (0..work.linedefs.len())
+        .map(|line| [(0, LineSide::Front), (1, LineSide::Back)].map(|(index, side)| {
+            let (previous, inside) = before[line][index];
+            if !inside {
+                return None;
+            }
+            let polygons = polygons.entry(previous).or_insert_with(|| {
+                let mut edges: Vec<Edge> = work.linedefs.iter().enumerate()
+                    .filter(|&(line, l)| l.v1 != l.v2 && before[line].iter()
+                        .filter(|&&s| s == (previous, true)).count() == 1)
+                    .map(|(_, l)| Edge::new(l.v1 as usize, l.v2 as usize))
+                    .collect();
+                edges.sort_unstable();
+                edges.dedup();
+                spb::build_polygons(&edges, &vertices)
+            });
+            let point = point_beside(&work, line, side);
+            polygon_at(polygons, &vertices, point).map(|polygon| (previous, polygon))
+        })).collect();
+
+    // Each area next to the drawn lines gets a new sector. Sectors which are
+    // completely inside of the shape are left as they are.
+    let mut new_sectors: Vec<(Area, usize)> = Vec::new();
+    result.lines.iter().flat_map(|&line| areas[line]).flatten().for_each(|area| {
+        if new_sectors.iter().any(|&(other, _)| other == area) {
+            return;
+        }
+        let sector = area.0.map_or_else(
+            || defaults.sector(),
+            |previous| work.sectors[previous].clone());
+        work.sectors.push(sector);
+        result.sectors.push(work.sectors.len() - 1);
+        new_sectors.push((area, work.sectors.len() - 1));
+    });
+    let had_back_side: Vec<bool> = work.linedefs.iter()
+        .map(|l| level::line_sidedef(l, LineSide::Back).is_some()).collect();
+    let mut changed_lines: HashSet<usize, RandomState> = HashSet::default();
+    (0..work.linedefs.len()).for_each(|line| {
+        [(0, LineSide::Front), (1, LineSide::Back)].into_iter().for_each(|(index, side)| {
+            let sector = areas[line][index]
+                .and_then(|area| new_sectors.iter().find(|&&(other, _)| other == area))
+                .map(|&(_, sector)| sector)
+                // New lines outside of the shape face the sector which was
+                // already there
+                .or(if line >= first_new_line { before[line][index].0 } else { None });
+            if let Some(sector) = sector {
+                set_side_sector(&mut work, line, side, sector);
+                changed_lines.insert(line);
+            }
+        });
+    });
+
+    if !finish_unsided_lines(&mut work, first_new_line) {
+        return Err(EditError::NoArea);
+    }
+    changed_lines.into_iter().for_each(|line| {
+        let has_back_side = level::line_sidedef(
+            &work.linedefs[line], LineSide::Back).is_some();
+        if line >= first_new_line || has_back_side != had_back_side[line] {
+            update_line_sidedness(&mut work, line, defaults);
+        }
+    });
+    *map = work;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testmap::*;
+
+    fn points(points: &[(f32, f32)]) -> Vec<Vec2> {
+        points.iter().map(|&(x, y)| Vec2::new(x, y)).collect()
+    }
+
+    #[test]
+    fn draw_in_void() {
+        let mut map = UDMFMap::default();
+        // Drawn counterclockwise, so the lines need to be flipped
+        let shape = points(&[(0., 0.), (64., 0.), (64., 64.), (0., 64.), (0., 0.)]);
+        let result = draw_sector(&mut map, &shape, &EditDefaults::default()).unwrap();
+        assert_eq!(result.sectors, vec![0]);
+        assert_eq!(map.vertices.len(), 4);
+        assert_eq!(map.linedefs.len(), 4);
+        (0..4).for_each(|line| {
+            assert_eq!(level::side_sector(&map, line, LineSide::Front), Some(0));
+            assert_eq!(level::line_sidedef(&map.linedefs[line], LineSide::Back), None);
+            assert_eq!(map.linedefs[line].props.get("blocking").unwrap(), "true");
+        });
+        // The front side is on the right
+        let l = &map.linedefs[0];
+        assert_eq!((l.v1, l.v2), (1, 0));
+        assert_eq!(map.sidedefs[0].texturemiddle.0, "STARTAN2");
+    }
+
+    #[test]
+    fn draw_inside_sector() {
+        let mut map = square();
+        map.sectors[0].heightceiling = 256;
+        let shape = points(&[(32., 32.), (32., 96.), (96., 96.), (96., 32.)]);
+        let result = draw_sector(&mut map, &shape, &EditDefaults::default()).unwrap();
+        assert_eq!(result.sectors, vec![1]);
+        // The new sector is a copy of the sector around it
+        assert_eq!(map.sectors[1].heightceiling, 256);
+        result.lines.iter().for_each(|&line| {
+            assert_eq!(level::side_sector(&map, line, LineSide::Front), Some(1));
+            assert_eq!(level::side_sector(&map, line, LineSide::Back), Some(0));
+            assert_eq!(map.linedefs[line].props.get("twosided").unwrap(), "true");
+        });
+        (0..4).for_each(|line| {
+            assert_eq!(level::side_sector(&map, line, LineSide::Front), Some(0));
+        });
+    }
+
+    #[test]
+    fn draw_across_sector() {
+        // Cut the right side off of the square, going out into the void
+        let mut map = square();
+        let shape = points(&[(64., -32.), (64., 160.), (192., 160.), (192., -32.)]);
+        let result = draw_sector(&mut map, &shape, &EditDefaults::default()).unwrap();
+        // The area overlapping the square, and the area in the void
+        assert_eq!(result.sectors.len(), 2);
+        // Lines 1 and 3 are split, and line 2 is fully inside the shape
+        assert_eq!(map.linedefs.len(), 4 + 2 + 6);
+        let inside = level::side_sector(&map, 2, LineSide::Front).unwrap();
+        assert!(result.sectors.contains(&inside));
+        assert_eq!(level::side_sector(&map, 3, LineSide::Front), Some(inside));
+        assert_eq!(level::side_sector(&map, 0, LineSide::Front), Some(0));
+        assert_eq!(level::side_sector(&map, 1, LineSide::Front), Some(0));
+        // The lines which the square used to be bounded by are now two-sided
+        let outside = *result.sectors.iter().find(|&&s| s != inside).unwrap();
+        assert_eq!(level::side_sector(&map, 2, LineSide::Back), Some(outside));
+    }
+
+    #[test]
+    fn draw_across_two_sectors() {
+        let mut map = make_map(
+            &[(0., 0.), (0., 64.), (64., 64.), (64., 0.), (128., 64.), (128., 0.)],
+            &[(0, 1, 0, None), (1, 2, 0, None), (2, 3, 0, Some(1)),
+              (3, 0, 0, None), (2, 4, 1, None), (4, 5, 1, None), (5, 3, 1, None)]
+        );
+        map.sectors[1].heightfloor = 8;
+        let shape = points(&[(32., 16.), (32., 48.), (96., 48.), (96., 16.)]);
+        let result = draw_sector(&mut map, &shape, &EditDefaults::default()).unwrap();
+        // Each half of the shape is a copy of the sector it was drawn in
+        assert_eq!(result.sectors.len(), 2);
+        let floors: Vec<i32> = result.sectors.iter()
+            .map(|&sector| map.sectors[sector].heightfloor).collect();
+        assert!(floors.contains(&0) && floors.contains(&8));
+        // The part of the line between the sectors inside of the shape is
+        // between the new sectors
+        let middle = (0..map.linedefs.len()).find(|&line| {
+            let Segment(a, b) = level::line_segment(&map, line);
+            a.x == 64. && b.x == 64. && a.y.min(b.y) == 16.
+        }).unwrap();
+        let front = level::side_sector(&map, middle, LineSide::Front).unwrap();
+        let back = level::side_sector(&map, middle, LineSide::Back).unwrap();
+        assert_eq!((map.sectors[front].heightfloor, map.sectors[back].heightfloor), (0, 8));
+        assert!(result.sectors.contains(&front) && result.sectors.contains(&back));
+        // The outer lines keep their sectors
+        assert_eq!(level::side_sector(&map, 0, LineSide::Front), Some(0));
+        assert_eq!(level::side_sector(&map, 5, LineSide::Front), Some(1));
+    }
+
+    #[test]
+    fn draw_over_existing_line() {
+        // Draw a room sharing a wall with the square
+        let mut map = square();
+        let shape = points(&[(128., 0.), (128., 128.), (256., 128.), (256., 0.)]);
+        let result = draw_sector(&mut map, &shape, &EditDefaults::default()).unwrap();
+        assert_eq!(result.sectors, vec![1]);
+        assert!(result.lines.contains(&2));
+        assert_eq!(map.linedefs.len(), 7);
+        assert_eq!(map.vertices.len(), 6);
+        assert_eq!(level::side_sector(&map, 2, LineSide::Front), Some(0));
+        assert_eq!(level::side_sector(&map, 2, LineSide::Back), Some(1));
+        let sidedef = level::line_sidedef(&map.linedefs[2], LineSide::Front).unwrap();
+        assert_eq!(map.sidedefs[sidedef].texturemiddle, SidedefTexture::default());
+    }
+
+    #[test]
+    fn not_enough_points() {
+        let mut map = square();
+        let shape = points(&[(0., 0.), (64., 0.), (64., 0.)]);
+        let result = draw_sector(&mut map, &shape, &EditDefaults::default());
+        assert_eq!(result, Err(EditError::NotEnoughPoints { needed: 3, got: 2 }));
+        assert_eq!(map.linedefs.len(), 4);
+    }
+}
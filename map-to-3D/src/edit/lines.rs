@@ -0,0 +1,400 @@
+//! # Line and vertex editing
+//!
+//! Splitting, joining and flipping lines, and merging vertices.
+use std::collections::HashMap;
+use glam::Vec2;
+use parsers::udmf::input::UDMFMap;
+use super::*;
+
+/// Split the line at the given point. If the point is slightly off of the
+/// line (less than 1 map unit away), it is moved onto the line.
+///
+/// The new line gets copies of the original line's properties and sidedefs,
+/// and goes from the split point to the original line's second vertex.
+/// Returns the index of the new line.
+///
+/// # Example
+///
+/// ```
+/// use glam::Vec2;
+/// use map_to_3D::edit::{draw_sector, split_line, EditDefaults};
+/// use parsers::udmf::input::UDMFMap;
+///
+/// let mut map = UDMFMap::default();
+/// let room = [Vec2::new(0., 0.), Vec2::new(0., 64.), Vec2::new(64., 0.)];
+/// draw_sector(&mut map, &room, &EditDefaults::default()).unwrap();
+/// let new_line = split_line(&mut map, 0, Vec2::new(0., 32.)).unwrap();
+/// assert_eq!(new_line, 3);
+/// assert_eq!(map.vertices.len(), 4);
+/// ```
+pub fn split_line(
+    map: &mut UDMFMap,
+    line: usize,
+    point: Vec2
+) -> Result<usize, EditError> {
+    check_line(map, line)?;
+    let segment = level::line_segment(map, line);
+    let Segment(a, b) = segment;
+    let along = (point - a).dot(b - a) / a.distance_squared(b);
+    let on_line = a + (b - a) * along;
+    if on_line.distance(point) >= 1. || !on_segment_interior(on_line, segment) {
+        return Err(EditError::PointNotOnLine { line });
+    }
+    let vertex = find_or_add_vertex(map, on_line);
+    Ok(split_line_at(map, line, vertex))
+}
+
+/// Check that the line is in the map
+fn check_line(map: &UDMFMap, line: usize) -> Result<(), EditError> {
+    if line < map.linedefs.len() {
+        Ok(())
+    } else {
+        Err(EditError::NoSuchLine { line })
+    }
+}
+
+/// Merge the `others` vertices into the `keep` vertex.
+///
+/// All of the lines using the merged vertices are connected to the kept
+/// vertex instead, and lines which end up with no length are removed, along
+/// with any of their sidedefs which aren't used by other lines. Lines which
+/// end up with the same vertices as another line are merged into that line.
+///
+/// The vertices after `keep` move down by one for each merged vertex which
+/// came before them.
+pub fn merge_vertices(
+    map: &mut UDMFMap,
+    keep: usize,
+    others: &[usize]
+) -> Result<(), EditError> {
+    if let Some(&vertex) = std::iter::once(&keep).chain(others)
+        .find(|&&vertex| vertex >= map.vertices.len()) {
+        return Err(EditError::NoSuchVertex { vertex });
+    }
+    let others: Vec<usize> = others.iter().copied()
+        .filter(|&other| other != keep).collect();
+    map.linedefs.iter_mut().for_each(|l| {
+        if others.contains(&(l.v1 as usize)) { l.v1 = keep as u32; }
+        if others.contains(&(l.v2 as usize)) { l.v2 = keep as u32; }
+    });
+    let zero_length: Vec<usize> = map.linedefs.iter().enumerate()
+        .filter(|(_, l)| l.v1 == l.v2)
+        .map(|(index, _)| index).collect();
+    remove_lines(map, &zero_length);
+    merge_duplicate_lines(map);
+    remove_vertices(map, &others);
+    Ok(())
+}
+
+/// Merge lines which connect the same two vertices into the first of those
+/// lines. Sides which the first line is missing are taken from the other
+/// lines, so two one-sided lines facing away from each other become one
+/// two-sided line.
+fn merge_duplicate_lines(map: &mut UDMFMap) {
+    let mut first_lines: HashMap<(u32, u32), usize> = HashMap::new();
+    let mut duplicates = Vec::new();
+    let mut gained_back = Vec::new();
+    (0..map.linedefs.len()).for_each(|line| {
+        let l = &map.linedefs[line];
+        let key = (l.v1.min(l.v2), l.v1.max(l.v2));
+        let &mut kept = first_lines.entry(key).or_insert(line);
+        if kept == line {
+            return;
+        }
+        let same_direction = map.linedefs[kept].v1 == l.v1;
+        [LineSide::Front, LineSide::Back].into_iter().for_each(|side| {
+            let other_side = if same_direction { side } else { side.opposite() };
+            if level::line_sidedef(&map.linedefs[kept], side).is_none() {
+                let sidedef = level::line_sidedef(&map.linedefs[line], other_side);
+                level::set_line_sidedef(&mut map.linedefs[kept], side, sidedef);
+                if side == LineSide::Back && sidedef.is_some() {
+                    gained_back.push(kept);
+                }
+            }
+        });
+        duplicates.push(line);
+    });
+    gained_back.into_iter().for_each(|line| {
+        update_line_sidedness(map, line, &EditDefaults::default());
+    });
+    remove_lines(map, &duplicates);
+}
+
+/// Merge vertices which are at the same position, or very close to each
+/// other. Line ends which use a vertex which doesn't exist are left alone.
+/// Returns the number of vertices which were removed.
+pub fn merge_coincident_vertices(map: &mut UDMFMap) -> usize {
+    let positions = level::vertex_positions(map);
+    let mut merge_into: Vec<usize> = (0..positions.len()).collect();
+    // Sort the vertices from left to right, so that only the vertices with
+    // nearly the same X coordinate need to be compared.
+    let mut order: Vec<usize> = (0..positions.len()).collect();
+    order.sort_by(|&a, &b| positions[a].x.total_cmp(&positions[b].x));
+    order.iter().enumerate().for_each(|(index, &vertex)| {
+        if merge_into[vertex] != vertex { return; }
+        order[index + 1..].iter()
+            .take_while(|&&other| {
+                positions[other].x - positions[vertex].x < VERTEX_MERGE_DISTANCE
+            })
+            .for_each(|&other| {
+                if merge_into[other] == other &&
+                    positions[other].distance(positions[vertex]) < VERTEX_MERGE_DISTANCE {
+                    merge_into[other] = vertex;
+                }
+            });
+    });
+    map.linedefs.iter_mut().for_each(|l| {
+        [&mut l.v1, &mut l.v2].into_iter().for_each(|vertex| {
+            if let Some(&merged) = merge_into.get(*vertex as usize) {
+                *vertex = merged as u32;
+            }
+        });
+    });
+    let merged: Vec<usize> = (0..positions.len())
+        .filter(|&vertex| merge_into[vertex] != vertex).collect();
+    let zero_length: Vec<usize> = map.linedefs.iter().enumerate()
+        .filter(|(_, l)| l.v1 == l.v2)
+        .map(|(index, _)| index).collect();
+    remove_lines(map, &zero_length);
+    merge_duplicate_lines(map);
+    remove_vertices(map, &merged);
+    merged.len()
+}
+
+/// Join two collinear lines which share a vertex into one line. Both lines
+/// must have the same sectors on each of their sides.
+///
+/// The first line is extended to cover the second line, which is removed,
+/// along with its sidedefs. The texture offsets of the first line's sidedefs
+/// are adjusted so that the textures don't move. The vertex the lines shared
+/// is also removed if no other lines use it.
+pub fn join_lines(
+    map: &mut UDMFMap,
+    first: usize,
+    second: usize
+) -> Result<(), EditError> {
+    check_line(map, first)?;
+    check_line(map, second)?;
+    let (a, b) = (&map.linedefs[first], &map.linedefs[second]);
+    let (shared, far) = if first == second {
+        return Err(EditError::LinesNotConnected { first, second });
+    } else if a.v1 == b.v1 || a.v1 == b.v2 {
+        (a.v1, if a.v1 == b.v1 { b.v2 } else { b.v1 })
+    } else if a.v2 == b.v1 || a.v2 == b.v2 {
+        (a.v2, if a.v2 == b.v1 { b.v2 } else { b.v1 })
+    } else {
+        return Err(EditError::LinesNotConnected { first, second });
+    };
+    let other_end = if a.v1 == shared { a.v2 } else { a.v1 };
+    let shared_pos = level::vertex_position(map, shared as usize);
+    let far_pos = level::vertex_position(map, far as usize);
+    let other_pos = level::vertex_position(map, other_end as usize);
+    // The shared vertex must be between the other two vertices
+    if far == other_end || !on_segment_interior(shared_pos, Segment(other_pos, far_pos)) {
+        return Err(EditError::LinesNotCollinear { first, second });
+    }
+    // If the lines go the same way, their fronts are on the same side
+    let same_direction = (a.v2 == shared) == (b.v1 == shared);
+    let same_sectors = [LineSide::Front, LineSide::Back].into_iter().all(|side| {
+        let other_side = if same_direction { side } else { side.opposite() };
+        level::side_sector(map, first, side) == level::side_sector(map, second, other_side)
+    });
+    if !same_sectors {
+        return Err(EditError::DifferentSectors { first, second });
+    }
+    let added = shared_pos.distance(far_pos).round() as i32;
+    // The side whose texture starts at the moved vertex needs to be offset
+    let moved_side = if a.v1 == shared { LineSide::Front } else { LineSide::Back };
+    let l = &mut map.linedefs[first];
+    if l.v1 == shared { l.v1 = far; } else { l.v2 = far; }
+    if let Some(sidedef) = level::line_sidedef(&map.linedefs[first], moved_side)
+        .filter(|&s| s < map.sidedefs.len()) {
+        // Don't affect any other lines using the same sidedef
+        let mut copy = map.sidedefs[sidedef].clone();
+        copy.offsetx -= added;
+        map.sidedefs.push(copy);
+        level::set_line_sidedef(
+            &mut map.linedefs[first], moved_side, Some(map.sidedefs.len() - 1));
+    }
+    remove_lines(map, &[second]);
+    let shared = shared as usize;
+    if !map.linedefs.iter().any(|l| l.v1 as usize == shared || l.v2 as usize == shared) {
+        remove_vertices(map, &[shared]);
+    }
+    Ok(())
+}
+
+/// Flip the direction of the line, and swap its sides, so that the sides
+/// stay on the same side of the line.
+///
+/// One-sided lines can't be flipped this way, since their only side would
+/// end up on the back.
+pub fn flip_line(map: &mut UDMFMap, line: usize) -> Result<(), EditError> {
+    check_line(map, line)?;
+    let l = &mut map.linedefs[line];
+    let back = level::line_sidedef(l, LineSide::Back)
+        .ok_or(EditError::OneSided { line })?;
+    let front = level::line_sidedef(l, LineSide::Front);
+    std::mem::swap(&mut l.v1, &mut l.v2);
+    level::set_line_sidedef(l, LineSide::Front, Some(back));
+    level::set_line_sidedef(l, LineSide::Back, front);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testmap::*;
+
+    #[test]
+    fn split_off_line() {
+        let mut map = square();
+        assert_eq!(
+            split_line(&mut map, 0, Vec2::new(64., 64.)),
+            Err(EditError::PointNotOnLine { line: 0 }));
+        assert_eq!(
+            split_line(&mut map, 0, Vec2::new(0., 128.)),
+            Err(EditError::PointNotOnLine { line: 0 }));
+        assert_eq!(
+            split_line(&mut map, 4, Vec2::new(0., 32.)),
+            Err(EditError::NoSuchLine { line: 4 }));
+        assert_eq!(map.linedefs.len(), 4);
+        let new_line = split_line(&mut map, 0, Vec2::new(0.001, 32.)).unwrap();
+        assert_eq!(map.vertices[4].x, 0.);
+        assert_eq!(map.vertices[4].y, 32.);
+        assert_eq!(level::side_sector(&map, new_line, LineSide::Front), Some(0));
+        assert_ne!(map.linedefs[new_line].sidefront, map.linedefs[0].sidefront);
+    }
+
+    #[test]
+    fn merge_removes_zero_length_lines() {
+        let mut map = square();
+        // Merge the top right corner into the top left corner, making a
+        // triangle
+        assert_eq!(
+            merge_vertices(&mut map, 4, &[1]),
+            Err(EditError::NoSuchVertex { vertex: 4 }));
+        assert_eq!(
+            merge_vertices(&mut map, 2, &[1, 7]),
+            Err(EditError::NoSuchVertex { vertex: 7 }));
+        merge_vertices(&mut map, 2, &[1]).unwrap();
+        assert_eq!(map.vertices.len(), 3);
+        assert_eq!((map.vertices[1].x, map.vertices[1].y), (128., 128.));
+        assert_eq!(map.linedefs.len(), 3);
+        assert_eq!(map.sidedefs.len(), 3);
+        map.linedefs.iter().for_each(|l| {
+            assert_ne!(l.v1, l.v2);
+            assert!(l.v1 < 3 && l.v2 < 3);
+        });
+    }
+
+    #[test]
+    fn merge_joins_duplicate_lines() {
+        // Two triangles next to each other, with a gap between them
+        let mut map = make_map(
+            &[(0., 0.), (0., 64.), (64., 0.), (64.001, 64.), (128., 0.), (64., 64.)],
+            &[(0, 1, 0, None), (1, 5, 0, None), (5, 2, 0, None), (2, 0, 0, None),
+                (2, 3, 1, None), (3, 4, 1, None), (4, 2, 1, None)]
+        );
+        merge_vertices(&mut map, 5, &[3]).unwrap();
+        assert_eq!(map.linedefs.len(), 6);
+        assert_eq!(map.vertices.len(), 5);
+        // The lines between the triangles became one two-sided line
+        let l = &map.linedefs[2];
+        assert_eq!((l.v1, l.v2), (4, 2));
+        assert_eq!(level::side_sector(&map, 2, LineSide::Front), Some(0));
+        assert_eq!(level::side_sector(&map, 2, LineSide::Back), Some(1));
+        assert_eq!(map.linedefs[2].props.get("twosided").map(String::as_str), Some("true"));
+        assert_eq!(map.sidedefs.len(), 7);
+    }
+
+    #[test]
+    fn merge_coincident() {
+        let mut map = make_map(
+            &[(0., 0.), (64., 0.), (64., 64.), (64.001, 0.), (0., 0.)],
+            &[(0, 1, 0, None), (3, 2, 0, None), (2, 4, 0, None)]
+        );
+        assert_eq!(merge_coincident_vertices(&mut map), 2);
+        assert_eq!(map.vertices.len(), 3);
+        let l = &map.linedefs;
+        assert_eq!((l[0].v1, l[0].v2, l[1].v1, l[1].v2, l[2].v1, l[2].v2),
+            (0, 1, 1, 2, 2, 0));
+    }
+
+    #[test]
+    fn merge_coincident_skips_missing_vertices() {
+        let mut map = make_map(
+            &[(0., 0.), (64., 0.), (64.001, 0.)],
+            &[(0, 1, 0, None), (2, 0, 0, None)]
+        );
+        map.linedefs[1].v2 = 9;
+        assert_eq!(merge_coincident_vertices(&mut map), 1);
+        assert_eq!(map.vertices.len(), 2);
+        assert_eq!((map.linedefs[1].v1, map.linedefs[1].v2), (1, 9));
+    }
+
+    #[test]
+    fn join() {
+        let mut map = make_map(
+            &[(0., 0.), (64., 0.), (96., 0.), (64., 64.)],
+            &[(1, 0, 0, Some(1)), (2, 1, 0, Some(1)), (1, 3, 0, None)]
+        );
+        assert_eq!(
+            join_lines(&mut map, 0, 2),
+            Err(EditError::LinesNotCollinear { first: 0, second: 2 }));
+        assert_eq!(
+            join_lines(&mut map, 0, 3),
+            Err(EditError::NoSuchLine { line: 3 }));
+        join_lines(&mut map, 0, 1).unwrap();
+        assert_eq!(map.linedefs.len(), 2);
+        assert_eq!((map.linedefs[0].v1, map.linedefs[0].v2), (2, 0));
+        let front = level::line_sidedef(&map.linedefs[0], LineSide::Front).unwrap();
+        assert_eq!(map.sidedefs[front].offsetx, -32);
+        let back = level::line_sidedef(&map.linedefs[0], LineSide::Back).unwrap();
+        assert_eq!(map.sidedefs[back].offsetx, 0);
+        // The shared vertex is still used by the last line
+        assert_eq!(map.vertices.len(), 4);
+    }
+
+    #[test]
+    fn join_needs_same_sectors() {
+        let mut map = make_map(
+            &[(0., 0.), (64., 0.), (96., 0.), (-32., 0.)],
+            &[(0, 1, 0, Some(1)), (1, 2, 0, None), (0, 3, 1, Some(0))]
+        );
+        assert_eq!(
+            join_lines(&mut map, 0, 1),
+            Err(EditError::DifferentSectors { first: 0, second: 1 }));
+        assert_eq!(map.linedefs.len(), 3);
+        // The third line goes the other way, so its sides are swapped
+        join_lines(&mut map, 0, 2).unwrap();
+        assert_eq!(map.linedefs.len(), 2);
+        assert_eq!((map.linedefs[0].v1, map.linedefs[0].v2), (2, 0));
+    }
+
+    #[test]
+    fn join_removes_vertex() {
+        let mut map = make_map(
+            &[(0., 0.), (64., 0.), (96., 0.)],
+            &[(0, 1, 0, None), (1, 2, 0, None)]
+        );
+        join_lines(&mut map, 0, 1).unwrap();
+        assert_eq!(map.vertices.len(), 2);
+        assert_eq!((map.linedefs[0].v1, map.linedefs[0].v2), (0, 1));
+        assert_eq!(map.vertices[1].x, 96.);
+    }
+
+    #[test]
+    fn flip() {
+        let mut map = make_map(
+            &[(0., 0.), (64., 0.)],
+            &[(0, 1, 0, Some(1)), (1, 0, 0, None)]
+        );
+        flip_line(&mut map, 0).unwrap();
+        assert_eq!((map.linedefs[0].v1, map.linedefs[0].v2), (1, 0));
+        assert_eq!(level::side_sector(&map, 0, LineSide::Front), Some(1));
+        assert_eq!(level::side_sector(&map, 0, LineSide::Back), Some(0));
+        assert_eq!(flip_line(&mut map, 1), Err(EditError::OneSided { line: 1 }));
+        assert_eq!(flip_line(&mut map, 2), Err(EditError::NoSuchLine { line: 2 }));
+    }
+}
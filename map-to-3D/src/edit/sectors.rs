@@ -0,0 +1,520 @@
+//! # Sector editing
+//!
+//! Joining, merging and splitting sectors.
+use glam::Vec2;
+use parsers::udmf::input::UDMFMap;
+use std::collections::HashSet;
+use ahash::RandomState;
+use crate::predicates::point_in_polygon;
+use super::*;
+use super::draw::clean_path;
+
+/// Make the given sectors into one sector, by making all of the sidedefs
+/// which use them use the first sector instead. The other sectors are
+/// removed, and the lines between the sectors are kept. Sectors which are
+/// given more than once are only removed once. Returns the new index of the
+/// first sector.
+pub fn join_sectors(map: &mut UDMFMap, sectors: &[usize]) -> Result<usize, EditError> {
+    check_sectors(map, sectors)?;
+    let keep = sectors[0];
+    let mut others: Vec<usize> = sectors.iter().copied()
+        .filter(|&sector| sector != keep).collect();
+    others.sort_unstable();
+    others.dedup();
+    map.sidedefs.iter_mut().for_each(|sidedef| {
+        if others.contains(&(sidedef.sector as usize)) {
+            sidedef.sector = keep as u32;
+        }
+    });
+    remove_sectors(map, &others);
+    Ok(keep - others.iter().filter(|&&other| other < keep).count())
+}
+
+/// Check that there are some sectors, and that they are all in the map
+fn check_sectors(map: &UDMFMap, sectors: &[usize]) -> Result<(), EditError> {
+    if sectors.is_empty() {
+        return Err(EditError::NoSectors);
+    }
+    match sectors.iter().find(|&&sector| sector >= map.sectors.len()) {
+        Some(&sector) => Err(EditError::NoSuchSector { sector }),
+        None => Ok(()),
+    }
+}
+
+/// Merge the given sectors into one sector, like `join_sectors`, and remove
+/// the lines between them. Vertices which were only used by the removed lines
+/// are removed as well. Returns the new index of the first sector.
+pub fn merge_sectors(map: &mut UDMFMap, sectors: &[usize]) -> Result<usize, EditError> {
+    check_sectors(map, sectors)?;
+    let between: Vec<usize> = (0..map.linedefs.len()).filter(|&line| {
+        let front = level::side_sector(map, line, LineSide::Front);
+        let back = level::side_sector(map, line, LineSide::Back);
+        matches!((front, back), (Some(front), Some(back))
+            if sectors.contains(&front) && sectors.contains(&back))
+    }).collect();
+    let mut vertices: Vec<usize> = between.iter().flat_map(|&line| {
+        let l = &map.linedefs[line];
+        [l.v1 as usize, l.v2 as usize]
+    }).collect();
+    remove_lines(map, &between);
+    vertices.retain(|&vertex| !map.linedefs.iter().any(|l| {
+        l.v1 as usize == vertex || l.v2 as usize == vertex
+    }));
+    vertices.sort_unstable();
+    vertices.dedup();
+    remove_vertices(map, &vertices);
+    join_sectors(map, sectors)
+}
+
+/// A part of a sector which is being split, made up of the edges of its
+/// outline, and the polygons built from them.
+struct Piece {
+    edges: HashSet<Edge, RandomState>,
+    polygons: Vec<SectorPolygon>,
+}
+
+impl Piece {
+    fn new(edges: HashSet<Edge, RandomState>, vertices: &[Vec2]) -> Piece {
+        // Sorted, so that the polygons don't depend on the order of the set
+        let mut sorted: Vec<Edge> = edges.iter().copied().collect();
+        sorted.sort_unstable();
+        let polygons = spb::build_polygons(&sorted, vertices);
+        Piece { edges, polygons }
+    }
+
+    /// The area of the piece, not counting its holes
+    fn area(&self, vertices: &[Vec2]) -> f32 {
+        self.polygons.iter().map(|polygon| {
+            let area = loop_area(&polygon.vertices, vertices);
+            if polygon.hole_of.is_some() { -area } else { area }
+        }).sum()
+    }
+}
+
+/// The area enclosed by a closed loop of vertices
+fn loop_area(vertices: &[usize], positions: &[Vec2]) -> f32 {
+    vertices.iter().zip(vertices.iter().cycle().skip(1))
+        .map(|(&a, &b)| positions[a].perp_dot(positions[b]))
+        .sum::<f32>().abs() / 2.
+}
+
+/// The edges between each vertex of a closed loop and the next one
+fn loop_edges(vertices: &[usize]) -> impl Iterator<Item = Edge> + '_ {
+    vertices.iter().zip(vertices.iter().cycle().skip(1))
+        .filter_map(|(&a, &b)| Edge::try_new(a, b))
+}
+
+/// Split one of the pieces along a path of new lines.
+///
+/// The path either goes between two vertices on the same contour of a piece,
+/// or is a closed loop. Either way, it divides the piece into two loops, and
+/// the smaller one becomes a new piece, along with any contours inside of it.
+/// Returns false if the path doesn't divide any of the pieces.
+fn split_piece(pieces: &mut Vec<Piece>, path: &[usize], vertices: &[Vec2]) -> bool {
+    let (start, end) = (path[0], path[path.len() - 1]);
+    let middle = (vertices[path[0]] + vertices[path[1]]) / 2.;
+    let Some(index) = pieces.iter().position(|piece| {
+        polygon_at(&piece.polygons, vertices, middle).is_some()
+    }) else {
+        return false;
+    };
+    let piece = &pieces[index];
+    let between = &path[1..path.len() - 1];
+    let contour = piece.polygons.iter().position(|polygon| {
+        polygon.vertices.contains(&start) && polygon.vertices.contains(&end)
+    });
+    let loops: Vec<Vec<usize>> = match contour {
+        Some(contour) => {
+            // Start going around the contour from the start of the path
+            let contour = &piece.polygons[contour].vertices;
+            let first = contour.iter().position(|&v| v == start)
+                .expect("The contour contains the start of the path");
+            let around: Vec<usize> = contour[first..].iter()
+                .chain(&contour[..first]).copied().collect();
+            let last = around.iter().position(|&v| v == end)
+                .expect("The contour contains the end of the path");
+            vec![
+                around[..=last].iter().chain(between.iter().rev()).copied().collect(),
+                around[last..].iter().chain(&[start]).chain(between).copied().collect(),
+            ]
+        },
+        None if start == end => vec![path[..path.len() - 1].to_vec()],
+        None => return false,
+    };
+    let new_loop = loops.into_iter().min_by(|a, b| {
+        loop_area(a, vertices).total_cmp(&loop_area(b, vertices))
+    }).expect("There is at least one loop");
+    let positions: Vec<Vec2> = new_loop.iter().map(|&v| vertices[v]).collect();
+    let mut new_edges: HashSet<Edge, RandomState> = loop_edges(&new_loop).collect();
+    // Other contours inside of the loop go with it
+    piece.polygons.iter().enumerate()
+        .filter(|&(other, _)| Some(other) != contour)
+        .for_each(|(_, polygon)| {
+            let inside = polygon.vertices.iter()
+                .find(|vertex| !new_loop.contains(vertex))
+                .is_some_and(|&vertex| point_in_polygon(vertices[vertex], &positions));
+            if inside {
+                new_edges.extend(loop_edges(&polygon.vertices));
+            }
+        });
+    let mut rest: HashSet<Edge, RandomState> = piece.edges.difference(&new_edges)
+        .copied().collect();
+    rest.extend(path.windows(2).filter_map(|pair| Edge::try_new(pair[0], pair[1])));
+    pieces[index] = Piece::new(rest, vertices);
+    pieces.push(Piece::new(new_edges, vertices));
+    true
+}
+
+/// Split a sector by drawing lines across it.
+///
+/// The drawn lines are given as a path, which does not need to be closed.
+/// Only the parts of the path which are inside the sector are added as lines,
+/// and the sector's lines are split where the path crosses them. Each part of
+/// the path which goes from the sector's outline back to its outline, or
+/// which closes a loop, cuts off a piece of the sector. The pieces are built
+/// from their lines the same way as the polygons of any other sector, so
+/// islands inside the sector (like pillars, or holes into the void) end up in
+/// the piece they are in. The largest piece keeps the original sector, and
+/// the other pieces get copies of it.
+///
+/// Returns the sectors of the pieces, starting with the original sector.
+///
+/// # Example
+///
+/// ```
+/// use glam::Vec2;
+/// use map_to_3D::edit::{draw_sector, split_sector, EditDefaults};
+/// use parsers::udmf::input::UDMFMap;
+///
+/// let mut map = UDMFMap::default();
+/// let defaults = EditDefaults::default();
+/// let room = [
+///     Vec2::new(0., 0.),
+///     Vec2::new(0., 128.),
+///     Vec2::new(128., 128.),
+///     Vec2::new(128., 0.),
+/// ];
+/// draw_sector(&mut map, &room, &defaults).unwrap();
+/// let cut = [Vec2::new(-32., 64.), Vec2::new(160., 64.)];
+/// let sectors = split_sector(&mut map, 0, &cut, &defaults).unwrap();
+/// assert_eq!(sectors, vec![0, 1]);
+/// assert_eq!(map.linedefs.len(), 7);
+/// ```
+pub fn split_sector(
+    map: &mut UDMFMap,
+    sector: usize,
+    points: &[Vec2],
+    defaults: &EditDefaults
+) -> Result<Vec<usize>, EditError> {
+    let path = clean_path(points);
+    if path.len() < 2 {
+        return Err(EditError::NotEnoughPoints { needed: 2, got: path.len() });
+    }
+    let mut work = map.clone();
+    let old_vertex_count = work.vertices.len();
+    let drawn: Vec<Segment> = path.windows(2)
+        .map(|pair| Segment(pair[0], pair[1])).collect();
+
+    // Split the sector's lines where the drawn path crosses them
+    let old_lines = level::sector_lines(&work, sector);
+    let chain = split_lines_along(&mut work, &old_lines, &drawn);
+
+    // Add the parts of the drawn path which are inside the sector
+    let old_vertices = level::vertex_positions(map);
+    let polygons = sector_polygons(map, sector);
+    let mut existing: HashSet<Edge, RandomState> = work.linedefs.iter()
+        .filter(|l| l.v1 != l.v2)
+        .map(|l| Edge::new(l.v1 as usize, l.v2 as usize)).collect();
+    let first_new_line = work.linedefs.len();
+    let added: Vec<bool> = chain.windows(2).map(|pair| {
+        let (a, b) = (pair[0], pair[1]);
+        let middle = (level::vertex_position(&work, a) +
+            level::vertex_position(&work, b)) / 2.;
+        let inside = polygon_at(&polygons, &old_vertices, middle).is_some();
+        if inside && existing.insert(Edge::new(a, b)) {
+            add_unsided_line(&mut work, a, b);
+            return true;
+        }
+        false
+    }).collect();
+    if work.linedefs.len() == first_new_line {
+        return Err(EditError::SectorNotSplit { sector });
+    }
+
+    // Cut the sector into pieces, one part of the path at a time
+    let vertices = level::vertex_positions(&work);
+    let outline = level::sector_edges(&work).into_iter().nth(sector)
+        .unwrap_or_default();
+    let mut pieces = vec![Piece::new(outline.into_iter().collect(), &vertices)];
+    let mut on_outline: HashSet<usize, RandomState> = pieces[0].edges.iter()
+        .flat_map(|edge| [edge.lo(), edge.hi()]).collect();
+    let mut run: Vec<usize> = Vec::new();
+    chain.windows(2).zip(added).for_each(|(pair, added)| {
+        if !added {
+            run.clear();
+            return;
+        }
+        if run.is_empty() {
+            run.push(pair[0]);
+        }
+        let vertex = pair[1];
+        let closes_loop = run.iter().position(|&v| v == vertex);
+        run.push(vertex);
+        let cut = match closes_loop {
+            Some(start) => run.split_off(start),
+            None if on_outline.contains(&vertex) => std::mem::take(&mut run),
+            None => return,
+        };
+        // Parts of the path which stick out from the outline, or into the
+        // sector from nowhere, don't cut anything off
+        if (closes_loop.is_some() || on_outline.contains(&cut[0])) &&
+            split_piece(&mut pieces, &cut, &vertices) {
+            on_outline.extend(&cut);
+        }
+        run = vec![vertex];
+    });
+    if pieces.len() < 2 {
+        return Err(EditError::SectorNotSplit { sector });
+    }
+
+    // The largest piece keeps the sector, and the others get copies of it
+    let areas: Vec<f32> = pieces.iter().map(|piece| piece.area(&vertices)).collect();
+    let largest = (0..pieces.len())
+        .fold(0, |best, piece| if areas[piece] > areas[best] { piece } else { best });
+    let mut sectors = vec![sector];
+    let piece_sectors: Vec<usize> = (0..pieces.len()).map(|piece| {
+        if piece == largest {
+            return sector;
+        }
+        work.sectors.push(work.sectors[sector].clone());
+        sectors.push(work.sectors.len() - 1);
+        work.sectors.len() - 1
+    }).collect();
+    let sides: Vec<(usize, LineSide)> = (0..work.linedefs.len())
+        .flat_map(|line| [(line, LineSide::Front), (line, LineSide::Back)])
+        .filter(|&(line, side)| {
+            line >= first_new_line || level::side_sector(&work, line, side) == Some(sector)
+        }).collect();
+    sides.into_iter().for_each(|(line, side)| {
+        let point = point_beside(&work, line, side);
+        let new_sector = pieces.iter()
+            .position(|piece| polygon_at(&piece.polygons, &vertices, point).is_some())
+            .map_or(sector, |piece| piece_sectors[piece]);
+        if line >= first_new_line || new_sector != sector {
+            set_side_sector(&mut work, line, side, new_sector);
+        }
+    });
+    if !finish_unsided_lines(&mut work, first_new_line) {
+        return Err(EditError::SectorNotSplit { sector });
+    }
+    (first_new_line..work.linedefs.len()).for_each(|line| {
+        update_line_sidedness(&mut work, line, defaults);
+    });
+
+    // Remove the vertices added for the parts of the path outside the sector
+    let mut used = vec![false; work.vertices.len()];
+    work.linedefs.iter().for_each(|l| {
+        used[l.v1 as usize] = true;
+        used[l.v2 as usize] = true;
+    });
+    let unused: Vec<usize> = (old_vertex_count..work.vertices.len())
+        .filter(|&vertex| !used[vertex]).collect();
+    remove_vertices(&mut work, &unused);
+    *map = work;
+    Ok(sectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testmap::*;
+
+    /// The holey sector from `examples/holey.rs`, with the triangles in the
+    /// big square being holes into the void, so their front sides face out. The small triangle outside of
+    /// the square is part of the same sector.
+    fn holey() -> UDMFMap {
+        make_map(
+            &[(70., 30.), (68., 30.), (69., 32.), (64., 64.), (64., -64.),
+              (-64., -64.), (-64., 64.), (44., 52.), (-52., 52.), (-52., -44.),
+              (52., 44.), (52., -52.), (-44., -52.)],
+            &[(3, 4, 0, None), (4, 5, 0, None), (5, 6, 0, None),
+              (6, 3, 0, None), (7, 8, 0, None), (8, 9, 0, None),
+              (9, 7, 0, None), (11, 10, 0, None), (12, 11, 0, None),
+              (10, 12, 0, None), (0, 1, 0, None), (1, 2, 0, None),
+              (2, 0, 0, None)]
+        )
+    }
+
+    #[test]
+    fn join() {
+        let mut map = make_map(
+            &[(0., 0.), (0., 64.), (64., 64.), (64., 0.), (128., 64.), (128., 0.)],
+            &[(0, 1, 0, None), (1, 2, 0, None), (2, 3, 0, Some(1)),
+              (3, 0, 0, None), (2, 4, 1, None), (4, 5, 1, None), (5, 3, 1, None)]
+        );
+        map.sectors.push(EditDefaults::default().sector());
+        map.sectors[1].heightfloor = 8;
+        assert_eq!(join_sectors(&mut map, &[]), Err(EditError::NoSectors));
+        assert_eq!(join_sectors(&mut map, &[7, 0]),
+            Err(EditError::NoSuchSector { sector: 7 }));
+        assert_eq!(join_sectors(&mut map, &[0, 7]),
+            Err(EditError::NoSuchSector { sector: 7 }));
+        assert!(map.sidedefs.iter().any(|s| s.sector == 1));
+        assert_eq!(join_sectors(&mut map, &[1, 0]), Ok(0));
+        assert_eq!(map.sectors.len(), 2);
+        assert_eq!(map.sectors[0].heightfloor, 8);
+        assert_eq!(map.linedefs.len(), 7);
+        assert_eq!(level::side_sector(&map, 2, LineSide::Front), Some(0));
+        assert_eq!(level::side_sector(&map, 2, LineSide::Back), Some(0));
+        assert!(map.sidedefs.iter().all(|s| s.sector == 0));
+    }
+
+    #[test]
+    fn join_repeated_sectors() {
+        let mut map = make_map(
+            &[(0., 0.), (0., 64.), (64., 64.), (64., 0.), (128., 64.), (128., 0.)],
+            &[(0, 1, 0, None), (1, 2, 0, None), (2, 3, 0, Some(1)),
+              (3, 0, 0, None), (2, 4, 1, None), (4, 5, 1, None), (5, 3, 1, None)]
+        );
+        map.sectors.push(EditDefaults::default().sector());
+        map.sectors[2].heightfloor = 8;
+        // Sector 0 is only removed once, so the kept sector ends up at 1
+        assert_eq!(join_sectors(&mut map, &[2, 0, 0]), Ok(1));
+        assert_eq!(map.sectors.len(), 2);
+        assert_eq!(map.sectors[1].heightfloor, 8);
+    }
+
+    #[test]
+    fn merge() {
+        let mut map = make_map(
+            &[(0., 0.), (0., 64.), (64., 64.), (64., 0.), (128., 64.), (128., 0.)],
+            &[(0, 1, 0, None), (1, 2, 0, None), (2, 3, 0, Some(1)),
+              (3, 0, 0, None), (2, 4, 1, None), (4, 5, 1, None), (5, 3, 1, None)]
+        );
+        assert_eq!(merge_sectors(&mut map, &[]), Err(EditError::NoSectors));
+        assert_eq!(merge_sectors(&mut map, &[0, 1, 2]),
+            Err(EditError::NoSuchSector { sector: 2 }));
+        assert_eq!(map.linedefs.len(), 7);
+        assert_eq!(merge_sectors(&mut map, &[0, 1]), Ok(0));
+        assert_eq!(map.sectors.len(), 1);
+        assert_eq!(map.linedefs.len(), 6);
+        // The vertices are still used by the outline
+        assert_eq!(map.vertices.len(), 6);
+        assert_eq!(sector_polygons(&map, 0).len(), 1);
+    }
+
+    #[test]
+    fn split_square() {
+        let mut map = square();
+        let cut = [Vec2::new(64., -32.), Vec2::new(64., 160.)];
+        let sectors = split_sector(&mut map, 0, &cut, &EditDefaults::default())
+            .unwrap();
+        assert_eq!(sectors, vec![0, 1]);
+        // The square's top and bottom lines are split, and the path's ends
+        // outside of the square are left out
+        assert_eq!(map.linedefs.len(), 7);
+        assert_eq!(map.vertices.len(), 6);
+        let new_line = 6;
+        assert_eq!(map.linedefs[new_line].props.get("twosided").unwrap(), "true");
+        let front = level::side_sector(&map, new_line, LineSide::Front).unwrap();
+        let back = level::side_sector(&map, new_line, LineSide::Back).unwrap();
+        assert_ne!(front, back);
+        // The left line is in a different area than the right line
+        assert_ne!(level::side_sector(&map, 0, LineSide::Front),
+            level::side_sector(&map, 2, LineSide::Front));
+    }
+
+    #[test]
+    fn split_holey() {
+        let mut map = holey();
+        // Split the big square along the diagonal between the holes
+        let cut = [Vec2::new(-64., -64.), Vec2::new(64., 64.)];
+        let sectors = split_sector(&mut map, 0, &cut, &EditDefaults::default())
+            .unwrap();
+        assert_eq!(sectors.len(), 2);
+        let new_line = map.linedefs.len() - 1;
+        assert_eq!(map.linedefs.len(), 14);
+        let upper_left = level::side_sector(&map, 2, LineSide::Front).unwrap();
+        let lower_right = level::side_sector(&map, 0, LineSide::Front).unwrap();
+        assert_ne!(upper_left, lower_right);
+        [1, 3].into_iter().for_each(|line| {
+            let expected = if line == 1 { lower_right } else { upper_left };
+            assert_eq!(level::side_sector(&map, line, LineSide::Front), Some(expected));
+        });
+        // Each hole is surrounded by the half of the square it's in
+        (4..7).for_each(|line| {
+            assert_eq!(level::side_sector(&map, line, LineSide::Front), Some(upper_left));
+        });
+        (7..10).for_each(|line| {
+            assert_eq!(level::side_sector(&map, line, LineSide::Front), Some(lower_right));
+        });
+        // The triangle outside of the square keeps the original sector
+        (10..13).for_each(|line| {
+            assert_eq!(level::side_sector(&map, line, LineSide::Front), Some(0));
+        });
+        let sides = [
+            level::side_sector(&map, new_line, LineSide::Front).unwrap(),
+            level::side_sector(&map, new_line, LineSide::Back).unwrap(),
+        ];
+        assert!(sides.contains(&upper_left) && sides.contains(&lower_right));
+    }
+
+    #[test]
+    fn split_off_loop() {
+        let mut map = square();
+        // A closed loop inside of the square, with a tail sticking out of it
+        let cut = [(32., 32.), (32., 96.), (96., 96.), (96., 32.), (32., 32.), (16., 16.)]
+            .map(|(x, y)| Vec2::new(x, y));
+        let sectors = split_sector(&mut map, 0, &cut, &EditDefaults::default())
+            .unwrap();
+        assert_eq!(sectors, vec![0, 1]);
+        assert_eq!(map.linedefs.len(), 9);
+        (4..8).for_each(|line| {
+            let front = level::side_sector(&map, line, LineSide::Front);
+            let back = level::side_sector(&map, line, LineSide::Back);
+            assert_eq!([front, back].iter().filter(|&&s| s == Some(1)).count(), 1);
+            assert!(front == Some(0) || back == Some(0));
+        });
+        // The tail is inside of the square's sector on both sides
+        assert_eq!(level::side_sector(&map, 8, LineSide::Front), Some(0));
+        assert_eq!(level::side_sector(&map, 8, LineSide::Back), Some(0));
+        (0..4).for_each(|line| {
+            assert_eq!(level::side_sector(&map, line, LineSide::Front), Some(0));
+        });
+    }
+
+    #[test]
+    fn split_with_zero_length_line() {
+        let mut map = square();
+        let mut line = map.linedefs[0].clone();
+        line.v2 = line.v1;
+        map.linedefs.push(line);
+        let cut = [Vec2::new(64., -32.), Vec2::new(64., 160.)];
+        let sectors = split_sector(&mut map, 0, &cut, &EditDefaults::default())
+            .unwrap();
+        assert_eq!(sectors, vec![0, 1]);
+        assert_ne!(level::side_sector(&map, 0, LineSide::Front),
+            level::side_sector(&map, 2, LineSide::Front));
+    }
+
+    #[test]
+    fn split_needs_separate_areas() {
+        let mut map = square();
+        // A line sticking into the sector doesn't split it
+        let cut = [Vec2::new(-32., 64.), Vec2::new(64., 64.)];
+        assert_eq!(
+            split_sector(&mut map, 0, &cut, &EditDefaults::default()),
+            Err(EditError::SectorNotSplit { sector: 0 }));
+        // Neither does a line in the void
+        let cut = [Vec2::new(-32., 0.), Vec2::new(-32., 128.)];
+        assert_eq!(
+            split_sector(&mut map, 0, &cut, &EditDefaults::default()),
+            Err(EditError::SectorNotSplit { sector: 0 }));
+        let cut = [Vec2::new(32., 64.)];
+        assert_eq!(
+            split_sector(&mut map, 0, &cut, &EditDefaults::default()),
+            Err(EditError::NotEnoughPoints { needed: 2, got: 1 }));
+        assert_eq!(map.linedefs.len(), 4);
+        assert_eq!(map.vertices.len(), 4);
+    }
+}
@@ -0,0 +1,403 @@
+//! # Exporting
+//!
+//! Converts a map to 3D model formats which other programs can open. The
+//! geometry is split up into one mesh for each sector (its floor and
+//! ceiling) and one mesh for each line (its walls), so that the sector or
+//! line which any part of the model came from can be found. Floors and
+//! ceilings which show the sky are put in one separate mesh, which uses the
+//! sky texture as its material.
+//!
+//! `stitch_portals` can add copies of the geometry which can be seen through
+//! portals, moved to where the portals show it.
+use std::collections::{BTreeMap, BTreeSet};
+use glam::{Affine3A, Vec2, Vec3};
+use parsers::udmf::input::UDMFMap;
+use crate::attributes::VertexAttributes;
+use crate::buffer::{self, Blend};
+use crate::floor3d::{Floor3D, build_3d_floors};
+use crate::level::{self, LineSide};
+use crate::plane::SectorPlanes;
+use crate::polyobject;
+use crate::portal::PortalGraph;
+use crate::sectorpolygonbuilder as spb;
+use crate::sky;
+use crate::wall::{LineQuads, WallOptions};
+
+#[cfg(feature = "gltf")]
+pub mod gltf;
+pub mod obj;
+pub mod stl;
+
+/// Convert a position or direction from Doom's coordinates, where Z is up,
+/// to coordinates where Y is up, and the map's Y axis becomes -Z
+pub(crate) fn y_up(vector: Vec3) -> [f32; 3] {
+    [vector.x, vector.z, -vector.y]
+}
+
+/// A vertex of an exported mesh
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ExportVertex {
+    pub position: Vec3,
+    /// The direction the surface faces
+    pub normal: Vec3,
+    /// The colour of the vertex, from the light level and colour of the
+    /// sector
+    pub colour: Vec3,
+    pub uv: Vec2,
+}
+
+/// Some triangles which use the same material
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Primitive {
+    /// The name of the texture or flat
+    pub material: String,
+    /// How the triangles are blended with what is behind them
+    pub blend: Blend,
+    pub vertices: Vec<ExportVertex>,
+    /// The indices of the vertices of each triangle. Unlike the vertex
+    /// buffers, the triangles go counterclockwise when seen from the side
+    /// they face, which is what most 3D model formats use.
+    pub indices: Vec<u32>,
+}
+
+impl Primitive {
+    fn add<V: VertexAttributes>(&mut self, vertices: &[V], indices: &[u32], normal: Vec3) {
+        let first = self.vertices.len() as u32;
+        self.vertices.extend(vertices.iter().map(|vertex| ExportVertex {
+            position: vertex.position(),
+            normal,
+            colour: vertex.colour(),
+            uv: vertex.uv(),
+        }));
+        self.indices.extend(indices.chunks_exact(3)
+            .flat_map(|triangle| [triangle[0], triangle[2], triangle[1]])
+            .map(|index| index + first));
+    }
+
+    /// Get the three vertices of each triangle
+    pub fn triangles(&self) -> impl Iterator<Item = [&ExportVertex; 3]> + '_ {
+        self.indices.chunks_exact(3).map(|triangle| {
+            [0, 1, 2].map(|corner| &self.vertices[triangle[corner] as usize])
+        })
+    }
+}
+
+/// What part of the map a mesh is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MeshSource {
+    /// The floor and ceiling of the sector with the given index
+    Sector(usize),
+    /// The walls of the line with the given index
+    Line(usize),
+    /// The floors and ceilings which show the sky
+    Sky,
+}
+
+/// The geometry of a sector or line, split into primitives by material
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mesh {
+    pub source: MeshSource,
+    pub primitives: Vec<Primitive>,
+    /// If the mesh is a copy of the geometry seen through a portal, the
+    /// index of the portal in the `PortalGraph`. See `stitch_portals`.
+    pub portal: Option<usize>,
+}
+
+impl Mesh {
+    /// The name of the mesh, like "sector 12", "line 34" or "sky". Copies
+    /// seen through portals are named like "sector 12 via portal 3".
+    pub fn name(&self) -> String {
+        let name = match self.source {
+            MeshSource::Sector(sector) => format!("sector {sector}"),
+            MeshSource::Line(line) => format!("line {line}"),
+            MeshSource::Sky => String::from("sky"),
+        };
+        match self.portal {
+            Some(portal) => format!("{name} via portal {portal}"),
+            None => name,
+        }
+    }
+
+    /// Get a copy of the mesh, moved and rotated by the transform
+    pub fn transformed(&self, transform: Affine3A) -> Mesh {
+        let primitives = self.primitives.iter().map(|primitive| Primitive {
+            vertices: primitive.vertices.iter().map(|vertex| ExportVertex {
+                position: transform.transform_point3(vertex.position),
+                normal: transform.transform_vector3(vertex.normal).normalize_or_zero(),
+                ..*vertex
+            }).collect(),
+            ..primitive.clone()
+        }).collect();
+        Mesh { primitives, ..self.clone() }
+    }
+}
+
+/// Collects primitives, grouping them by how they are blended, and by
+/// material
+#[derive(Default)]
+struct MeshBuilder(BTreeMap<(Blend, String), Primitive>);
+
+impl MeshBuilder {
+    fn add<V: VertexAttributes>(
+        &mut self,
+        material: String,
+        vertices: &[V],
+        indices: &[u32],
+        normal: Vec3
+    ) {
+        self.add_blended(material, Blend::Opaque, vertices, indices, normal);
+    }
+
+    fn add_blended<V: VertexAttributes>(
+        &mut self,
+        material: String,
+        blend: Blend,
+        vertices: &[V],
+        indices: &[u32],
+        normal: Vec3
+    ) {
+        self.0.entry((blend, material.clone()))
+            .or_insert_with(|| Primitive { material, blend, ..Default::default() })
+            .add(vertices, indices, normal);
+    }
+
+    fn build(self, source: MeshSource) -> Option<Mesh> {
+        let primitives: Vec<Primitive> = self.0.into_values()
+            .filter(|primitive| !primitive.indices.is_empty()).collect();
+        (!primitives.is_empty()).then_some(Mesh { source, primitives, portal: None })
+    }
+}
+
+/// Get the direction a wall on the given side of a line faces, which is
+/// towards that side of the line
+fn wall_normal(map: &UDMFMap, line: usize, side: LineSide) -> Vec3 {
+    let segment = level::side_segment(map, line, side);
+    let along = segment.1 - segment.0;
+    Vec2::new(along.y, -along.x).normalize_or_zero().extend(0.)
+}
+
+/// Build the meshes for the floors and ceilings of every sector, and the
+/// walls of every line. The tops and bottoms of 3D floors are put in the
+/// meshes of their target sectors, and their sides are put in the meshes of
+/// the lines they are along, in primitives of their own if the 3D floors
+/// are translucent. Sectors and lines with no geometry don't get a
+/// mesh. Floors and ceilings which show the sky are put in the sky mesh,
+/// which comes last, if there is any sky. The walls of polyobjects are moved
+/// to the polyobjects' start spots.
+///
+/// `planes` are the floor and ceiling planes of each sector in the map, as
+/// returned by `level::sector_planes`, and `texture_size` gets the size of
+/// a texture or flat, in pixels, from its name.
+pub fn map_meshes(
+    map: &UDMFMap,
+    planes: &[SectorPlanes],
+    options: &WallOptions,
+    texture_size: impl Fn(&str) -> Vec2
+) -> Vec<Mesh> {
+    let positions = level::vertex_positions(map);
+    let floors = build_3d_floors(map, &Floor3D::for_map(map), planes, &texture_size);
+    let mut sky = MeshBuilder::default();
+    let sectors = level::build_sector_polygons(map).iter().enumerate()
+        .filter_map(|(sector, result)| {
+        let triangles: Vec<usize> = spb::auto_triangulate(
+            &result.polygons, &positions).into_iter().flatten().flatten()
+            .collect();
+        let mut mesh = MeshBuilder::default();
+        [false, true].into_iter().for_each(|ceiling| {
+            let plane = if ceiling {
+                planes[sector].ceiling
+            } else {
+                planes[sector].floor
+            };
+            // Floors face up, and ceilings face down
+            let normal = plane.normal(false);
+            let normal = if (normal.z < 0.) != ceiling { -normal } else { normal };
+            if sky::is_sky(map, sector, ceiling, options) {
+                let (vertices, indices) = sky::sky_geometry(
+                    sector, ceiling, &triangles, &positions, planes,
+                    texture_size(&options.sky_texture));
+                sky.add(options.sky_texture.clone(), &vertices, &indices, normal);
+                return;
+            }
+            let (flat, vertices, indices) = buffer::flat_geometry(
+                map, sector, ceiling, &triangles, &positions, planes,
+                &texture_size);
+            mesh.add(flat, &vertices, &indices, normal);
+        });
+        floors.flats.iter().filter(|flat| flat.sector == sector).for_each(|flat| {
+            let normal = flat.plane.normal(false);
+            let normal = if (normal.z < 0.) != flat.faces_down() { -normal } else { normal };
+            mesh.add_blended(flat.material.clone(), flat.blend, &flat.vertices,
+                &flat.indices, normal);
+        });
+        mesh.build(MeshSource::Sector(sector))
+    }).collect::<Vec<Mesh>>();
+    // Polyobjects are moved to their start spots before their walls are
+    // built
+    let walls = polyobject::walls_map(map);
+    let lines = (0..map.linedefs.len()).filter_map(|line| {
+        let mut mesh = MeshBuilder::default();
+        LineQuads::for_line(&walls, line, planes, options).iter().for_each(|quad| {
+            let Some((texture, vertices, indices)) = buffer::wall_geometry(
+                &walls, quad, planes, &texture_size) else {
+                return;
+            };
+            mesh.add(texture, &vertices, &indices,
+                wall_normal(&walls, quad.line, quad.side));
+        });
+        floors.sides.iter().filter(|side| side.line == line).for_each(|side| {
+            mesh.add_blended(side.material.clone(), side.blend, &side.vertices,
+                &side.indices, wall_normal(map, side.line, side.side));
+        });
+        mesh.build(MeshSource::Line(line))
+    });
+    sectors.into_iter().chain(lines).chain(sky.build(MeshSource::Sky)).collect()
+}
+
+/// Copy the meshes which can be seen through each portal to where they are
+/// seen, so that the model looks like what the player would see. `meshes`
+/// are the meshes of the map, from `map_meshes`, and the copies are
+/// returned. Copies aren't copied again, so portals seen through other
+/// portals don't show anything.
+///
+/// The copy for each portal has the sector it shows, and the sectors which
+/// can be reached from it by going through up to `depth` two-sided lines,
+/// along with their lines. Portals which don't show another area, like
+/// horizon portals, aren't copied.
+pub fn stitch_portals(
+    map: &UDMFMap,
+    meshes: &[Mesh],
+    portals: &PortalGraph,
+    depth: usize
+) -> Vec<Mesh> {
+    // The sectors on the other side of each two-sided line of each sector
+    let mut neighbours: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); map.sectors.len()];
+    (0..map.linedefs.len()).for_each(|line| {
+        if let (Some(front), Some(back)) = (
+            level::side_sector(map, line, LineSide::Front),
+            level::side_sector(map, line, LineSide::Back)) {
+            neighbours[front].insert(back);
+            neighbours[back].insert(front);
+        }
+    });
+    portals.portals.iter().enumerate()
+        .filter(|(_, portal)| portal.shows_area())
+        .flat_map(|(index, portal)| {
+        let mut sectors = BTreeSet::from([portal.destination]);
+        let mut edge = vec![portal.destination];
+        (0..depth).for_each(|_| {
+            edge = edge.iter().flat_map(|&sector| &neighbours[sector])
+                .filter(|&&sector| sectors.insert(sector)).copied().collect();
+        });
+        let shown = |source: MeshSource| match source {
+            MeshSource::Sector(sector) => sectors.contains(&sector),
+            MeshSource::Line(line) => [LineSide::Front, LineSide::Back].into_iter()
+                .filter_map(|side| level::side_sector(map, line, side))
+                .any(|sector| sectors.contains(&sector)),
+            MeshSource::Sky => false,
+        };
+        let transform = portal.transform.inverse();
+        meshes.iter().filter(|mesh| mesh.portal.is_none() && shown(mesh.source))
+            .map(|mesh| Mesh { portal: Some(index), ..mesh.transformed(transform) })
+            .collect::<Vec<Mesh>>()
+    }).collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    pub(crate) const TEXTMAP: &str = r#"namespace = "zdoom";
+vertex { x = 0.0; y = 0.0; }
+vertex { x = 0.0; y = 64.0; }
+vertex { x = 64.0; y = 64.0; }
+vertex { x = 64.0; y = 0.0; }
+linedef { v1 = 0; v2 = 1; sidefront = 0; }
+linedef { v1 = 1; v2 = 2; sidefront = 0; }
+linedef { v1 = 2; v2 = 3; sidefront = 0; }
+linedef { v1 = 3; v2 = 0; sidefront = 0; }
+linedef { v1 = 0; v2 = 2; sidefront = 1; sideback = 1; }
+sidedef { sector = 0; texturemiddle = "STARTAN2"; }
+sidedef { sector = 0; }
+sector { texturefloor = "FLAT1"; textureceiling = "CEIL1_1";
+    heightceiling = 128; }
+"#;
+
+    pub(crate) fn test_meshes() -> Vec<Mesh> {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let planes = level::sector_planes(&map);
+        map_meshes(&map, &planes, &WallOptions::default(), |_| Vec2::splat(64.))
+    }
+
+    #[test]
+    fn meshes() {
+        let meshes = test_meshes();
+        let names: Vec<String> = meshes.iter().map(Mesh::name).collect();
+        // Line 4 is inside of the sector, and has no walls
+        assert_eq!(names, vec!["sector 0", "line 0", "line 1", "line 2", "line 3"]);
+        let materials: Vec<&str> = meshes[0].primitives.iter()
+            .map(|primitive| primitive.material.as_str()).collect();
+        assert_eq!(materials, vec!["CEIL1_1", "FLAT1"]);
+        // Counterclockwise, seen from the side the triangles face
+        meshes.iter().flat_map(|mesh| &mesh.primitives)
+            .flat_map(Primitive::triangles).for_each(|[a, b, c]| {
+            let facing = (b.position - a.position).cross(c.position - a.position);
+            assert!(facing.normalize().dot(a.normal) > 0.999);
+        });
+        // The wall on line 0 faces east
+        assert_eq!(meshes[1].primitives[0].vertices[0].normal, Vec3::X);
+
+        // A sky ceiling goes in the sky mesh instead
+        let map = UDMFMap::from_str(&TEXTMAP.replace("CEIL1_1", "F_SKY1")).unwrap();
+        let planes = level::sector_planes(&map);
+        let options = WallOptions {
+            sky_texture: String::from("SKY2"),
+            ..Default::default()
+        };
+        let meshes = map_meshes(&map, &planes, &options, |_| Vec2::splat(64.));
+        assert_eq!(meshes[0].primitives.len(), 1);
+        let sky = meshes.last().unwrap();
+        assert_eq!(sky.name(), "sky");
+        assert_eq!(sky.primitives[0].material, "SKY2");
+        assert_eq!(sky.primitives[0].vertices[0].normal, Vec3::NEG_Z);
+    }
+
+    #[test]
+    fn stitched_portals() {
+        let map = UDMFMap::from_str(crate::portal::tests::TEXTMAP).unwrap();
+        let planes = level::sector_planes(&map);
+        let options = WallOptions::default();
+        let meshes = map_meshes(&map, &planes, &options, |_| Vec2::splat(64.));
+        let portals = PortalGraph::for_map(&map, &options);
+        let line_portal = portals.line_portal(2).unwrap();
+        let copies = stitch_portals(&map, &meshes, &portals, 1);
+        // The second room and its walls, for the skybox and the line portal
+        assert_eq!(copies.len(), 10);
+        let floor = copies.iter().find(|mesh| mesh.portal == Some(line_portal) &&
+            mesh.source == MeshSource::Sector(1)).unwrap();
+        assert_eq!(floor.name(), format!("sector 1 via portal {line_portal}"));
+        // It is seen behind the portal, at the height of the first room
+        let flat = floor.primitives.iter()
+            .find(|primitive| primitive.material == "FLAT1").unwrap();
+        assert!(flat.vertices.iter().all(|vertex| {
+            (64. ..=128.).contains(&vertex.position.x) && vertex.position.z.abs() < 0.001
+        }));
+    }
+
+    #[test]
+    fn polyobject_walls() {
+        let map = UDMFMap::from_str(crate::polyobject::tests::TEXTMAP).unwrap();
+        let planes = level::sector_planes(&map);
+        let meshes = map_meshes(&map, &planes, &WallOptions::default(),
+            |_| Vec2::splat(64.));
+        let wall = meshes.iter().find(|mesh| mesh.source == MeshSource::Line(9))
+            .unwrap();
+        // The wall is in the middle of the first room, and as tall as it
+        let positions: Vec<Vec3> = wall.primitives[0].vertices.iter()
+            .map(|vertex| vertex.position).collect();
+        assert!(positions.contains(&Vec3::new(120., 120., 128.)));
+        assert!(positions.contains(&Vec3::new(136., 120., 0.)));
+        assert_eq!(wall.primitives[0].vertices[0].normal, Vec3::NEG_Y);
+    }
+}
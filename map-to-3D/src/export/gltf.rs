@@ -0,0 +1,332 @@
+//! # glTF
+//!
+//! Writes meshes as glTF 2.0 files, either as a JSON `.gltf` file with a
+//! separate `.bin` file for the geometry, or as a single binary `.glb` file.
+//!
+//! Each mesh gets a node named after the sector or line it came from, and
+//! the index of the sector or line is also stored in the node's `extras`, so
+//! that programs like Blender can show it. The sky mesh has `"sky": true` in
+//! its `extras` instead. Copies of meshes seen through portals also have
+//! the index of the portal. Each texture or flat is a
+//! material, which refers to an image using a URI. Translucent surfaces get
+//! materials of their own, with `"alphaMode": "BLEND"`. glTF can't add
+//! colours together, so additive surfaces are blended in the same way, with
+//! `"additive": true` in the material's `extras`.
+//!
+//! glTF uses Y as the up axis, whereas Doom uses Z, so the positions and
+//! normals are rotated, so that the map's Y axis becomes glTF's -Z axis.
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use glam::{Vec2, Vec3};
+use serde_json::{json, Value};
+use crate::buffer::Blend;
+use super::{Mesh, MeshSource, y_up};
+
+// Constants from the glTF specification
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const NEAREST: u32 = 9728;
+const REPEAT: u32 = 10497;
+
+const GLB_MAGIC: u32 = 0x46546C67;
+const GLB_JSON_CHUNK: u32 = 0x4E4F534A;
+const GLB_BIN_CHUNK: u32 = 0x004E4942;
+
+/// Builds the JSON document and the binary buffer
+#[derive(Default)]
+struct Document {
+    buffer: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    /// The index of each material, by name and how it is blended
+    materials: BTreeMap<(String, Blend), usize>,
+}
+
+impl Document {
+    /// Add an accessor for the given data, and return its index
+    fn accessor(
+        &mut self,
+        data: impl Iterator<Item = u8>,
+        target: u32,
+        component_type: u32,
+        count: usize,
+        value_type: &str,
+    ) -> usize {
+        let offset = self.buffer.len();
+        self.buffer.extend(data);
+        self.buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": self.buffer.len() - offset,
+            "target": target,
+        }));
+        self.accessors.push(json!({
+            "bufferView": self.buffer_views.len() - 1,
+            "componentType": component_type,
+            "count": count,
+            "type": value_type,
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn vec3_accessor(&mut self, values: &[[f32; 3]]) -> usize {
+        self.accessor(values.iter().flatten().flat_map(|v| v.to_le_bytes()),
+            ARRAY_BUFFER, FLOAT, values.len(), "VEC3")
+    }
+
+    fn material(&mut self, name: &str, blend: Blend) -> usize {
+        let count = self.materials.len();
+        *self.materials.entry((String::from(name), blend)).or_insert(count)
+    }
+
+    fn mesh(&mut self, mesh: &Mesh) -> Value {
+        let primitives: Vec<Value> = mesh.primitives.iter().map(|primitive| {
+            let positions: Vec<[f32; 3]> = primitive.vertices.iter()
+                .map(|vertex| y_up(vertex.position)).collect();
+            let normals: Vec<[f32; 3]> = primitive.vertices.iter()
+                .map(|vertex| y_up(vertex.normal)).collect();
+            let colours: Vec<[f32; 3]> = primitive.vertices.iter()
+                .map(|vertex| vertex.colour.to_array()).collect();
+            let uvs: Vec<Vec2> = primitive.vertices.iter()
+                .map(|vertex| vertex.uv).collect();
+            let position = self.vec3_accessor(&positions);
+            // glTF needs the bounds of the positions
+            let (min, max) = positions.iter().fold(
+                (Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &position| {
+                let position = Vec3::from_array(position);
+                (min.min(position), max.max(position))
+            });
+            self.accessors[position]["min"] = json!(min.to_array());
+            self.accessors[position]["max"] = json!(max.to_array());
+            let normal = self.vec3_accessor(&normals);
+            let colour = self.vec3_accessor(&colours);
+            let uv = self.accessor(
+                uvs.iter().flat_map(|uv| uv.to_array()).flat_map(f32::to_le_bytes),
+                ARRAY_BUFFER, FLOAT, uvs.len(), "VEC2");
+            let indices = self.accessor(
+                primitive.indices.iter().flat_map(|index| index.to_le_bytes()),
+                ELEMENT_ARRAY_BUFFER, UNSIGNED_INT, primitive.indices.len(),
+                "SCALAR");
+            json!({
+                "attributes": {
+                    "POSITION": position,
+                    "NORMAL": normal,
+                    "COLOR_0": colour,
+                    "TEXCOORD_0": uv,
+                },
+                "indices": indices,
+                "material": self.material(&primitive.material, primitive.blend),
+            })
+        }).collect();
+        json!({ "name": mesh.name(), "primitives": primitives })
+    }
+
+    /// Build the JSON document. If `buffer_uri` is `None`, the buffer is
+    /// expected to be in the same file, as it is in a `.glb` file.
+    fn build(
+        meshes: &[Mesh],
+        image_uri: impl Fn(&str) -> String,
+        buffer_uri: Option<&str>
+    ) -> (Value, Vec<u8>) {
+        let mut document = Document::default();
+        let gltf_meshes: Vec<Value> = meshes.iter()
+            .map(|mesh| document.mesh(mesh)).collect();
+        let nodes: Vec<Value> = meshes.iter().enumerate().map(|(index, mesh)| {
+            let mut extras = match mesh.source {
+                MeshSource::Sector(sector) => json!({ "sector": sector }),
+                MeshSource::Line(line) => json!({ "line": line }),
+                MeshSource::Sky => json!({ "sky": true }),
+            };
+            if let Some(portal) = mesh.portal {
+                extras["portal"] = json!(portal);
+            }
+            json!({ "name": mesh.name(), "mesh": index, "extras": extras })
+        }).collect();
+        let mut materials: Vec<(&(String, Blend), &usize)> = document.materials.iter().collect();
+        materials.sort_by_key(|(_, &index)| index);
+        // Materials with the same texture share its image
+        let mut textures: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut names: Vec<&str> = Vec::new();
+        materials.iter().for_each(|((name, _), _)| {
+            textures.entry(name).or_insert_with(|| {
+                names.push(name);
+                names.len() - 1
+            });
+        });
+        let images: Vec<Value> = names.iter()
+            .map(|name| json!({ "uri": image_uri(name) })).collect();
+        let gltf_textures: Vec<Value> = (0..names.len())
+            .map(|index| json!({ "source": index, "sampler": 0 })).collect();
+        let gltf_materials: Vec<Value> = materials.iter()
+            .map(|((name, blend), _)| {
+                let mut material = json!({
+                    "name": name,
+                    "pbrMetallicRoughness": {
+                        "baseColorTexture": { "index": textures[name.as_str()] },
+                        "metallicFactor": 0.,
+                        "roughnessFactor": 1.,
+                    },
+                });
+                if *blend != Blend::Opaque {
+                    material["alphaMode"] = json!("BLEND");
+                    material["pbrMetallicRoughness"]["baseColorFactor"] =
+                        json!([1., 1., 1., blend.alpha()]);
+                }
+                if let Blend::Additive(_) = blend {
+                    material["extras"] = json!({ "additive": true });
+                }
+                material
+            }).collect();
+        let mut buffer = json!({ "byteLength": document.buffer.len() });
+        if let Some(uri) = buffer_uri {
+            buffer["uri"] = json!(uri);
+        }
+        let gltf = json!({
+            "asset": { "version": "2.0", "generator": "map-to-3D" },
+            "scene": 0,
+            "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+            "nodes": nodes,
+            "meshes": gltf_meshes,
+            "materials": gltf_materials,
+            "textures": gltf_textures,
+            "images": images,
+            "samplers": [{
+                "magFilter": NEAREST,
+                "minFilter": NEAREST,
+                "wrapS": REPEAT,
+                "wrapT": REPEAT,
+            }],
+            "buffers": [buffer],
+            "bufferViews": document.buffer_views,
+            "accessors": document.accessors,
+        });
+        (gltf, document.buffer)
+    }
+}
+
+/// Write the meshes as a `.gltf` file to `gltf`, and the geometry to `bin`.
+/// `bin_uri` is the URI of the `.bin` file, relative to the `.gltf` file, and
+/// `image_uri` gets the URI of the image for a texture or flat from its
+/// name.
+pub fn write_gltf(
+    meshes: &[Mesh],
+    image_uri: impl Fn(&str) -> String,
+    bin_uri: &str,
+    mut gltf: impl Write,
+    mut bin: impl Write
+) -> io::Result<()> {
+    let (json, buffer) = Document::build(meshes, image_uri, Some(bin_uri));
+    serde_json::to_writer(&mut gltf, &json)?;
+    bin.write_all(&buffer)
+}
+
+/// Write the meshes as a binary `.glb` file. `image_uri` gets the URI of the
+/// image for a texture or flat from its name.
+pub fn write_glb(
+    meshes: &[Mesh],
+    image_uri: impl Fn(&str) -> String,
+    mut glb: impl Write
+) -> io::Result<()> {
+    let (json, mut buffer) = Document::build(meshes, image_uri, None);
+    let mut json = serde_json::to_vec(&json)?;
+    // Chunks have to be padded to a multiple of 4 bytes
+    json.resize(json.len().next_multiple_of(4), b' ');
+    buffer.resize(buffer.len().next_multiple_of(4), 0);
+    let length = 12 + 8 + json.len() + 8 + buffer.len();
+    [GLB_MAGIC, 2, length as u32, json.len() as u32, GLB_JSON_CHUNK]
+        .iter().try_for_each(|value| glb.write_all(&value.to_le_bytes()))?;
+    glb.write_all(&json)?;
+    [buffer.len() as u32, GLB_BIN_CHUNK]
+        .iter().try_for_each(|value| glb.write_all(&value.to_le_bytes()))?;
+    glb.write_all(&buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use parsers::udmf::input::UDMFMap;
+    use crate::export::map_meshes;
+    use crate::export::tests::test_meshes;
+    use crate::floor3d;
+    use crate::level;
+    use crate::wall::WallOptions;
+
+    fn image_uri(name: &str) -> String {
+        format!("textures/{name}.png")
+    }
+
+    #[test]
+    fn gltf_document() {
+        let meshes = test_meshes();
+        let (mut gltf, mut bin) = (Vec::new(), Vec::new());
+        write_gltf(&meshes, image_uri, "map.bin", &mut gltf, &mut bin).unwrap();
+        let gltf: Value = serde_json::from_slice(&gltf).unwrap();
+        assert_eq!(gltf["buffers"][0]["uri"], "map.bin");
+        assert_eq!(gltf["buffers"][0]["byteLength"], bin.len());
+        assert_eq!(gltf["nodes"][0]["name"], "sector 0");
+        assert_eq!(gltf["nodes"][0]["extras"]["sector"], 0);
+        assert_eq!(gltf["nodes"][1]["extras"]["line"], 0);
+        assert_eq!(gltf["images"].as_array().unwrap().len(), 3);
+        let floor = &gltf["meshes"][0]["primitives"][1];
+        let material = floor["material"].as_u64().unwrap() as usize;
+        assert_eq!(gltf["materials"][material]["name"], "FLAT1");
+        let texture = &gltf["materials"][material]["pbrMetallicRoughness"]
+            ["baseColorTexture"]["index"];
+        assert_eq!(gltf["images"][texture.as_u64().unwrap() as usize]["uri"],
+            "textures/FLAT1.png");
+        // The floor is at Y = 0, and goes from Z = -64 to Z = 0
+        let position = floor["attributes"]["POSITION"].as_u64().unwrap() as usize;
+        assert_eq!(gltf["accessors"][position]["min"], json!([0., 0., -64.]));
+        assert_eq!(gltf["accessors"][position]["max"], json!([64., 0., 0.]));
+    }
+
+    #[test]
+    fn translucent_materials() {
+        // The top of the translucent 3D floor uses the same flat as the
+        // floors of the room
+        let map = UDMFMap::from_str(&floor3d::tests::TEXTMAP.replace(
+            r#"textureceiling = "TOP""#, r#"textureceiling = "FLAT1""#)).unwrap();
+        let planes = level::sector_planes(&map);
+        let meshes = map_meshes(&map, &planes, &WallOptions::default(), |_| Vec2::splat(64.));
+        let (mut gltf, mut bin) = (Vec::new(), Vec::new());
+        write_gltf(&meshes, image_uri, "map.bin", &mut gltf, &mut bin).unwrap();
+        let gltf: Value = serde_json::from_slice(&gltf).unwrap();
+        let flats: Vec<&Value> = gltf["materials"].as_array().unwrap().iter()
+            .filter(|material| material["name"] == "FLAT1").collect();
+        assert_eq!(flats.len(), 2);
+        assert!(flats[0].get("alphaMode").is_none());
+        assert_eq!(flats[1]["alphaMode"], "BLEND");
+        let factor = &flats[1]["pbrMetallicRoughness"]["baseColorFactor"];
+        assert_eq!(factor[3].as_f64().unwrap() as f32, 128. / 255.);
+        // Both of them use the same image
+        let texture = |material: &Value| material["pbrMetallicRoughness"]
+            ["baseColorTexture"]["index"].clone();
+        assert_eq!(texture(flats[0]), texture(flats[1]));
+        let images: Vec<&Value> = gltf["images"].as_array().unwrap().iter()
+            .filter(|image| image["uri"] == "textures/FLAT1.png").collect();
+        assert_eq!(images.len(), 1);
+    }
+
+    #[test]
+    fn glb_layout() {
+        let meshes = test_meshes();
+        let mut glb = Vec::new();
+        write_glb(&meshes, image_uri, &mut glb).unwrap();
+        let word = |offset: usize| u32::from_le_bytes(
+            glb[offset..offset + 4].try_into().unwrap()) as usize;
+        assert_eq!(word(0), GLB_MAGIC as usize);
+        assert_eq!(word(4), 2);
+        assert_eq!(word(8), glb.len());
+        let json_length = word(12);
+        assert_eq!(json_length % 4, 0);
+        assert_eq!(word(16), GLB_JSON_CHUNK as usize);
+        let gltf: Value = serde_json::from_slice(&glb[20..20 + json_length]).unwrap();
+        assert!(gltf["buffers"][0].get("uri").is_none());
+        let bin_length = word(20 + json_length);
+        assert_eq!(word(24 + json_length), GLB_BIN_CHUNK as usize);
+        assert_eq!(28 + json_length + bin_length, glb.len());
+    }
+}
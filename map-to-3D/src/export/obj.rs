@@ -0,0 +1,100 @@
+//! # Wavefront OBJ
+//!
+//! Writes meshes as a Wavefront `.obj` file, with the materials in a
+//! separate `.mtl` file. Each mesh is a group named after the sector or line
+//! it came from, and each texture or flat is a material.
+//!
+//! Like glTF, OBJ files usually use Y as the up axis, so the positions and
+//! normals are rotated in the same way as they are for glTF files.
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use super::{Mesh, y_up};
+
+/// Write the meshes as a `.obj` file. `mtl_name` is the name of the `.mtl`
+/// file with the materials, relative to the `.obj` file.
+pub fn write_obj(meshes: &[Mesh], mtl_name: &str, mut obj: impl Write) -> io::Result<()> {
+    writeln!(obj, "mtllib {mtl_name}")?;
+    // OBJ indices start at 1, and are shared by the whole file
+    let mut first = 1;
+    meshes.iter().try_for_each(|mesh| {
+        writeln!(obj, "g {}", mesh.name().replace(' ', "_"))?;
+        mesh.primitives.iter().try_for_each(|primitive| {
+            primitive.vertices.iter().try_for_each(|vertex| {
+                let [x, y, z] = y_up(vertex.position);
+                writeln!(obj, "v {x} {y} {z}")?;
+                // The V axis of OBJ texture coordinates goes up
+                writeln!(obj, "vt {} {}", vertex.uv.x, 1. - vertex.uv.y)?;
+                let [x, y, z] = y_up(vertex.normal);
+                writeln!(obj, "vn {x} {y} {z}")
+            })?;
+            writeln!(obj, "usemtl {}", primitive.material)?;
+            primitive.indices.chunks_exact(3).try_for_each(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|corner| triangle[corner] + first);
+                writeln!(obj, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")
+            })?;
+            first += primitive.vertices.len() as u32;
+            Ok(())
+        })
+    })
+}
+
+/// Write the materials used by the meshes as a `.mtl` file. `image_uri` gets
+/// the path of the image for a texture or flat from its name.
+pub fn write_mtl(
+    meshes: &[Mesh],
+    image_uri: impl Fn(&str) -> String,
+    mut mtl: impl Write
+) -> io::Result<()> {
+    let materials: BTreeSet<&str> = meshes.iter()
+        .flat_map(|mesh| &mesh.primitives)
+        .map(|primitive| primitive.material.as_str()).collect();
+    materials.into_iter().try_for_each(|material| {
+        writeln!(mtl, "newmtl {material}")?;
+        writeln!(mtl, "Kd 1 1 1")?;
+        writeln!(mtl, "map_Kd {}", image_uri(material))?;
+        writeln!(mtl)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::tests::test_meshes;
+
+    #[test]
+    fn obj_and_mtl() {
+        let meshes = test_meshes();
+        let mut obj = Vec::new();
+        write_obj(&meshes, "map.mtl", &mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let lines: Vec<&str> = obj.lines().collect();
+        assert_eq!(lines[0], "mtllib map.mtl");
+        assert_eq!(lines[1], "g sector_0");
+        let count = |prefix: &str| lines.iter()
+            .filter(|line| line.starts_with(prefix)).count();
+        let vertices: usize = meshes.iter().flat_map(|mesh| &mesh.primitives)
+            .map(|primitive| primitive.vertices.len()).sum();
+        assert_eq!(count("v "), vertices);
+        assert_eq!(count("vn "), vertices);
+        assert_eq!(count("g "), meshes.len());
+        // Every face refers to a vertex which exists
+        let highest = lines.iter().filter(|line| line.starts_with("f "))
+            .flat_map(|line| line.split(' ').skip(1))
+            .map(|corner| corner.split('/').next().unwrap().parse::<usize>().unwrap())
+            .max().unwrap();
+        assert_eq!(highest, vertices);
+        // The floor faces up
+        let floor = lines.iter().position(|line| *line == "usemtl FLAT1").unwrap();
+        let normals: Vec<Vec<f32>> = lines[..floor].iter()
+            .filter_map(|line| line.strip_prefix("vn "))
+            .map(|normal| normal.split(' ').map(|co| co.parse().unwrap()).collect())
+            .collect();
+        assert!(normals.iter().any(|normal| *normal == [0., 1., 0.]), "{normals:?}");
+
+        let mut mtl = Vec::new();
+        write_mtl(&meshes, |name| format!("textures/{name}.png"), &mut mtl).unwrap();
+        let mtl = String::from_utf8(mtl).unwrap();
+        assert_eq!(mtl.matches("newmtl").count(), 3);
+        assert!(mtl.contains("newmtl FLAT1\nKd 1 1 1\nmap_Kd textures/FLAT1.png\n"));
+    }
+}
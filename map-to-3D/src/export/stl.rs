@@ -0,0 +1,62 @@
+//! # STL
+//!
+//! Writes triangles as a binary STL file, e.g. for 3D printing a map. STL
+//! files don't have textures or colours, so only the shape of the map is
+//! kept. Unlike the other formats, Z is up, like it is in Doom.
+use std::io::{self, Write};
+use glam::Vec3;
+use super::Mesh;
+
+/// Write the triangles as a binary STL file. The triangles should go
+/// counterclockwise when seen from the side they face.
+pub fn write_stl(triangles: &[[Vec3; 3]], mut stl: impl Write) -> io::Result<()> {
+    let mut header = [0u8; 80];
+    let name = b"map-to-3D";
+    header[..name.len()].copy_from_slice(name);
+    stl.write_all(&header)?;
+    stl.write_all(&(triangles.len() as u32).to_le_bytes())?;
+    triangles.iter().try_for_each(|&[a, b, c]| {
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        [normal, a, b, c].iter().flat_map(|vector| vector.to_array())
+            .try_for_each(|value| stl.write_all(&value.to_le_bytes()))?;
+        // The "attribute byte count", which is usually 0
+        stl.write_all(&0u16.to_le_bytes())
+    })
+}
+
+/// Write all of the triangles of the meshes as a binary STL file
+pub fn write_meshes_stl(meshes: &[Mesh], stl: impl Write) -> io::Result<()> {
+    let triangles: Vec<[Vec3; 3]> = meshes.iter()
+        .flat_map(|mesh| &mesh.primitives)
+        .flat_map(|primitive| primitive.triangles())
+        .map(|triangle| triangle.map(|vertex| vertex.position))
+        .collect();
+    write_stl(&triangles, stl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::tests::test_meshes;
+
+    #[test]
+    fn binary_stl() {
+        let triangle = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        let mut stl = Vec::new();
+        write_stl(&[triangle], &mut stl).unwrap();
+        assert_eq!(stl.len(), 80 + 4 + 50);
+        assert_eq!(&stl[80..84], &1u32.to_le_bytes());
+        let float = |offset: usize| f32::from_le_bytes(
+            stl[offset..offset + 4].try_into().unwrap());
+        // The normal points up, since the triangle is counterclockwise
+        assert_eq!([float(84), float(88), float(92)], [0., 0., 1.]);
+        assert_eq!([float(108), float(112), float(116)], [1., 0., 0.]);
+
+        let meshes = test_meshes();
+        let mut stl = Vec::new();
+        write_meshes_stl(&meshes, &mut stl).unwrap();
+        let triangles: usize = meshes.iter().flat_map(|mesh| &mesh.primitives)
+            .map(|primitive| primitive.indices.len() / 3).sum();
+        assert_eq!(stl.len(), 84 + triangles * 50);
+    }
+}
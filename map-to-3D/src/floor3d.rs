@@ -0,0 +1,567 @@
+//! # 3D floors
+//!
+//! Builds the geometry of ZDoom's 3D floors, which are made with the
+//! `Sector_Set3DFloor` (160) special on a control line. The floor and
+//! ceiling of the sector on the front of the control line (the control
+//! sector) are the bottom and top of the 3D floor, which is put inside of
+//! every sector with the tag given by the special (the target sectors).
+use glam::{Vec2, Vec3};
+use parsers::udmf::input::UDMFMap;
+use crate::attributes::{FlatVertex, WallVertex};
+use crate::buffer::{self, Blend};
+use crate::level::{self, LineSide, WallPart};
+use crate::light;
+use crate::plane::{Plane, SectorPlanes};
+use crate::sectorpolygonbuilder as spb;
+use crate::segment::Segment;
+use crate::uv::{FlatTexturing, WallTexturing};
+use crate::wall::{self, Height, LineQuad};
+
+/// `Sector_Set3DFloor`
+pub const SECTOR_SET_3D_FLOOR: u32 = 160;
+
+/// What kind of 3D floor it is, from the first two bits of the second
+/// argument of `Sector_Set3DFloor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloorKind {
+    /// A solid 3D floor, where the floor of the control sector is the top of
+    /// the 3D floor, and the ceiling is the bottom
+    Vavoom,
+    /// A solid 3D floor
+    Solid,
+    /// A 3D floor which can be swum in, like water
+    Swimmable,
+    /// A 3D floor which things can go through
+    NonSolid,
+}
+
+/// Which texture is used for the sides of a 3D floor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SideTexture {
+    /// The middle texture of the front side of the control line
+    Control,
+    /// The upper texture of the side of the line the side of the 3D floor is
+    /// on
+    Upper,
+    /// The lower texture of the side of the line the side of the 3D floor is
+    /// on
+    Lower,
+}
+
+/// A 3D floor, from a line with the `Sector_Set3DFloor` special
+#[derive(Debug, Clone, PartialEq)]
+pub struct Floor3D {
+    /// The index of the control line
+    pub line: usize,
+    /// The index of the control sector
+    pub control: usize,
+    /// The indices of the sectors the 3D floor is in
+    pub targets: Vec<usize>,
+    pub kind: FloorKind,
+    /// Whether the 3D floor can also be seen from the inside
+    pub render_inside: bool,
+    /// Whether the 3D floor is thin, with its bottom on the same plane as its
+    /// top, rather than at the bottom of the control sector
+    pub ignore_bottom: bool,
+    /// Whether the 3D floor changes the light below it
+    pub light_below: bool,
+    pub side_texture: SideTexture,
+    /// How opaque the 3D floor is, from 0 to 1
+    pub alpha: f32,
+    /// Whether the 3D floor is drawn with additive translucency
+    pub additive: bool,
+}
+
+impl Floor3D {
+    /// Get the 3D floor made by the given line, if it has the
+    /// `Sector_Set3DFloor` special and a control sector.
+    pub fn new(map: &UDMFMap, line: usize) -> Option<Floor3D> {
+        let linedef = &map.linedefs[line];
+        if level::line_special(linedef) != SECTOR_SET_3D_FLOOR {
+            return None;
+        }
+        let control = level::side_sector(map, line, LineSide::Front)?;
+        let [tag, kind, flags, alpha, high] = level::prop_args(&linedef.props);
+        // Unless bit 3 of the type is set, the fifth argument is the high
+        // byte of the tag, rather than a line ID
+        let tag = if kind & 8 == 0 { tag + (high << 8) } else { tag };
+        let targets = map.sectors.iter().enumerate()
+            .filter(|(index, sector)| *index != control && sector.id as i32 == tag)
+            .map(|(index, _)| index).collect();
+        Some(Floor3D {
+            line,
+            control,
+            targets,
+            kind: match kind & 3 {
+                0 => FloorKind::Vavoom,
+                1 => FloorKind::Solid,
+                2 => FloorKind::Swimmable,
+                _ => FloorKind::NonSolid,
+            },
+            render_inside: kind & 4 != 0,
+            ignore_bottom: flags & 8 != 0,
+            light_below: flags & 1 == 0,
+            side_texture: if flags & 16 != 0 {
+                SideTexture::Upper
+            } else if flags & 32 != 0 {
+                SideTexture::Lower
+            } else {
+                SideTexture::Control
+            },
+            alpha: alpha.clamp(0, 255) as f32 / 255.,
+            additive: flags & 64 != 0,
+        })
+    }
+
+    /// Get every 3D floor in the map
+    pub fn for_map(map: &UDMFMap) -> Vec<Floor3D> {
+        (0..map.linedefs.len()).filter_map(|line| Floor3D::new(map, line))
+            .collect()
+    }
+
+    /// Whether the top and bottom of the 3D floor are the floor and ceiling
+    /// of the control sector, rather than the other way around
+    fn inverted(&self) -> bool {
+        self.kind == FloorKind::Vavoom
+    }
+
+    /// Get the plane of the top of the 3D floor
+    pub fn top(&self, planes: &[SectorPlanes]) -> Plane {
+        if self.inverted() {
+            planes[self.control].floor
+        } else {
+            planes[self.control].ceiling
+        }
+    }
+
+    /// Get the plane of the bottom of the 3D floor
+    pub fn bottom(&self, planes: &[SectorPlanes]) -> Plane {
+        if self.ignore_bottom {
+            self.top(planes)
+        } else if self.inverted() {
+            planes[self.control].ceiling
+        } else {
+            planes[self.control].floor
+        }
+    }
+
+    /// Get the height of the top of the 3D floor, which the textures on the
+    /// sides are pegged to
+    fn top_height(&self, map: &UDMFMap) -> f32 {
+        let control = &map.sectors[self.control];
+        if self.inverted() {
+            control.heightfloor as f32
+        } else {
+            control.heightceiling as f32
+        }
+    }
+
+    /// Get the flat on the top or bottom of the 3D floor, and whether it is
+    /// the control sector's ceiling flat
+    fn flat<'a>(&self, map: &'a UDMFMap, top: bool) -> (&'a str, bool) {
+        let control = &map.sectors[self.control];
+        if top != self.inverted() {
+            (&control.textureceiling, true)
+        } else {
+            (&control.texturefloor, false)
+        }
+    }
+
+    /// Is the 3D floor see-through?
+    pub fn is_translucent(&self) -> bool {
+        self.alpha < 1. || self.additive
+    }
+
+    /// How the tops, bottoms and sides of the 3D floor are blended with what
+    /// is behind them
+    pub fn blend(&self) -> Blend {
+        let alpha = (self.alpha * 255.).round() as u8;
+        if self.additive {
+            Blend::Additive(alpha)
+        } else if self.is_translucent() {
+            Blend::Translucent(alpha)
+        } else {
+            Blend::Opaque
+        }
+    }
+}
+
+/// The top or bottom of a 3D floor in one of its target sectors
+#[derive(Debug, Clone, PartialEq)]
+pub struct Floor3DFlat {
+    /// The index of the 3D floor, in the list given to `build_3d_floors`
+    pub floor: usize,
+    /// The index of the target sector
+    pub sector: usize,
+    /// Whether this is the top of the 3D floor
+    pub top: bool,
+    /// Whether this faces into the 3D floor
+    pub inside: bool,
+    /// The plane the surface is on
+    pub plane: Plane,
+    /// The name of the flat
+    pub material: String,
+    /// How the surface is blended with what is behind it
+    pub blend: Blend,
+    pub vertices: Vec<FlatVertex>,
+    /// The triangles, which go clockwise when seen from the side they face
+    pub indices: Vec<u32>,
+}
+
+impl Floor3DFlat {
+    /// Does the surface face down?
+    pub fn faces_down(&self) -> bool {
+        self.top == self.inside
+    }
+}
+
+/// One side of a 3D floor, along one side of a line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Floor3DSide {
+    /// The index of the 3D floor, in the list given to `build_3d_floors`
+    pub floor: usize,
+    /// The index of the line
+    pub line: usize,
+    /// Which side of the line the wall faces
+    pub side: LineSide,
+    /// Whether this faces into the 3D floor
+    pub inside: bool,
+    /// The name of the texture
+    pub material: String,
+    /// How the wall is blended with what is behind it
+    pub blend: Blend,
+    pub vertices: Vec<WallVertex>,
+    /// The triangles, which go clockwise when seen from the side they face
+    pub indices: Vec<u32>,
+}
+
+/// The geometry of some 3D floors
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Floor3DGeometry {
+    pub flats: Vec<Floor3DFlat>,
+    pub sides: Vec<Floor3DSide>,
+}
+
+/// Build the tops, bottoms and sides of the 3D floors. `planes` are the
+/// floor and ceiling planes of each sector in the map, as returned by
+/// `level::sector_planes`, and `texture_size` gets the size of a texture or
+/// flat, in pixels, from its name.
+///
+/// The tops of 3D floors are lit like the floors of their target sectors,
+/// and the bottoms are lit like the ceilings of their control sectors,
+/// unless the 3D floor doesn't change the light below it. The sides are lit
+/// like walls in the target sectors.
+pub fn build_3d_floors(
+    map: &UDMFMap,
+    floors: &[Floor3D],
+    planes: &[SectorPlanes],
+    texture_size: impl Fn(&str) -> Vec2
+) -> Floor3DGeometry {
+    let mut geometry = Floor3DGeometry::default();
+    let positions = level::vertex_positions(map);
+    let edges = level::sector_edges(map);
+    floors.iter().enumerate().for_each(|(index, floor)| {
+        floor.targets.iter().for_each(|&target| {
+            let polygons = spb::build_polygons(&edges[target], &positions);
+            let triangles: Vec<usize> = spb::auto_triangulate(&polygons, &positions)
+                .into_iter().flatten().flatten().collect();
+            flats(map, floor, index, target, &triangles, &positions, planes,
+                &texture_size, &mut geometry.flats);
+        });
+        sides(map, floor, index, planes, &texture_size, &mut geometry.sides);
+    });
+    geometry
+}
+
+#[allow(clippy::too_many_arguments)]
+fn flats(
+    map: &UDMFMap,
+    floor: &Floor3D,
+    index: usize,
+    target: usize,
+    triangles: &[usize],
+    positions: &[Vec2],
+    planes: &[SectorPlanes],
+    texture_size: impl Fn(&str) -> Vec2,
+    flats: &mut Vec<Floor3DFlat>
+) {
+    let target_sector = &map.sectors[target];
+    let fog = light::sector_fog(target_sector);
+    [true, false].into_iter().for_each(|top| {
+        let plane = if top {
+            floor.top(planes)
+        } else {
+            floor.bottom(planes)
+        };
+        let (flat, ceiling) = floor.flat(map, top);
+        let texturing = FlatTexturing::new(
+            &map.sectors[floor.control], ceiling, texture_size(flat));
+        let colour = if top {
+            light::flat_colour(target_sector, false)
+        } else if floor.light_below {
+            light::flat_colour(&map.sectors[floor.control], true)
+        } else {
+            light::flat_colour(target_sector, true)
+        };
+        let template = FlatVertex { colour, fog, sector: target, ..Default::default() };
+        let insides = if floor.render_inside { &[false, true][..] } else { &[false] };
+        insides.iter().for_each(|&inside| {
+            let down = top == inside;
+            let (vertices, indices) = buffer::flat_vertices(
+                template, triangles, positions, plane, down, &texturing);
+            flats.push(Floor3DFlat {
+                floor: index,
+                sector: target,
+                top,
+                inside,
+                plane,
+                material: String::from(flat),
+                blend: floor.blend(),
+                vertices,
+                indices,
+            });
+        });
+    });
+}
+
+fn sides(
+    map: &UDMFMap,
+    floor: &Floor3D,
+    index: usize,
+    planes: &[SectorPlanes],
+    texture_size: impl Fn(&str) -> Vec2,
+    sides: &mut Vec<Floor3DSide>
+) {
+    let control = level::line_sidedef(&map.linedefs[floor.line], LineSide::Front)
+        .and_then(|sidedef| map.sidedefs.get(sidedef));
+    let Some(control) = control else { return; };
+    let top_plane = floor.top(planes);
+    (0..map.linedefs.len()).for_each(|line| {
+        [LineSide::Front, LineSide::Back].into_iter().for_each(|side| {
+            // The side of the 3D floor is seen from outside of the target
+            // sector, through the gap between the sectors on either side of
+            // the line
+            let (Some(outside), Some(target)) = (
+                level::side_sector(map, line, side),
+                level::side_sector(map, line, side.opposite())
+            ) else {
+                return;
+            };
+            if !floor.targets.contains(&target) || floor.targets.contains(&outside) {
+                return;
+            }
+            let top = Height::Lowest(vec![top_plane, planes[outside].ceiling,
+                planes[target].ceiling]);
+            let bottom = Height::Highest(vec![floor.bottom(planes),
+                planes[outside].floor, planes[target].floor]);
+            let texture = match floor.side_texture {
+                SideTexture::Control => &control.texturemiddle,
+                SideTexture::Upper | SideTexture::Lower => {
+                    let sidedef = level::line_sidedef(&map.linedefs[line], side)
+                        .and_then(|sidedef| map.sidedefs.get(sidedef));
+                    let Some(sidedef) = sidedef else { return; };
+                    if floor.side_texture == SideTexture::Upper {
+                        &sidedef.texturetop
+                    } else {
+                        &sidedef.texturebottom
+                    }
+                },
+            };
+            // The textures are pegged to the top of the 3D floor, and use the
+            // offsets of the control line
+            let texturing = WallTexturing {
+                top: floor.top_height(map),
+                offset: Vec2::new(control.offsetx as f32, control.offsety as f32),
+                scale: Vec2::ONE,
+                size: texture_size(&texture.0),
+            };
+            let fog = light::sector_fog(&map.sectors[outside]);
+            let Segment(start, end) = level::side_segment(map, line, side);
+            wall::wall_vertices(start, end, top, bottom).into_iter().for_each(|vertices| {
+                let quad = LineQuad { vertices, line, side, part: WallPart::Middle };
+                let vertices: Vec<WallVertex> = quad.vertices.iter().map(|&position| {
+                    WallVertex {
+                        position,
+                        colour: light::wall_colour(map, line, side, planes, position)
+                            .unwrap_or(Vec3::ONE),
+                        fog,
+                        uv: texturing.uv(position.truncate().distance(start), position.z),
+                        line,
+                    }
+                }).collect();
+                let indices: Vec<u32> = quad.triangles().flatten()
+                    .map(|index| index as u32).collect();
+                sides.push(Floor3DSide {
+                    floor: index,
+                    line,
+                    side,
+                    inside: false,
+                    material: texture.0.clone(),
+                    blend: floor.blend(),
+                    vertices: vertices.clone(),
+                    indices: indices.clone(),
+                });
+                if floor.render_inside {
+                    // The same wall, seen from the other side
+                    sides.push(Floor3DSide {
+                        floor: index,
+                        line,
+                        side: side.opposite(),
+                        inside: true,
+                        material: texture.0.clone(),
+                        blend: floor.blend(),
+                        vertices,
+                        indices: indices.chunks_exact(3)
+                            .flat_map(|triangle| [triangle[0], triangle[2], triangle[1]])
+                            .collect(),
+                    });
+                }
+            });
+        });
+    });
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // A 128x64 room split into two 64x64 sectors, where the right sector
+    // (tag 1) has a 3D floor from 32 to 48, controlled by a line in a
+    // separate control sector.
+    pub(crate) const TEXTMAP: &str = r#"namespace = "zdoom";
+vertex { x = 0.0; y = 0.0; }
+vertex { x = 0.0; y = 64.0; }
+vertex { x = 64.0; y = 64.0; }
+vertex { x = 64.0; y = 0.0; }
+vertex { x = 128.0; y = 64.0; }
+vertex { x = 128.0; y = 0.0; }
+vertex { x = 256.0; y = 0.0; }
+vertex { x = 256.0; y = 64.0; }
+vertex { x = 320.0; y = 64.0; }
+linedef { v1 = 0; v2 = 1; sidefront = 0; }
+linedef { v1 = 1; v2 = 2; sidefront = 0; }
+linedef { v1 = 3; v2 = 2; sidefront = 1; sideback = 0; }
+linedef { v1 = 3; v2 = 0; sidefront = 0; }
+linedef { v1 = 2; v2 = 4; sidefront = 1; }
+linedef { v1 = 4; v2 = 5; sidefront = 1; }
+linedef { v1 = 5; v2 = 3; sidefront = 1; }
+linedef { v1 = 6; v2 = 7; sidefront = 2; special = 160; arg0 = 1;
+    arg1 = 1; arg3 = 128; }
+linedef { v1 = 7; v2 = 8; sidefront = 2; }
+linedef { v1 = 8; v2 = 6; sidefront = 2; }
+sidedef { sector = 0; texturetop = "UPPER"; }
+sidedef { sector = 1; }
+sidedef { sector = 2; texturemiddle = "SIDE"; offsety = 8; }
+sector { texturefloor = "FLAT1"; textureceiling = "CEIL1_1";
+    heightceiling = 128; }
+sector { texturefloor = "FLAT1"; textureceiling = "CEIL1_1";
+    heightceiling = 128; id = 1; }
+sector { texturefloor = "BOTTOM"; textureceiling = "TOP";
+    heightfloor = 32; heightceiling = 48; }
+"#;
+
+    #[test]
+    fn floor_properties() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let floors = Floor3D::for_map(&map);
+        assert_eq!(floors.len(), 1);
+        let floor = &floors[0];
+        assert_eq!((floor.line, floor.control), (7, 2));
+        assert_eq!(floor.targets, vec![1]);
+        assert_eq!(floor.kind, FloorKind::Solid);
+        assert_eq!(floor.side_texture, SideTexture::Control);
+        assert!(floor.is_translucent());
+        let planes = level::sector_planes(&map);
+        assert_eq!(floor.top(&planes), Plane::Flat(48.));
+        assert_eq!(floor.bottom(&planes), Plane::Flat(32.));
+    }
+
+    #[test]
+    fn solid_geometry() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let planes = level::sector_planes(&map);
+        let floors = Floor3D::for_map(&map);
+        let geometry = build_3d_floors(&map, &floors, &planes, |_| Vec2::splat(64.));
+        let flats: Vec<(&str, bool, usize)> = geometry.flats.iter()
+            .map(|flat| (flat.material.as_str(), flat.faces_down(), flat.indices.len()))
+            .collect();
+        assert_eq!(flats, vec![("TOP", false, 6), ("BOTTOM", true, 6)]);
+        assert!(geometry.flats[0].vertices.iter().all(|v| v.position.z == 48.));
+        // Only the line between the two sectors has a visible side, which
+        // faces the left sector
+        assert_eq!(geometry.sides.len(), 1);
+        let side = &geometry.sides[0];
+        assert_eq!((side.line, side.side, side.inside), (2, LineSide::Back, false));
+        assert_eq!(side.material, "SIDE");
+        assert_eq!(side.vertices[0].position, Vec3::new(64., 64., 48.));
+        assert_eq!(side.vertices[3].position, Vec3::new(64., 64., 32.));
+        // Pegged to the top, with the control line's offset
+        assert_eq!(side.vertices[0].uv, Vec2::new(0., 0.125));
+        assert_eq!(side.vertices[2].uv, Vec2::new(1., 0.375));
+    }
+
+    #[test]
+    fn ignore_bottom() {
+        let mut map = UDMFMap::from_str(TEXTMAP).unwrap();
+        map.linedefs[7].props.insert("arg2".into(), "8".into());
+        let planes = level::sector_planes(&map);
+        let floors = Floor3D::for_map(&map);
+        assert!(floors[0].ignore_bottom);
+        assert_eq!(floors[0].bottom(&planes), Plane::Flat(48.));
+        // The top and bottom are on the same plane, and there are no sides
+        let geometry = build_3d_floors(&map, &floors, &planes, |_| Vec2::splat(64.));
+        let flats: Vec<(&str, bool, Plane)> = geometry.flats.iter()
+            .map(|flat| (flat.material.as_str(), flat.faces_down(), flat.plane))
+            .collect();
+        assert_eq!(flats, vec![
+            ("TOP", false, Plane::Flat(48.)),
+            ("BOTTOM", true, Plane::Flat(48.)),
+        ]);
+        assert!(geometry.sides.is_empty());
+    }
+
+    #[test]
+    fn translucency() {
+        let mut map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let planes = level::sector_planes(&map);
+        let floors = Floor3D::for_map(&map);
+        assert_eq!(floors[0].blend(), Blend::Translucent(128));
+        let geometry = build_3d_floors(&map, &floors, &planes, |_| Vec2::splat(64.));
+        let mut builder = buffer::BufferBuilder::new();
+        builder.add_3d_floors(&geometry);
+        let draws: Vec<(String, Blend)> = builder.build().draws.into_iter()
+            .map(|draw| (draw.material, draw.blend)).collect();
+        assert_eq!(draws, vec![
+            (String::from("BOTTOM"), Blend::Translucent(128)),
+            (String::from("SIDE"), Blend::Translucent(128)),
+            (String::from("TOP"), Blend::Translucent(128)),
+        ]);
+        // Additive, and opaque
+        map.linedefs[7].props.insert("arg2".into(), "64".into());
+        assert_eq!(Floor3D::for_map(&map)[0].blend(), Blend::Additive(128));
+        map.linedefs[7].props.insert("arg2".into(), "0".into());
+        map.linedefs[7].props.insert("arg3".into(), "255".into());
+        assert_eq!(Floor3D::for_map(&map)[0].blend(), Blend::Opaque);
+    }
+
+    #[test]
+    fn swimmable_inside() {
+        let mut map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let props = &mut map.linedefs[7].props;
+        // Swimmable, rendered inside, with the upper texture on the sides
+        props.insert("arg1".into(), "6".into());
+        props.insert("arg2".into(), "16".into());
+        let planes = level::sector_planes(&map);
+        let floors = Floor3D::for_map(&map);
+        let geometry = build_3d_floors(&map, &floors, &planes, |_| Vec2::splat(64.));
+        assert_eq!(geometry.flats.len(), 4);
+        assert_eq!(geometry.flats.iter().filter(|flat| flat.faces_down()).count(), 2);
+        let sides: Vec<(LineSide, bool, &str)> = geometry.sides.iter()
+            .map(|side| (side.side, side.inside, side.material.as_str())).collect();
+        assert_eq!(sides, vec![
+            (LineSide::Back, false, "UPPER"),
+            (LineSide::Front, true, "UPPER"),
+        ]);
+    }
+}
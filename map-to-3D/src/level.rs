@@ -0,0 +1,355 @@
+//! # Level data
+//!
+//! Helpers for looking up the vertices, lines, sides and sectors of a parsed
+//! UDMF map, so that the rest of this crate doesn't have to deal with the
+//! quirks of the UDMF data (e.g. `-1` meaning "no back side").
+use glam::Vec2;
+use parsers::udmf::input::{
+    UDMFMap, UDMFLinedef, UDMFSidedef, SidedefIndex, SidedefTexture, PropMap
+};
+use crate::segment::Segment;
+use crate::edge::Edge;
+use crate::plane::{Plane, SectorPlanes};
+use crate::slope;
+use crate::sectorpolygonbuilder::{self as spb, PolygonBuildResult};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+/// Which side of a linedef something is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LineSide {
+    /// The right-hand side, when looking from the first vertex to the second
+    Front,
+    /// The left-hand side, when looking from the first vertex to the second
+    Back,
+}
+
+impl LineSide {
+    /// Get the other side of the line
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use map_to_3D::level::LineSide;
+    /// assert_eq!(LineSide::Front.opposite(), LineSide::Back);
+    /// ```
+    pub fn opposite(self) -> LineSide {
+        match self {
+            LineSide::Front => LineSide::Back,
+            LineSide::Back => LineSide::Front,
+        }
+    }
+}
+
+/// One of the three parts of a wall which can have a texture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum WallPart {
+    /// The part above the sector on the other side of the line
+    Upper,
+    /// The part between the floor and ceiling
+    Middle,
+    /// The part below the sector on the other side of the line
+    Lower,
+}
+
+impl WallPart {
+    /// All of the parts, from top to bottom
+    pub const ALL: [WallPart; 3] = [WallPart::Upper, WallPart::Middle, WallPart::Lower];
+
+    /// Get the texture of this part of the wall from the sidedef
+    pub fn texture(self, sidedef: &UDMFSidedef) -> &SidedefTexture {
+        match self {
+            WallPart::Upper => &sidedef.texturetop,
+            WallPart::Middle => &sidedef.texturemiddle,
+            WallPart::Lower => &sidedef.texturebottom,
+        }
+    }
+}
+
+/// Get the position of the vertex with the given index
+pub fn vertex_position(map: &UDMFMap, index: usize) -> Vec2 {
+    let vertex = &map.vertices[index];
+    Vec2::new(vertex.x, vertex.y)
+}
+
+/// Get the positions of all of the vertices in the map
+pub fn vertex_positions(map: &UDMFMap) -> Vec<Vec2> {
+    map.vertices.iter().map(|v| Vec2::new(v.x, v.y)).collect()
+}
+
+/// Get the line segment for the linedef with the given index, going from the
+/// first vertex to the second.
+pub fn line_segment(map: &UDMFMap, line: usize) -> Segment {
+    let line = &map.linedefs[line];
+    Segment(
+        vertex_position(map, line.v1 as usize),
+        vertex_position(map, line.v2 as usize)
+    )
+}
+
+/// Get the line segment for the given side of the linedef, going from left
+/// to right when looking at that side of the line.
+pub fn side_segment(map: &UDMFMap, line: usize, side: LineSide) -> Segment {
+    let Segment(a, b) = line_segment(map, line);
+    match side {
+        LineSide::Front => Segment(a, b),
+        LineSide::Back => Segment(b, a),
+    }
+}
+
+/// Is the boolean UDMF property set to true?
+pub fn prop_flag(props: &PropMap, name: &str) -> bool {
+    props.get(name).is_some_and(|value| value == "true")
+}
+
+/// Get the value of a numeric UDMF property, if it is set to a valid number
+pub fn prop_number(props: &PropMap, name: &str) -> Option<f32> {
+    props.get(name).and_then(|value| value.parse().ok())
+}
+
+/// Get the arguments of a line or thing special (`arg0` to `arg4`). Missing
+/// or invalid arguments are 0.
+pub fn prop_args(props: &PropMap) -> [i32; 5] {
+    [0, 1, 2, 3, 4].map(|arg| prop_number(props, &format!("arg{arg}"))
+        .map_or(0, |value| value as i32))
+}
+
+/// Get the special of a line, or 0 if it doesn't have one
+pub fn line_special(line: &UDMFLinedef) -> u32 {
+    prop_number(&line.props, "special").map_or(0, |special| special as u32)
+}
+
+/// Get the index of the sidedef on the given side of the line, if there is
+/// one.
+///
+/// This doesn't check whether the sidedef actually exists in the map.
+pub fn line_sidedef(line: &UDMFLinedef, side: LineSide) -> Option<usize> {
+    match side {
+        LineSide::Front => Some(line.sidefront as usize),
+        LineSide::Back => match line.sideback {
+            SidedefIndex(index) if index >= 0 => Some(index as usize),
+            _ => None
+        }
+    }
+}
+
+/// Set the sidedef on the given side of the line, or remove it if `sidedef`
+/// is `None`.
+///
+/// # Panics
+///
+/// UDMF linedefs always have a front side, so this panics if you try to
+/// remove the front side.
+pub fn set_line_sidedef(
+    line: &mut UDMFLinedef,
+    side: LineSide,
+    sidedef: Option<usize>
+) {
+    match side {
+        LineSide::Front => {
+            line.sidefront = sidedef
+                .expect("A linedef must have a front side") as u32;
+        },
+        LineSide::Back => {
+            line.sideback = SidedefIndex(sidedef.map_or(-1, |s| s as i32));
+        }
+    }
+}
+
+/// Get the index of the sector on the given side of the line, if there is a
+/// valid sidedef on that side, and it refers to a valid sector.
+pub fn side_sector(map: &UDMFMap, line: usize, side: LineSide) -> Option<usize> {
+    let sidedef = line_sidedef(&map.linedefs[line], side)?;
+    let sector = map.sidedefs.get(sidedef)?.sector as usize;
+    (sector < map.sectors.len()).then_some(sector)
+}
+
+/// Get the indices of the lines which have the given sector on either side
+pub fn sector_lines(map: &UDMFMap, sector: usize) -> Vec<usize> {
+    (0..map.linedefs.len()).filter(|&line| {
+        side_sector(map, line, LineSide::Front) == Some(sector) ||
+            side_sector(map, line, LineSide::Back) == Some(sector)
+    }).collect()
+}
+
+/// Get the indices of the lines which have each sector on either side,
+/// indexed by sector. This is the same as calling `sector_lines` for every
+/// sector, but only goes through the lines once.
+pub fn lines_of_sectors(map: &UDMFMap) -> Vec<Vec<usize>> {
+    let mut lines: Vec<Vec<usize>> = vec![Vec::new(); map.sectors.len()];
+    (0..map.linedefs.len()).for_each(|line| {
+        let front = side_sector(map, line, LineSide::Front);
+        let back = side_sector(map, line, LineSide::Back);
+        front.into_iter().chain(back.filter(|&back| Some(back) != front))
+            .for_each(|sector| lines[sector].push(line));
+    });
+    lines
+}
+
+/// Get the floor and ceiling planes of every sector in the map, indexed by
+/// sector.
+///
+/// The planes are sloped using vertex heights, slope specials and slope
+/// things, as described in the `slope` module.
+pub fn sector_planes(map: &UDMFMap) -> Vec<SectorPlanes> {
+    let mut planes: Vec<SectorPlanes> = map.sectors.iter()
+        .map(|sector| SectorPlanes {
+            floor: Plane::Flat(sector.heightfloor as f32),
+            ceiling: Plane::Flat(sector.heightceiling as f32),
+        }).collect();
+    slope::apply_slopes(map, &mut planes);
+    planes
+}
+
+/// Get the edges of the lines which make up the outline of each sector,
+/// indexed by sector.
+///
+/// Lines which have the same sector on both sides are left out, since they
+/// are inside of the sector rather than on its outline. Lines with missing
+/// vertices, or with the same vertex at both ends, are also left out.
+pub fn sector_edges(map: &UDMFMap) -> Vec<Vec<Edge>> {
+    let mut edges: Vec<Vec<Edge>> = vec![Vec::new(); map.sectors.len()];
+    let vertex_count = map.vertices.len() as u32;
+    map.linedefs.iter().enumerate().for_each(|(index, line)| {
+        if line.v1 == line.v2 || line.v1 >= vertex_count ||
+            line.v2 >= vertex_count {
+            return;
+        }
+        let front = side_sector(map, index, LineSide::Front);
+        let back = side_sector(map, index, LineSide::Back);
+        if front == back {
+            return;
+        }
+        let edge = Edge::new(line.v1 as usize, line.v2 as usize);
+        front.into_iter().chain(back).for_each(|sector| {
+            edges[sector].push(edge);
+        });
+    });
+    edges
+}
+
+/// Build the polygons for every sector in the map, indexed by sector.
+///
+/// With the `rayon` feature enabled, the sectors are built in parallel.
+///
+/// # Example
+///
+/// ```
+/// use std::str::FromStr;
+/// use parsers::udmf::input::UDMFMap;
+/// use map_to_3D::level;
+///
+/// let map = UDMFMap::from_str(r#"namespace = "zdoom";
+/// vertex { x = 0.0; y = 0.0; }
+/// vertex { x = 64.0; y = 0.0; }
+/// vertex { x = 64.0; y = 64.0; }
+/// vertex { x = 0.0; y = 64.0; }
+/// linedef { v1 = 0; v2 = 3; sidefront = 0; }
+/// linedef { v1 = 3; v2 = 2; sidefront = 0; }
+/// linedef { v1 = 2; v2 = 1; sidefront = 0; }
+/// linedef { v1 = 1; v2 = 0; sidefront = 0; }
+/// sidedef { sector = 0; }
+/// sector { texturefloor = "FLAT1"; textureceiling = "FLAT1"; }
+/// "#).unwrap();
+/// let sectors = level::build_sector_polygons(&map);
+/// assert_eq!(sectors.len(), 1);
+/// assert_eq!(sectors[0].polygons.len(), 1);
+/// assert!(sectors[0].is_closed());
+/// ```
+pub fn build_sector_polygons(map: &UDMFMap) -> Vec<PolygonBuildResult> {
+    let vertices = vertex_positions(map);
+    let edges = sector_edges(map);
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "rayon")] {
+            let edges = edges.par_iter();
+        } else {
+            let edges = edges.iter();
+        }
+    }
+    edges.map(|edges| spb::build_polygons_detailed(edges, &vertices))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const TEXTMAP: &str = r#"namespace = "zdoom";
+vertex { x = 0.0; y = 0.0; }
+vertex { x = 64.0; y = 0.0; }
+linedef { v1 = 0; v2 = 1; sidefront = 0; sideback = 1; }
+linedef { v1 = 1; v2 = 0; sidefront = 2; }
+sidedef { sector = 0; }
+sidedef { sector = 5; }
+sidedef { sector = 0; }
+sector { texturefloor = "FLAT1"; textureceiling = "FLAT1"; }
+"#;
+
+    #[test]
+    fn sides_and_sectors() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        assert_eq!(line_sidedef(&map.linedefs[0], LineSide::Back), Some(1));
+        assert_eq!(line_sidedef(&map.linedefs[1], LineSide::Back), None);
+        assert_eq!(side_sector(&map, 0, LineSide::Front), Some(0));
+        // Sidedef 1 refers to a sector which does not exist
+        assert_eq!(side_sector(&map, 0, LineSide::Back), None);
+        assert_eq!(side_sector(&map, 1, LineSide::Back), None);
+    }
+
+    #[test]
+    fn set_back_side() {
+        let mut map = UDMFMap::from_str(TEXTMAP).unwrap();
+        set_line_sidedef(&mut map.linedefs[0], LineSide::Back, None);
+        assert_eq!(map.linedefs[0].sideback, SidedefIndex(-1));
+        set_line_sidedef(&mut map.linedefs[1], LineSide::Back, Some(2));
+        assert_eq!(line_sidedef(&map.linedefs[1], LineSide::Back), Some(2));
+    }
+
+    #[test]
+    fn segment() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let Segment(a, b) = line_segment(&map, 1);
+        assert_eq!(a, Vec2::new(64., 0.));
+        assert_eq!(b, Vec2::new(0., 0.));
+    }
+
+    #[test]
+    fn whole_map_polygons() {
+        // Two squares next to each other, with a line inside of the left
+        // square which has the left square on both sides.
+        let map = UDMFMap::from_str(r#"namespace = "zdoom";
+vertex { x = 0.0; y = 0.0; }
+vertex { x = 64.0; y = 0.0; }
+vertex { x = 64.0; y = 64.0; }
+vertex { x = 0.0; y = 64.0; }
+vertex { x = 128.0; y = 0.0; }
+vertex { x = 128.0; y = 64.0; }
+vertex { x = 16.0; y = 16.0; }
+vertex { x = 32.0; y = 32.0; }
+linedef { v1 = 0; v2 = 3; sidefront = 0; }
+linedef { v1 = 3; v2 = 2; sidefront = 0; }
+linedef { v1 = 1; v2 = 0; sidefront = 0; }
+linedef { v1 = 1; v2 = 2; sidefront = 0; sideback = 1; }
+linedef { v1 = 2; v2 = 5; sidefront = 1; }
+linedef { v1 = 5; v2 = 4; sidefront = 1; }
+linedef { v1 = 4; v2 = 1; sidefront = 1; }
+linedef { v1 = 6; v2 = 7; sidefront = 0; sideback = 0; }
+sidedef { sector = 0; }
+sidedef { sector = 1; }
+sector { texturefloor = "FLAT1"; textureceiling = "FLAT1"; }
+sector { texturefloor = "FLAT1"; textureceiling = "FLAT1"; }
+"#).unwrap();
+        let edges = sector_edges(&map);
+        assert_eq!(edges[0].len(), 4);
+        assert_eq!(edges[1].len(), 4);
+        assert!(edges[1].contains(&Edge::new(1, 2)));
+        let sectors = build_sector_polygons(&map);
+        assert_eq!(sectors.len(), 2);
+        sectors.iter().for_each(|sector| {
+            assert!(sector.is_closed());
+            assert_eq!(sector.polygons.len(), 1);
+            assert_eq!(sector.polygons[0].vertices.len(), 4);
+        });
+    }
+}
@@ -7,10 +7,26 @@
 
 // pub mod vector; // Now using glam
 pub mod plane;
-pub(crate) mod boundingbox;
+pub mod slope;
+pub mod boundingbox;
 pub mod sectorpolygonbuilder;
+pub mod sectorindex;
 pub mod edge;
-// pub mod wall;
+pub mod wall;
+pub mod floor3d;
+pub mod sky;
+pub mod portal;
+pub mod polyobject;
+pub mod thing;
+pub mod uv;
+pub mod light;
+pub mod attributes;
+pub mod buffer;
+pub mod export;
 // pub mod sector;
 pub(crate) mod util;
 pub mod segment;
+pub mod predicates;
+pub mod level;
+pub mod edit;
+pub mod analysis;
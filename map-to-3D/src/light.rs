@@ -0,0 +1,247 @@
+//! # Lighting
+//!
+//! Works out how bright walls, floors, ceilings and things are, what colour
+//! they are tinted, and how foggy they are, using the sectors' light levels
+//! and the ZDoom (and Doom 64) UDMF extensions.
+use glam::{Vec2, Vec3, Vec4};
+use parsers::udmf::input::{UDMFMap, UDMFSector, MultiplicativeColour, PropMap};
+use crate::level::{self, LineSide};
+use crate::plane::SectorPlanes;
+
+/// How much lighter walls going north to south are, and how much darker
+/// walls going east to west are
+pub const FAKE_CONTRAST: i32 = 16;
+
+/// How far away the fog is if the sector's fog density is 1. See
+/// `sector_fog`.
+pub const FOG_DISTANCE: f32 = 65536.;
+
+/// Convert a colour from a UDMF property to a `Vec3` with components from 0
+/// to 1
+pub fn colour_vec3(colour: MultiplicativeColour) -> Vec3 {
+    Vec3::new(colour.r as f32, colour.g as f32, colour.b as f32) / 255.
+}
+
+/// Convert a light level to a brightness from 0 to 1
+pub fn brightness(light: i32) -> f32 {
+    light.clamp(0, 255) as f32 / 255.
+}
+
+/// Get a light level after applying a relative or absolute light property,
+/// like `lightfloor` and `lightfloorabsolute`.
+fn apply_light(base: i32, props: &PropMap, light: &str, absolute: &str) -> i32 {
+    let light = level::prop_number(props, light)
+        .map_or(0, |light| light as i32);
+    if level::prop_flag(props, absolute) {
+        light
+    } else {
+        base + light
+    }
+}
+
+/// Get the light level of the floor or ceiling of a sector, which is the
+/// light level of the sector, unless it is changed by `lightfloor` or
+/// `lightceiling`.
+pub fn flat_light(sector: &UDMFSector, ceiling: bool) -> i32 {
+    let (light, absolute) = if ceiling {
+        ("lightceiling", "lightceilingabsolute")
+    } else {
+        ("lightfloor", "lightfloorabsolute")
+    };
+    apply_light(sector.lightlevel.0, &sector.props, light, absolute)
+        .clamp(0, 255)
+}
+
+/// Get how much lighter or darker one side of a line is, because of the
+/// direction it goes in.
+///
+/// Like the Doom engine, lines going exactly north to south are lighter, and
+/// lines going exactly east to west are darker. If the sidedef has the
+/// `smoothlighting` flag, lines going in other directions are lightened or
+/// darkened depending on their angle, and if it has the `nofakecontrast`
+/// flag, the line isn't lightened or darkened at all.
+pub fn fake_contrast(map: &UDMFMap, line: usize, side: LineSide) -> i32 {
+    let Some(sidedef) = level::line_sidedef(&map.linedefs[line], side)
+        .and_then(|sidedef| map.sidedefs.get(sidedef)) else {
+        return 0;
+    };
+    if level::prop_flag(&sidedef.props, "nofakecontrast") {
+        return 0;
+    }
+    let segment = level::line_segment(map, line);
+    let delta: Vec2 = segment.1 - segment.0;
+    if delta.x == 0. {
+        FAKE_CONTRAST
+    } else if delta.y == 0. {
+        -FAKE_CONTRAST
+    } else if level::prop_flag(&sidedef.props, "smoothlighting") {
+        // 0 for east to west, and 1 for north to south
+        let steepness = (delta.y / delta.x).atan().abs() / std::f32::consts::FRAC_PI_2;
+        (-FAKE_CONTRAST as f32 + steepness * (FAKE_CONTRAST * 2) as f32)
+            .round() as i32
+    } else {
+        0
+    }
+}
+
+/// Get the light level of one side of a line, using the light level of the
+/// sector on that side, the sidedef's `light` and `lightabsolute`
+/// properties, and fake contrast.
+///
+/// Returns `None` if the side of the line doesn't have a sector.
+pub fn wall_light(map: &UDMFMap, line: usize, side: LineSide) -> Option<i32> {
+    let sector = &map.sectors[level::side_sector(map, line, side)?];
+    let sidedef = &map.sidedefs[level::line_sidedef(&map.linedefs[line], side)?];
+    let light = apply_light(
+        sector.lightlevel.0, &sidedef.props, "light", "lightabsolute");
+    Some((light + fake_contrast(map, line, side)).clamp(0, 255))
+}
+
+/// Get the fog of a sector, from its `fadecolor` and `fogdensity`
+/// properties. The colour of the fog is in `xyz`, and `w` is the distance at
+/// which the fog completely hides everything.
+///
+/// A sector without a fade colour doesn't have any fog, so `Vec4::ZERO` is
+/// returned. If it has a fade colour, but no fog density, the fog is thinner
+/// in brighter sectors.
+pub fn sector_fog(sector: &UDMFSector) -> Vec4 {
+    let colour = match sector.props.get("fadecolor")
+        .and_then(|colour| colour.parse::<MultiplicativeColour>().ok()) {
+        Some(MultiplicativeColour { r: 0, g: 0, b: 0 }) | None => {
+            return Vec4::ZERO;
+        },
+        Some(colour) => colour_vec3(colour),
+    };
+    let density = level::prop_number(&sector.props, "fogdensity")
+        .filter(|density| *density > 0.)
+        .unwrap_or_else(|| (256 - sector.lightlevel.0.clamp(0, 255)) as f32);
+    colour.extend(FOG_DISTANCE / density)
+}
+
+/// The colour of the walls in a sector, which fades from `color_walltop` at
+/// the ceiling to `color_wallbottom` at the floor, like in Doom 64.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WallGradient {
+    pub top: Vec3,
+    pub bottom: Vec3,
+    pub planes: SectorPlanes,
+}
+
+impl WallGradient {
+    pub fn new(sector: &UDMFSector, planes: SectorPlanes) -> WallGradient {
+        WallGradient {
+            top: colour_vec3(sector.color_walltop),
+            bottom: colour_vec3(sector.color_wallbottom),
+            planes,
+        }
+    }
+
+    /// Get the colour of the wall at the given position. The colour doesn't
+    /// change above the ceiling or below the floor.
+    pub fn colour(&self, position: Vec3) -> Vec3 {
+        let point = position.truncate();
+        let ceiling = self.planes.ceiling.z_at(point);
+        let floor = self.planes.floor.z_at(point);
+        if ceiling <= floor {
+            return self.top;
+        }
+        let fraction = ((position.z - floor) / (ceiling - floor)).clamp(0., 1.);
+        self.bottom.lerp(self.top, fraction)
+    }
+}
+
+/// Get the colour of a vertex of a wall, with the light level of the side of
+/// the line and the colour of its sector applied.
+///
+/// Returns `None` if the side of the line doesn't have a sector.
+pub fn wall_colour(
+    map: &UDMFMap,
+    line: usize,
+    side: LineSide,
+    planes: &[SectorPlanes],
+    position: Vec3
+) -> Option<Vec3> {
+    let sector = level::side_sector(map, line, side)?;
+    let gradient = WallGradient::new(&map.sectors[sector], planes[sector]);
+    Some(gradient.colour(position) * brightness(wall_light(map, line, side)?))
+}
+
+/// Get the colour of the sprites of things in a sector, with the light level
+/// and `color_sprites` applied
+pub fn sprite_colour(sector: &UDMFSector) -> Vec3 {
+    colour_vec3(sector.color_sprites) * brightness(sector.lightlevel.0)
+}
+
+/// Get the colour of the floor or ceiling of a sector, with the light level
+/// and the colour of the floor or ceiling applied.
+pub fn flat_colour(sector: &UDMFSector, ceiling: bool) -> Vec3 {
+    let colour = if ceiling {
+        sector.color_ceiling
+    } else {
+        sector.color_floor
+    };
+    colour_vec3(colour) * brightness(flat_light(sector, ceiling))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::plane::Plane;
+
+    const TEXTMAP: &str = r#"namespace = "zdoom";
+vertex { x = 0.0; y = 0.0; }
+vertex { x = 0.0; y = 64.0; }
+vertex { x = 64.0; y = 64.0; }
+vertex { x = 64.0; y = 0.0; }
+vertex { x = 128.0; y = 32.0; }
+linedef { v1 = 0; v2 = 1; sidefront = 0; }
+linedef { v1 = 1; v2 = 2; sidefront = 0; }
+linedef { v1 = 2; v2 = 4; sidefront = 0; }
+linedef { v1 = 4; v2 = 3; sidefront = 1; }
+linedef { v1 = 3; v2 = 0; sidefront = 2; }
+sidedef { sector = 0; }
+sidedef { sector = 0; smoothlighting = true; }
+sidedef { sector = 0; nofakecontrast = true; light = 300; lightabsolute = true; }
+sector { texturefloor = "FLAT1"; textureceiling = "CEIL1_1";
+    heightceiling = 128; lightlevel = 128; lightfloor = -32;
+    lightceiling = 200; lightceilingabsolute = true;
+    color_floor = 0xFF0000; color_walltop = 0x00FF00;
+    color_wallbottom = 0x0000FF; fadecolor = 0x808080; }
+"#;
+
+    #[test]
+    fn light_levels() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let sector = &map.sectors[0];
+        assert_eq!(flat_light(sector, false), 96);
+        assert_eq!(flat_light(sector, true), 200);
+        assert_eq!(wall_light(&map, 0, LineSide::Front), Some(144));
+        assert_eq!(wall_light(&map, 1, LineSide::Front), Some(112));
+        // Not smooth, so diagonal lines aren't changed
+        assert_eq!(wall_light(&map, 2, LineSide::Front), Some(128));
+        assert_eq!(wall_light(&map, 2, LineSide::Back), None);
+        // Closer to east-west than north-south
+        assert_eq!(fake_contrast(&map, 3, LineSide::Front), -7);
+        assert_eq!(wall_light(&map, 4, LineSide::Front), Some(255));
+    }
+
+    #[test]
+    fn colours_and_fog() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let sector = &map.sectors[0];
+        assert_eq!(flat_colour(sector, false), Vec3::new(96. / 255., 0., 0.));
+        let planes = [SectorPlanes {
+            floor: Plane::Flat(0.),
+            ceiling: Plane::Flat(128.),
+        }];
+        let colour = |z| wall_colour(
+            &map, 4, LineSide::Front, &planes, Vec3::new(32., 0., z)).unwrap();
+        assert_eq!(colour(128.), Vec3::new(0., 1., 0.));
+        assert_eq!(colour(64.), Vec3::new(0., 0.5, 0.5));
+        assert_eq!(colour(-16.), Vec3::new(0., 0., 1.));
+        let fog = sector_fog(sector);
+        assert_eq!(fog.truncate(), Vec3::splat(128. / 255.));
+        assert_eq!(fog.w, FOG_DISTANCE / 128.);
+    }
+}
@@ -233,6 +233,13 @@ impl Plane {
     }
 }
 
+/// The floor and ceiling planes of a sector
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SectorPlanes {
+    pub floor: Plane,
+    pub ceiling: Plane,
+}
+
 // Necessary?
 /* 
 #[derive(Debug, Clone, Copy)]
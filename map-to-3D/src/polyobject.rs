@@ -0,0 +1,292 @@
+//! # Polyobjects
+//!
+//! Finds Hexen's polyobjects, which are drawn somewhere out of the way in
+//! the map, and moved to where they are used when the map starts. Each
+//! polyobject has a number, and is made up of:
+//!
+//! - Lines, which are either the lines joined to a line with the
+//!   `Polyobj_StartLine` (1) special, or the lines with the
+//!   `Polyobj_ExplicitLine` (5) special, in the order given by the special.
+//! - An anchor thing (9300), which is the point on the polyobject which is
+//!   moved to the start spot.
+//! - A start spot thing (9301 to 9303), which is where the anchor is moved
+//!   to.
+//!
+//! The number of the polyobject is in the angle of the things, and the
+//! first argument of the specials.
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use glam::Vec2;
+use parsers::udmf::input::UDMFMap;
+use crate::level::{self, LineSide};
+use crate::sectorindex::SectorIndex;
+
+/// `Polyobj_StartLine`
+pub const POLYOBJ_START_LINE: u32 = 1;
+/// `Polyobj_ExplicitLine`
+pub const POLYOBJ_EXPLICIT_LINE: u32 = 5;
+/// The polyobject anchor thing
+pub const POLYOBJ_ANCHOR: u32 = 9300;
+/// The polyobject start spot thing
+pub const POLYOBJ_START_SPOT: u32 = 9301;
+/// The start spot thing for polyobjects which crush things
+pub const POLYOBJ_START_SPOT_CRUSH: u32 = 9302;
+/// The start spot thing for polyobjects which hurt things that touch them
+pub const POLYOBJ_START_SPOT_HURT: u32 = 9303;
+
+/// What happens to things which get in the way of a polyobject, from the
+/// type of its start spot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolyobjectKind {
+    /// The polyobject is blocked
+    Normal,
+    /// The polyobject crushes the thing
+    Crushing,
+    /// The thing is hurt when it touches the polyobject
+    Harmful,
+}
+
+/// A polyobject, with its lines, and where it is moved from and to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polyobject {
+    pub number: i32,
+    pub kind: PolyobjectKind,
+    /// The indices of the lines which make up the polyobject
+    pub lines: Vec<usize>,
+    /// The position of the anchor thing
+    pub anchor: Vec2,
+    /// The position of the start spot thing
+    pub start: Vec2,
+    /// The index of the sector the start spot is in, which the polyobject
+    /// gets its floor, ceiling and light from once it has been moved
+    pub sector: Option<usize>,
+}
+
+impl Polyobject {
+    /// Get every polyobject in the map which has lines, an anchor and a
+    /// start spot, in order of their numbers
+    pub fn for_map(map: &UDMFMap) -> Vec<Polyobject> {
+        let mut anchors: BTreeMap<i32, Vec2> = BTreeMap::new();
+        let mut starts: BTreeMap<i32, (Vec2, PolyobjectKind)> = BTreeMap::new();
+        map.things.iter().for_each(|thing| {
+            let position = Vec2::new(thing.x, thing.y);
+            let kind = match thing.ednum {
+                POLYOBJ_ANCHOR => {
+                    anchors.insert(thing.angle, position);
+                    return;
+                },
+                POLYOBJ_START_SPOT => PolyobjectKind::Normal,
+                POLYOBJ_START_SPOT_CRUSH => PolyobjectKind::Crushing,
+                POLYOBJ_START_SPOT_HURT => PolyobjectKind::Harmful,
+                _ => return,
+            };
+            starts.insert(thing.angle, (position, kind));
+        });
+        // Finding the sectors of the start spots needs the sector polygons,
+        // so they are only built if there could be polyobjects
+        if starts.is_empty() || anchors.is_empty() {
+            return Vec::new();
+        }
+        let finder = SectorIndex::new(map);
+        starts.into_iter().filter_map(|(number, (start, kind))| {
+            let lines = polyobject_lines(map, number);
+            if lines.is_empty() {
+                return None;
+            }
+            Some(Polyobject {
+                number,
+                kind,
+                lines,
+                anchor: *anchors.get(&number)?,
+                start,
+                sector: finder.sector_at(start),
+            })
+        }).collect()
+    }
+
+    /// How far the polyobject is moved
+    pub fn offset(&self) -> Vec2 {
+        self.start - self.anchor
+    }
+}
+
+/// Get the lines of the polyobject with the given number, in order
+fn polyobject_lines(map: &UDMFMap, number: i32) -> Vec<usize> {
+    let with_special = |special: u32| (0..map.linedefs.len()).filter(move |&line| {
+        let linedef = &map.linedefs[line];
+        level::line_special(linedef) == special &&
+            level::prop_args(&linedef.props)[0] == number
+    });
+    let mut explicit: Vec<usize> = with_special(POLYOBJ_EXPLICIT_LINE).collect();
+    if !explicit.is_empty() {
+        explicit.sort_by_key(|&line| level::prop_args(&map.linedefs[line].props)[1]);
+        return explicit;
+    }
+    let Some(first) = with_special(POLYOBJ_START_LINE).next() else {
+        return Vec::new();
+    };
+    // Follow the lines from the start line until they get back to it
+    let start = map.linedefs[first].v1;
+    let mut lines = vec![first];
+    let mut end = map.linedefs[first].v2;
+    while end != start && lines.len() < map.linedefs.len() {
+        let Some(next) = (0..map.linedefs.len()).find(|&line| {
+            map.linedefs[line].v1 == end && !lines.contains(&line)
+        }) else {
+            break;
+        };
+        lines.push(next);
+        end = map.linedefs[next].v2;
+    }
+    lines
+}
+
+/// Get a copy of the map with the polyobjects moved to their start spots.
+///
+/// The lines of each polyobject get their own copies of their vertices,
+/// which are moved, and their own copies of their sidedefs, which are put
+/// in the sector the polyobject is moved to. This way, walls built from the
+/// copy have the floor, ceiling and light of that sector. The indices of
+/// everything in the map stay the same.
+///
+/// Only the walls should be built from the copy, since the sectors the
+/// polyobjects are moved to and from have the wrong outlines in it.
+pub fn place_polyobjects(map: &UDMFMap, polyobjects: &[Polyobject]) -> UDMFMap {
+    let mut placed = map.clone();
+    polyobjects.iter().for_each(|polyobject| {
+        let offset = polyobject.offset();
+        // Lines which share a vertex share the moved copy of it
+        let mut moved: BTreeMap<u32, u32> = BTreeMap::new();
+        polyobject.lines.iter().for_each(|&line| {
+            let linedef = &map.linedefs[line];
+            let vertices = [linedef.v1, linedef.v2].map(|vertex| {
+                *moved.entry(vertex).or_insert_with(|| {
+                    let mut copy = map.vertices[vertex as usize].clone();
+                    copy.x += offset.x;
+                    copy.y += offset.y;
+                    placed.vertices.push(copy);
+                    placed.vertices.len() as u32 - 1
+                })
+            });
+            let placed_line = &mut placed.linedefs[line];
+            [placed_line.v1, placed_line.v2] = vertices;
+            [LineSide::Front, LineSide::Back].into_iter().for_each(|side| {
+                let Some(mut sidedef) = level::line_sidedef(linedef, side)
+                    .and_then(|sidedef| map.sidedefs.get(sidedef)).cloned() else {
+                    return;
+                };
+                if let Some(sector) = polyobject.sector {
+                    sidedef.sector = sector as u32;
+                }
+                placed.sidedefs.push(sidedef);
+                level::set_line_sidedef(&mut placed.linedefs[line], side,
+                    Some(placed.sidedefs.len() - 1));
+            });
+        });
+    });
+    placed
+}
+
+/// Get the map which the walls should be built from. If there are
+/// polyobjects, this is a copy of the map with them moved to their start
+/// spots, from `place_polyobjects`. Otherwise, it is the map itself, so it
+/// isn't copied.
+pub fn walls_map(map: &UDMFMap) -> Cow<'_, UDMFMap> {
+    let polyobjects = Polyobject::for_map(map);
+    if polyobjects.is_empty() {
+        Cow::Borrowed(map)
+    } else {
+        Cow::Owned(place_polyobjects(map, &polyobjects))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // A 256x256 room, and a 64x64 sector far away from it with a 16x16
+    // polyobject in the middle, which faces out into the sector. The
+    // polyobject is moved to the middle of the room.
+    pub(crate) const TEXTMAP: &str = r#"namespace = "hexen";
+vertex { x = 0.0; y = 0.0; }
+vertex { x = 0.0; y = 256.0; }
+vertex { x = 256.0; y = 256.0; }
+vertex { x = 256.0; y = 0.0; }
+vertex { x = 1024.0; y = 1024.0; }
+vertex { x = 1024.0; y = 1088.0; }
+vertex { x = 1088.0; y = 1088.0; }
+vertex { x = 1088.0; y = 1024.0; }
+vertex { x = 1048.0; y = 1048.0; }
+vertex { x = 1064.0; y = 1048.0; }
+vertex { x = 1064.0; y = 1064.0; }
+vertex { x = 1048.0; y = 1064.0; }
+linedef { v1 = 0; v2 = 1; sidefront = 0; }
+linedef { v1 = 1; v2 = 2; sidefront = 0; }
+linedef { v1 = 2; v2 = 3; sidefront = 0; }
+linedef { v1 = 3; v2 = 0; sidefront = 0; }
+linedef { v1 = 4; v2 = 5; sidefront = 1; }
+linedef { v1 = 5; v2 = 6; sidefront = 1; }
+linedef { v1 = 6; v2 = 7; sidefront = 1; }
+linedef { v1 = 7; v2 = 4; sidefront = 1; }
+linedef { v1 = 10; v2 = 11; sidefront = 2; }
+linedef { v1 = 8; v2 = 9; sidefront = 2; special = 1; arg0 = 1; }
+linedef { v1 = 9; v2 = 10; sidefront = 2; }
+linedef { v1 = 11; v2 = 8; sidefront = 2; }
+sidedef { sector = 0; texturemiddle = "STARTAN2"; }
+sidedef { sector = 1; texturemiddle = "STARTAN2"; }
+sidedef { sector = 1; texturemiddle = "DOOR1"; }
+sector { texturefloor = "FLAT1"; textureceiling = "CEIL1_1"; heightceiling = 128; }
+sector { texturefloor = "FLAT1"; textureceiling = "CEIL1_1"; heightceiling = 64; }
+thing { x = 1056.0; y = 1056.0; angle = 1; type = 9300; }
+thing { x = 128.0; y = 128.0; angle = 1; type = 9302; }
+"#;
+
+    #[test]
+    fn find_polyobjects() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let polyobjects = Polyobject::for_map(&map);
+        assert_eq!(polyobjects, vec![Polyobject {
+            number: 1,
+            kind: PolyobjectKind::Crushing,
+            lines: vec![9, 10, 8, 11],
+            anchor: Vec2::new(1056., 1056.),
+            start: Vec2::new(128., 128.),
+            sector: Some(0),
+        }]);
+        assert_eq!(polyobjects[0].offset(), Vec2::new(-928., -928.));
+
+        // Explicit lines are in the order given by their second arguments
+        let textmap = TEXTMAP.replace("special = 1; arg0 = 1;", "")
+            .replace("linedef { v1 = 10; v2 = 11; sidefront = 2; }",
+                "linedef { v1 = 10; v2 = 11; sidefront = 2; special = 5; arg0 = 1; arg1 = 2; }")
+            .replace("linedef { v1 = 9; v2 = 10; sidefront = 2; }",
+                "linedef { v1 = 9; v2 = 10; sidefront = 2; special = 5; arg0 = 1; arg1 = 1; }");
+        let map = UDMFMap::from_str(&textmap).unwrap();
+        assert_eq!(Polyobject::for_map(&map)[0].lines, vec![10, 8]);
+    }
+
+    #[test]
+    fn placed_polyobjects() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let placed = place_polyobjects(&map, &Polyobject::for_map(&map));
+        assert_eq!(placed.linedefs.len(), map.linedefs.len());
+        let start = level::vertex_position(&placed, placed.linedefs[9].v1 as usize);
+        assert_eq!(start, Vec2::new(120., 120.));
+        // The lines share the moved vertices
+        assert_eq!(placed.linedefs[9].v2, placed.linedefs[10].v1);
+        assert_eq!(placed.vertices.len(), map.vertices.len() + 4);
+        assert_eq!(level::side_sector(&placed, 9, LineSide::Front), Some(0));
+        // The original sidedef is still in the other sector
+        assert_eq!(placed.sidedefs[2].sector, 1);
+        assert_eq!(level::side_sector(&placed, 4, LineSide::Front), Some(1));
+    }
+
+    #[test]
+    fn walls_map_without_polyobjects() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        assert!(matches!(walls_map(&map), Cow::Owned(_)));
+        let map = UDMFMap::from_str(&TEXTMAP.replace("type = 9302;", "type = 1;")).unwrap();
+        assert!(matches!(walls_map(&map), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,530 @@
+//! # Portals
+//!
+//! Finds GZDoom's portals, which show another part of the map, and works out
+//! how to move the other part of the map so that it lines up with the
+//! portal. There are three kinds of portal:
+//!
+//! - Line portals, made with `Line_SetPortal` (156), which show the area in
+//!   front of the target line through the back of the portal line.
+//! - Sector portals, made with `Sector_SetPortal` (57), which show another
+//!   area through the floors or ceilings of the tagged sectors.
+//! - Skyboxes, which show the area around a viewpoint thing where the sky
+//!   would be seen. They are made with the `SkyViewpoint` (9080),
+//!   `SkyPicker` (9081) and `SkyCamCompat` (9083) things.
+//!
+//! `export::stitch_portals` uses the portals to copy the geometry which can
+//! be seen through them to where it is seen.
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+use glam::{Affine3A, Vec2, Vec3};
+use parsers::udmf::input::UDMFMap;
+use crate::level::{self, LineSide};
+use crate::segment::Segment;
+use crate::sectorindex::SectorIndex;
+use crate::wall::WallOptions;
+
+/// `Line_SetPortal`
+pub const LINE_SET_PORTAL: u32 = 156;
+/// `Sector_SetPortal`
+pub const SECTOR_SET_PORTAL: u32 = 57;
+/// The `SkyViewpoint` thing. The one without a TID is the default skybox.
+pub const SKY_VIEWPOINT: u32 = 9080;
+/// The `SkyPicker` thing, which sets the skybox of the sector it is in
+pub const SKY_PICKER: u32 = 9081;
+/// The `SkyCamCompat` thing, which is used with `Sector_SetPortal`
+pub const SKY_CAM_COMPAT: u32 = 9083;
+
+/// What kind of portal it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortalKind {
+    /// Only shows the other side
+    Visual,
+    /// Shows the other side, and teleports things which go through it
+    Teleporter,
+    /// Shows the other side, which things can go through
+    Interactive,
+    /// Like `Interactive`, but the two sides are joined together, so the
+    /// map works as if the other side was really there
+    Linked,
+    /// Shows the area around a viewpoint thing where the sky would be seen
+    Skybox,
+    /// Shows the floor or ceiling of the destination sector, as if it went
+    /// on forever
+    Plane,
+    /// Shows the floor and ceiling of the destination sector, stretched out
+    /// to the horizon
+    Horizon,
+}
+
+/// Where a portal can be seen from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PortalEntrance {
+    /// The back of the line with the given index
+    Line(usize),
+    /// The floor of the sector with the given index
+    Floor(usize),
+    /// The ceiling of the sector with the given index
+    Ceiling(usize),
+}
+
+impl PortalEntrance {
+    fn plane(sector: usize, ceiling: bool) -> PortalEntrance {
+        if ceiling {
+            PortalEntrance::Ceiling(sector)
+        } else {
+            PortalEntrance::Floor(sector)
+        }
+    }
+
+    /// Get the sector the entrance is seen from. For lines, this is the
+    /// sector on the front of the line.
+    pub fn sector(&self, map: &UDMFMap) -> Option<usize> {
+        match *self {
+            PortalEntrance::Line(line) => level::side_sector(map, line, LineSide::Front),
+            PortalEntrance::Floor(sector) | PortalEntrance::Ceiling(sector) => Some(sector),
+        }
+    }
+}
+
+/// A portal, and the part of the map which can be seen through it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Portal {
+    pub kind: PortalKind,
+    /// Where the portal can be seen from
+    pub entrances: Vec<PortalEntrance>,
+    /// The index of the sector which can be seen through the portal
+    pub destination: usize,
+    /// Moves points from the side of the portal it is seen from to the side
+    /// which is seen through it. For plane and horizon portals, this does
+    /// nothing, since they don't show another area.
+    ///
+    /// Skyboxes don't have a place in the map, so the transform moves the
+    /// viewpoint to the middle of the floors and ceilings the skybox is
+    /// seen through, at the height of the highest of them.
+    pub transform: Affine3A,
+}
+
+impl Portal {
+    /// Whether the portal shows another area of the map, rather than
+    /// stretching out the planes of a sector
+    pub fn shows_area(&self) -> bool {
+        !matches!(self.kind, PortalKind::Plane | PortalKind::Horizon)
+    }
+}
+
+/// Two sectors which are joined by a portal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortalLink {
+    /// The sector the portal is seen from
+    pub from: usize,
+    /// The sector seen through the portal
+    pub to: usize,
+    /// The index of the portal in the `PortalGraph`
+    pub portal: usize,
+}
+
+/// The portals in a map
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PortalGraph {
+    pub portals: Vec<Portal>,
+}
+
+impl PortalGraph {
+    /// Find every portal in the map. `options` says which flat shows the
+    /// sky, which is where skyboxes from `SkyViewpoint` things are seen.
+    pub fn for_map(map: &UDMFMap, options: &WallOptions) -> PortalGraph {
+        // Skyboxes come first, so that the other portals replace them
+        let mut portals = skyboxes(map, options, &SectorIndex::new(map));
+        portals.extend((0..map.linedefs.len())
+            .filter_map(|line| line_portal(map, line)));
+        portals.extend((0..map.linedefs.len())
+            .filter_map(|line| sector_portal(map, line)));
+        PortalGraph { portals }
+    }
+
+    /// Get the index of the portal on the given line, if there is one
+    pub fn line_portal(&self, line: usize) -> Option<usize> {
+        self.find(PortalEntrance::Line(line))
+    }
+
+    /// Get the index of the portal on the floor or ceiling of the given
+    /// sector, if there is one
+    pub fn plane_portal(&self, sector: usize, ceiling: bool) -> Option<usize> {
+        self.find(PortalEntrance::plane(sector, ceiling))
+    }
+
+    /// Like GZDoom, portals set later replace the ones set earlier
+    fn find(&self, entrance: PortalEntrance) -> Option<usize> {
+        self.portals.iter().rposition(|portal| portal.entrances.contains(&entrance))
+    }
+
+    /// Get the pairs of sectors which are joined by the portals. There is a
+    /// link for each entrance of each portal.
+    pub fn links(&self, map: &UDMFMap) -> Vec<PortalLink> {
+        self.portals.iter().enumerate().flat_map(|(portal, data)| {
+            data.entrances.iter().filter_map(move |entrance| Some(PortalLink {
+                from: entrance.sector(map)?,
+                to: data.destination,
+                portal,
+            }))
+        }).collect()
+    }
+}
+
+/// Get the angle of the line from its first vertex to its second
+fn angle(segment: Segment) -> f32 {
+    let delta = segment.1 - segment.0;
+    delta.y.atan2(delta.x)
+}
+
+/// Get the indices of the sectors with the given tag, or the sector in front
+/// of the line if the tag is 0
+fn tagged_sectors(map: &UDMFMap, line: usize, tag: i32) -> Vec<usize> {
+    if tag == 0 {
+        return level::side_sector(map, line, LineSide::Front).into_iter().collect();
+    }
+    map.sectors.iter().enumerate()
+        .filter(|(_, sector)| sector.id as i32 == tag)
+        .map(|(index, _)| index).collect()
+}
+
+/// Get the floors and/or ceilings of the sectors. `plane` is 0 for floors,
+/// 1 for ceilings and 2 for both, as it is for `Sector_SetPortal`.
+fn plane_entrances(sectors: &[usize], plane: i32) -> Vec<PortalEntrance> {
+    let planes: &[bool] = match plane {
+        0 => &[false],
+        1 => &[true],
+        _ => &[false, true],
+    };
+    sectors.iter().flat_map(|&sector| planes.iter()
+        .map(move |&ceiling| PortalEntrance::plane(sector, ceiling)))
+        .collect()
+}
+
+/// Get the line portal made by the given line, if it has the
+/// `Line_SetPortal` special and a target line
+fn line_portal(map: &UDMFMap, line: usize) -> Option<Portal> {
+    let linedef = &map.linedefs[line];
+    if level::line_special(linedef) != LINE_SET_PORTAL {
+        return None;
+    }
+    let [target_id, _, kind, align, _] = level::prop_args(&linedef.props);
+    // Lines without a target are only there to be targeted by other lines
+    if target_id == 0 {
+        return None;
+    }
+    let kind = match kind {
+        0 => PortalKind::Visual,
+        1 => PortalKind::Teleporter,
+        2 => PortalKind::Interactive,
+        3 => PortalKind::Linked,
+        _ => return None,
+    };
+    // The target can be given an ID by its second argument, like it is in
+    // the Hexen format
+    let target = (0..map.linedefs.len()).find(|&other| {
+        let data = &map.linedefs[other];
+        other != line && (data.id as i32 == target_id ||
+            (level::line_special(data) == LINE_SET_PORTAL &&
+            level::prop_args(&data.props)[1] == target_id))
+    })?;
+    let source = level::side_sector(map, line, LineSide::Front)?;
+    let destination = level::side_sector(map, target, LineSide::Front)?;
+    // The portal line and the target line face each other, so the first
+    // vertex of the portal line goes to the second vertex of the target line
+    let (from, to) = (level::line_segment(map, line), level::line_segment(map, target));
+    let (source_data, target_data) = (&map.sectors[source], &map.sectors[destination]);
+    let z = match align {
+        1 => target_data.heightfloor - source_data.heightfloor,
+        2 => target_data.heightceiling - source_data.heightceiling,
+        _ => 0,
+    } as f32;
+    let transform = Affine3A::from_translation(to.1.extend(z)) *
+        Affine3A::from_rotation_z(angle(to) - angle(from) + PI) *
+        Affine3A::from_translation(-from.0.extend(0.));
+    Some(Portal {
+        kind,
+        entrances: vec![PortalEntrance::Line(line)],
+        destination,
+        transform,
+    })
+}
+
+/// Get the sector portal set up by the given line, if it has the
+/// `Sector_SetPortal` special, and isn't a skybox, a copy, or the reference
+/// line of another portal
+fn sector_portal(map: &UDMFMap, line: usize) -> Option<Portal> {
+    let linedef = &map.linedefs[line];
+    if level::line_special(linedef) != SECTOR_SET_PORTAL {
+        return None;
+    }
+    let [tag, kind, plane, misc, _] = level::prop_args(&linedef.props);
+    let front = level::side_sector(map, line, LineSide::Front)?;
+    let (kind, destination, transform) = match kind {
+        // The anchor line is in the sector the portal is seen from, and the
+        // reference line is in the sector seen through it
+        0 | 6 if misc == 0 => {
+            let reference = (0..map.linedefs.len()).find(|&other| {
+                let data = &map.linedefs[other];
+                level::line_special(data) == SECTOR_SET_PORTAL &&
+                    level::prop_args(&data.props)[..4] == [tag, kind, plane, 1]
+            })?;
+            let middle = |line: usize| {
+                let Segment(start, end) = level::line_segment(map, line);
+                (start + end) / 2.
+            };
+            let offset = middle(reference) - middle(line);
+            let kind = if kind == 6 { PortalKind::Linked } else { PortalKind::Visual };
+            (kind, level::side_sector(map, reference, LineSide::Front)?,
+                Affine3A::from_translation(offset.extend(0.)))
+        },
+        3 => (PortalKind::Plane, front, Affine3A::IDENTITY),
+        4 => (PortalKind::Horizon, front, Affine3A::IDENTITY),
+        _ => return None,
+    };
+    let mut entrances = plane_entrances(&tagged_sectors(map, line, tag), plane);
+    entrances.extend(copied_entrances(map, tag, plane));
+    Some(Portal { kind, entrances, destination, transform })
+}
+
+/// Get the places where the portal of the sectors with the given tag is
+/// copied to, using `Sector_SetPortal` with type 1 (to floors and ceilings)
+/// or type 5 (to lines). `plane` is the plane argument of the portal. Like
+/// GZDoom, it is only copied to floors and ceilings by lines with the same
+/// plane argument, or 3 for any plane.
+fn copied_entrances(map: &UDMFMap, tag: i32, plane: i32) -> Vec<PortalEntrance> {
+    (0..map.linedefs.len()).flat_map(|line| {
+        let linedef = &map.linedefs[line];
+        let args = level::prop_args(&linedef.props);
+        if level::line_special(linedef) != SECTOR_SET_PORTAL || args[3] != tag {
+            return Vec::new();
+        }
+        match args[1] {
+            1 if args[2] == plane || args[2] == 3 =>
+                plane_entrances(&tagged_sectors(map, line, args[0]), plane),
+            5 => (0..map.linedefs.len())
+                .filter(|&other| map.linedefs[other].id as i32 == args[0] && args[0] != 0)
+                .map(PortalEntrance::Line).collect(),
+            _ => Vec::new(),
+        }
+    }).collect()
+}
+
+/// Get the skyboxes in the map
+fn skyboxes(map: &UDMFMap, options: &WallOptions, finder: &SectorIndex) -> Vec<Portal> {
+    // Which viewpoint thing each floor and ceiling with the sky flat uses
+    let mut skies: BTreeMap<PortalEntrance, Option<usize>> = BTreeMap::new();
+    let default = map.things.iter()
+        .position(|thing| thing.ednum == SKY_VIEWPOINT && thing.id == 0);
+    map.sectors.iter().enumerate().for_each(|(sector, data)| {
+        [(false, &data.texturefloor), (true, &data.textureceiling)].into_iter()
+            .filter(|(_, flat)| options.is_sky(flat))
+            .for_each(|(ceiling, _)| {
+            skies.insert(PortalEntrance::plane(sector, ceiling), default);
+        });
+    });
+    map.things.iter().filter(|thing| thing.ednum == SKY_PICKER).for_each(|thing| {
+        let Some(sector) = finder.sector_at(Vec2::new(thing.x, thing.y)) else {
+            return;
+        };
+        let [tid, flags, ..] = level::prop_args(&thing.props);
+        // Without a TID, the sector uses the normal sky
+        let viewpoint = (tid != 0).then(|| map.things.iter().position(|other| {
+            other.ednum == SKY_VIEWPOINT && other.id as i32 == tid
+        })).flatten();
+        [(true, 2), (false, 1)].into_iter().filter(|(_, flag)| flags & flag == 0)
+            .for_each(|(ceiling, _)| {
+            if let Some(sky) = skies.get_mut(&PortalEntrance::plane(sector, ceiling)) {
+                *sky = viewpoint;
+            }
+        });
+    });
+    let mut viewpoints: BTreeMap<usize, Vec<PortalEntrance>> = BTreeMap::new();
+    skies.into_iter().for_each(|(entrance, viewpoint)| {
+        if let Some(viewpoint) = viewpoint {
+            viewpoints.entry(viewpoint).or_default().push(entrance);
+        }
+    });
+    // SkyCamCompat things are used by the `Sector_SetPortal` line in the
+    // sector they are in
+    map.things.iter().enumerate().filter(|(_, thing)| thing.ednum == SKY_CAM_COMPAT)
+        .for_each(|(index, thing)| {
+        let Some(sector) = finder.sector_at(Vec2::new(thing.x, thing.y)) else {
+            return;
+        };
+        let setup = level::sector_lines(map, sector).into_iter().find(|&line| {
+            let linedef = &map.linedefs[line];
+            level::line_special(linedef) == SECTOR_SET_PORTAL &&
+                level::prop_args(&linedef.props)[1] == 2
+        });
+        if let Some(line) = setup {
+            let [tag, _, plane, ..] = level::prop_args(&map.linedefs[line].props);
+            let mut entrances = plane_entrances(&tagged_sectors(map, line, tag), plane);
+            entrances.extend(copied_entrances(map, tag, plane));
+            viewpoints.entry(index).or_default().extend(entrances);
+        }
+    });
+    viewpoints.into_iter().filter_map(|(viewpoint, entrances)| {
+        let thing = &map.things[viewpoint];
+        let position = Vec2::new(thing.x, thing.y);
+        let destination = finder.sector_at(position)?;
+        let viewpoint = position.extend(
+            map.sectors[destination].heightfloor as f32 + thing.height);
+        Some(Portal {
+            kind: PortalKind::Skybox,
+            transform: Affine3A::from_translation(
+                viewpoint - entrances_centre(map, &entrances)),
+            entrances,
+            destination,
+        })
+    }).collect()
+}
+
+/// Get the middle of the floors and ceilings, at the height of the highest
+/// of them
+fn entrances_centre(map: &UDMFMap, entrances: &[PortalEntrance]) -> Vec3 {
+    let (min, max, top) = entrances.iter().fold(
+        (Vec2::INFINITY, Vec2::NEG_INFINITY, f32::NEG_INFINITY),
+        |(min, max, top), entrance| {
+        let (sector, height) = match *entrance {
+            PortalEntrance::Floor(sector) => (sector, map.sectors[sector].heightfloor),
+            PortalEntrance::Ceiling(sector) => (sector, map.sectors[sector].heightceiling),
+            PortalEntrance::Line(_) => return (min, max, top),
+        };
+        let (min, max) = level::sector_lines(map, sector).into_iter()
+            .map(|line| level::line_segment(map, line))
+            .flat_map(|Segment(start, end)| [start, end])
+            .fold((min, max), |(min, max), point| (min.min(point), max.max(point)));
+        (min, max, top.max(height as f32))
+    });
+    if top.is_finite() {
+        ((min + max) / 2.).extend(top)
+    } else {
+        Vec3::ZERO
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // Two 64x64 rooms, far apart. The east wall of the first room is a line
+    // portal to the west wall of the second room, which is 256 units higher.
+    // The first room's ceiling is sky, and there is a skybox viewpoint in
+    // the second room.
+    pub(crate) const TEXTMAP: &str = r#"namespace = "zdoom";
+vertex { x = 0.0; y = 0.0; }
+vertex { x = 0.0; y = 64.0; }
+vertex { x = 64.0; y = 64.0; }
+vertex { x = 64.0; y = 0.0; }
+vertex { x = 1024.0; y = 0.0; }
+vertex { x = 1024.0; y = 64.0; }
+vertex { x = 1088.0; y = 64.0; }
+vertex { x = 1088.0; y = 0.0; }
+linedef { v1 = 0; v2 = 1; sidefront = 0; }
+linedef { v1 = 1; v2 = 2; sidefront = 0; }
+linedef { v1 = 2; v2 = 3; sidefront = 0; special = 156; arg0 = 5; arg2 = 3; arg3 = 1; }
+linedef { v1 = 3; v2 = 0; sidefront = 0; }
+linedef { v1 = 4; v2 = 5; sidefront = 1; id = 5; }
+linedef { v1 = 5; v2 = 6; sidefront = 1; }
+linedef { v1 = 6; v2 = 7; sidefront = 1; }
+linedef { v1 = 7; v2 = 4; sidefront = 1; }
+sidedef { sector = 0; texturemiddle = "STARTAN2"; }
+sidedef { sector = 1; texturemiddle = "STARTAN2"; }
+sector { texturefloor = "FLAT1"; textureceiling = "F_SKY1"; heightceiling = 128; }
+sector { texturefloor = "FLAT1"; textureceiling = "CEIL1_1";
+    heightfloor = 256; heightceiling = 384; }
+thing { x = 1056.0; y = 32.0; height = 16.0; type = 9080; }
+"#;
+
+    #[test]
+    fn line_portal() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let graph = PortalGraph::for_map(&map, &WallOptions::default());
+        let portal = &graph.portals[graph.line_portal(2).unwrap()];
+        assert_eq!(portal.kind, PortalKind::Linked);
+        assert_eq!(portal.destination, 1);
+        // The start of the portal line goes to the end of the target line,
+        // and the floors line up
+        let point = portal.transform.transform_point3(Vec3::new(64., 64., 0.));
+        assert!(point.abs_diff_eq(Vec3::new(1024., 64., 256.), 0.001));
+        // Behind the portal is the second room
+        let point = portal.transform.transform_point3(Vec3::new(96., 32., 8.));
+        assert!(point.abs_diff_eq(Vec3::new(1056., 32., 264.), 0.001));
+        assert_eq!(graph.line_portal(4), None);
+    }
+
+    #[test]
+    fn skybox() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let graph = PortalGraph::for_map(&map, &WallOptions::default());
+        assert_eq!(graph.portals.len(), 2);
+        let portal = &graph.portals[graph.plane_portal(0, true).unwrap()];
+        assert_eq!(portal.kind, PortalKind::Skybox);
+        assert_eq!(portal.destination, 1);
+        // The viewpoint is in the middle of the sky ceiling
+        let point = portal.transform.inverse()
+            .transform_point3(Vec3::new(1056., 32., 272.));
+        assert!(point.abs_diff_eq(Vec3::new(32., 32., 128.), 0.001));
+        assert_eq!(graph.links(&map), vec![
+            PortalLink { from: 0, to: 1, portal: 0 },
+            PortalLink { from: 0, to: 1, portal: 1 },
+        ]);
+
+        // A sky picker without a TID turns the skybox off
+        let map = UDMFMap::from_str(&format!(
+            "{TEXTMAP}thing {{ x = 32.0; y = 32.0; type = 9081; }}")).unwrap();
+        let graph = PortalGraph::for_map(&map, &WallOptions::default());
+        assert_eq!(graph.plane_portal(0, true), None);
+    }
+
+    #[test]
+    fn sector_portals() {
+        // Stacked sectors: the ceiling of the first room (tagged 3) shows
+        // the second room, and the floor of the second room shows the first,
+        // using a second anchor and reference line going the other way
+        let textmap = TEXTMAP.replace("special = 156; arg0 = 5; arg2 = 3; arg3 = 1;",
+            "special = 57; arg0 = 3; arg2 = 1;")
+            .replace("linedef { v1 = 6; v2 = 7; sidefront = 1; }",
+            "linedef { v1 = 6; v2 = 7; sidefront = 1; special = 57; arg0 = 3; arg2 = 1; arg3 = 1; }")
+            .replace("linedef { v1 = 4; v2 = 5; sidefront = 1; id = 5; }",
+            "linedef { v1 = 4; v2 = 5; sidefront = 1; special = 57; }")
+            .replace("linedef { v1 = 0; v2 = 1; sidefront = 0; }",
+            "linedef { v1 = 0; v2 = 1; sidefront = 0; special = 57; arg3 = 1; }")
+            .replace("heightceiling = 128;", "heightceiling = 128; id = 3;");
+        let map = UDMFMap::from_str(&textmap).unwrap();
+        let graph = PortalGraph::for_map(&map, &WallOptions::default());
+        let ceiling = &graph.portals[graph.plane_portal(0, true).unwrap()];
+        assert_eq!(ceiling.kind, PortalKind::Visual);
+        assert_eq!(ceiling.destination, 1);
+        assert_eq!(ceiling.entrances, vec![PortalEntrance::Ceiling(0)]);
+        let point = ceiling.transform.transform_point3(Vec3::new(64., 32., 0.));
+        assert_eq!(point, Vec3::new(1088., 32., 0.));
+        let floor = &graph.portals[graph.plane_portal(1, false).unwrap()];
+        assert_eq!(floor.destination, 0);
+        assert_eq!(floor.entrances, vec![PortalEntrance::Floor(1)]);
+        assert!(floor.transform.abs_diff_eq(ceiling.transform.inverse(), 0.001));
+        // The sector portal replaces the skybox
+        assert_eq!(graph.portals[0].kind, PortalKind::Skybox);
+        assert_eq!(graph.plane_portal(0, true), Some(1));
+        assert_eq!(graph.links(&map)[1..], [
+            PortalLink { from: 0, to: 1, portal: 1 },
+            PortalLink { from: 1, to: 0, portal: 2 },
+        ]);
+
+        // The ceiling portal is only copied by lines for ceilings, or for
+        // any plane
+        let copy = |plane: i32| {
+            let map = UDMFMap::from_str(&textmap.replace(
+                "linedef { v1 = 7; v2 = 4; sidefront = 1; }",
+                &format!("linedef {{ v1 = 7; v2 = 4; sidefront = 1; special = 57; \
+                    arg1 = 1; arg2 = {plane}; arg3 = 3; }}"))).unwrap();
+            let graph = PortalGraph::for_map(&map, &WallOptions::default());
+            graph.portals[1].entrances.clone()
+        };
+        assert_eq!(copy(0), vec![PortalEntrance::Ceiling(0)]);
+        assert_eq!(copy(1), vec![PortalEntrance::Ceiling(0), PortalEntrance::Ceiling(1)]);
+        assert_eq!(copy(3), vec![PortalEntrance::Ceiling(0), PortalEntrance::Ceiling(1)]);
+    }
+}
@@ -0,0 +1,243 @@
+//! # Geometric predicates
+//!
+//! Which side of a line a point is on, and the other tests built on top of
+//! it, are used to decide how lines and polygons fit together. By default,
+//! these are worked out using `f32`, which can give the wrong answer when
+//! the points are close together but far away from the origin.
+//!
+//! With the `fixed-point` feature enabled, points are converted to fixed
+//! point numbers with 16 fractional bits, like the coordinates used by the
+//! Doom engine, and the tests are done exactly using integers. The integer
+//! part is 48 bits wide instead of 16, so points outside of the range of the
+//! original engine are still handled correctly.
+use std::cmp::Ordering;
+use glam::Vec2;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "fixed-point")] {
+        use fixed::types::I48F16;
+
+        /// A coordinate, as the bits of a fixed point number
+        pub(crate) type Coord = i64;
+        /// The product of two coordinates
+        pub(crate) type Product = i128;
+
+        fn to_coord(value: f32) -> Coord {
+            if value.is_nan() {
+                0
+            } else {
+                I48F16::saturating_from_num(value).to_bits()
+            }
+        }
+
+        fn from_coord(value: Coord) -> f32 {
+            I48F16::from_bits(value).to_num()
+        }
+
+        fn product(a: Coord, b: Coord) -> Product {
+            Product::from(a) * Product::from(b)
+        }
+
+        fn lerp(a: Coord, b: Coord, numerator: Product, denominator: Product) -> Coord {
+            // The numerator and denominator can be too big to multiply by
+            // (b - a) without overflowing, so f64 is used. The result is
+            // rounded to the nearest fixed point number.
+            let fraction = numerator as f64 / denominator as f64;
+            a + ((b - a) as f64 * fraction).round() as Coord
+        }
+    } else {
+        /// A coordinate
+        pub(crate) type Coord = f32;
+        /// The product of two coordinates
+        pub(crate) type Product = f32;
+
+        fn to_coord(value: f32) -> Coord {
+            value
+        }
+
+        fn from_coord(value: Coord) -> f32 {
+            value
+        }
+
+        fn product(a: Coord, b: Coord) -> Product {
+            a * b
+        }
+
+        fn lerp(a: Coord, b: Coord, numerator: Product, denominator: Product) -> Coord {
+            a + (b - a) * (numerator / denominator)
+        }
+    }
+}
+
+/// A point, with its coordinates converted to the type used for the
+/// predicates
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct Point {
+    pub x: Coord,
+    pub y: Coord,
+}
+
+impl Point {
+    pub fn new(point: Vec2) -> Point {
+        Point { x: to_coord(point.x), y: to_coord(point.y) }
+    }
+
+    pub fn to_vec2(self) -> Vec2 {
+        Vec2::new(from_coord(self.x), from_coord(self.y))
+    }
+
+    pub fn sub(self, other: Point) -> Point {
+        Point { x: self.x - other.x, y: self.y - other.y }
+    }
+
+    /// The 2D cross product of the two points, treated as vectors
+    pub fn cross(self, other: Point) -> Product {
+        product(self.x, other.y) - product(self.y, other.x)
+    }
+
+    /// The point `numerator / denominator` of the way from this point to the
+    /// other point
+    pub fn lerp(self, other: Point, numerator: Product, denominator: Product) -> Point {
+        Point {
+            x: lerp(self.x, other.x, numerator, denominator),
+            y: lerp(self.y, other.y, numerator, denominator),
+        }
+    }
+}
+
+/// Which side of the line going from `a` to `b` is `c` on?
+///
+/// Returns `Ordering::Greater` if `c` is on the left (`a`, `b` and `c` go
+/// counterclockwise), `Ordering::Less` if `c` is on the right (they go
+/// clockwise), and `Ordering::Equal` if the three points are on the same line.
+///
+/// # Example
+///
+/// ```
+/// use std::cmp::Ordering;
+/// use glam::Vec2;
+/// use map_to_3D::predicates::orientation;
+///
+/// let a = Vec2::new(0., 0.);
+/// let b = Vec2::new(64., 0.);
+/// assert_eq!(orientation(a, b, Vec2::new(32., 16.)), Ordering::Greater);
+/// assert_eq!(orientation(a, b, Vec2::new(32., -16.)), Ordering::Less);
+/// assert_eq!(orientation(a, b, Vec2::new(128., 0.)), Ordering::Equal);
+/// ```
+pub fn orientation(a: Vec2, b: Vec2, c: Vec2) -> Ordering {
+    point_orientation(Point::new(a), Point::new(b), Point::new(c))
+}
+
+pub(crate) fn point_orientation(a: Point, b: Point, c: Point) -> Ordering {
+    sign(b.sub(a).cross(c.sub(a)))
+}
+
+pub(crate) fn sign(value: Product) -> Ordering {
+    value.partial_cmp(&Product::default()).unwrap_or(Ordering::Equal)
+}
+
+/// Is `numerator / denominator` at least 0, and less than 1?
+pub(crate) fn fraction_in_unit(numerator: Product, denominator: Product) -> bool {
+    let zero = Product::default();
+    if denominator > zero {
+        numerator >= zero && numerator < denominator
+    } else if denominator < zero {
+        numerator <= zero && numerator > denominator
+    } else {
+        false
+    }
+}
+
+/// Is the point inside of the polygon? Points on the edges of the polygon
+/// may be counted as either inside or outside of it.
+///
+/// # Example
+///
+/// ```
+/// use glam::Vec2;
+/// use map_to_3D::predicates::point_in_polygon;
+///
+/// let triangle = [
+///     Vec2::new(0., 0.),
+///     Vec2::new(64., 0.),
+///     Vec2::new(0., 64.),
+/// ];
+/// assert!(point_in_polygon(Vec2::new(16., 16.), &triangle));
+/// assert!(!point_in_polygon(Vec2::new(48., 48.), &triangle));
+/// ```
+pub fn point_in_polygon(point: Vec2, polygon: &[Vec2]) -> bool {
+    // Based on https://wrf.ecse.rpi.edu/Research/Short_Notes/pnpoly.html
+    // The edges which cross the horizontal line going right from the point
+    // are counted. An edge going up crosses the line to the right of the
+    // point if the point is on the left of it, and vice versa.
+    let point = Point::new(point);
+    let mut inside = false;
+    let mut previous = polygon.last().copied().map(Point::new);
+    polygon.iter().copied().map(Point::new).for_each(|vertex| {
+        if let Some(previous) = previous {
+            if (vertex.y > point.y) != (previous.y > point.y) {
+                let upward = if vertex.y > previous.y {
+                    Ordering::Greater
+                } else {
+                    Ordering::Less
+                };
+                if point_orientation(previous, vertex, point) == upward {
+                    inside = !inside;
+                }
+            }
+        }
+        previous = Some(vertex);
+    });
+    inside
+}
+
+#[cfg(test)]
+// The coordinates in the tests are exactly representable as f32
+#[allow(clippy::excessive_precision)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orientation_far_away() {
+        // Nearly on the same line, a long way from the origin
+        let a = Vec2::new(30000., 30000.);
+        let b = Vec2::new(30064., 30032.);
+        let c = Vec2::new(30128., 30064.);
+        assert_eq!(orientation(a, b, c), Ordering::Equal);
+        let c = Vec2::new(30128., 30064.0625);
+        assert_eq!(orientation(a, b, c), Ordering::Greater);
+        let c = Vec2::new(30128., 30063.9375);
+        assert_eq!(orientation(a, b, c), Ordering::Less);
+    }
+
+    #[test]
+    fn point_in_concave_polygon() {
+        // A "U" shape
+        let polygon = [
+            Vec2::new(0., 0.),
+            Vec2::new(96., 0.),
+            Vec2::new(96., 96.),
+            Vec2::new(64., 96.),
+            Vec2::new(64., 32.),
+            Vec2::new(32., 32.),
+            Vec2::new(32., 96.),
+            Vec2::new(0., 96.),
+        ];
+        assert!(point_in_polygon(Vec2::new(16., 64.), &polygon));
+        assert!(point_in_polygon(Vec2::new(48., 16.), &polygon));
+        assert!(!point_in_polygon(Vec2::new(48., 64.), &polygon));
+        assert!(!point_in_polygon(Vec2::new(128., 16.), &polygon));
+    }
+
+    #[cfg(feature = "fixed-point")]
+    #[test]
+    fn fixed_point_is_exact() {
+        // f32 rounds the cross product of these to zero, so they look like
+        // they are on the same line, but they aren't.
+        let a = Vec2::new(-829633.875, 379267.4375);
+        let b = Vec2::new(719869.1875, -432849.0625);
+        let c = Vec2::new(2269372.25, -1244965.5);
+        assert_eq!(orientation(a, b, c), Ordering::Greater);
+        assert_eq!(orientation(a, c, b), Ordering::Less);
+    }
+}
@@ -0,0 +1,230 @@
+//! # Sector index
+//!
+//! Finds which sector a point is in. Testing every polygon of every sector
+//! is slow for big maps, so the polygons are put into a uniform grid, by
+//! their bounding boxes. Each cell of the grid lists the polygons whose
+//! bounding boxes overlap it, so only a few polygons need to be tested for
+//! each point.
+//!
+//! Holes are handled by counting how many of a sector's polygons the point
+//! is inside of. A point inside of a hole is inside of both the hole and the
+//! polygon around it, so the count is even, and the point isn't in the
+//! sector. Islands inside of holes work the same way.
+use glam::Vec2;
+use parsers::udmf::input::UDMFMap;
+use crate::boundingbox::BoundingBox;
+use crate::level;
+use crate::predicates::point_in_polygon;
+use crate::sectorpolygonbuilder::PolygonBuildResult;
+
+/// How many cells there can be along each side of the grid
+const MAX_CELLS_ACROSS: usize = 1024;
+
+/// A polygon of a sector, with the positions of its contour
+#[derive(Debug, Clone)]
+struct IndexedPolygon {
+    sector: usize,
+    contour: Vec<Vec2>,
+    bounds: BoundingBox,
+}
+
+/// A uniform grid of the polygons of each sector, for finding which sector
+/// a point is in.
+///
+/// # Example
+///
+/// ```
+/// use glam::Vec2;
+/// use parsers::udmf::input::UDMFMap;
+/// use map_to_3D::edit::{draw_sector, EditDefaults};
+/// use map_to_3D::sectorindex::SectorIndex;
+///
+/// let mut map = UDMFMap::default();
+/// let room = [
+///     Vec2::new(0., 0.),
+///     Vec2::new(0., 64.),
+///     Vec2::new(64., 64.),
+///     Vec2::new(64., 0.),
+/// ];
+/// draw_sector(&mut map, &room, &EditDefaults::default()).unwrap();
+/// let index = SectorIndex::new(&map);
+/// assert_eq!(index.sector_at(Vec2::new(32., 32.)), Some(0));
+/// assert_eq!(index.sector_at(Vec2::new(96., 32.)), None);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SectorIndex {
+    /// The polygons of every sector, in order of their sectors
+    polygons: Vec<IndexedPolygon>,
+    /// The box around every polygon, which the grid covers
+    bounds: BoundingBox,
+    cell_size: Vec2,
+    columns: usize,
+    rows: usize,
+    /// The indices of the polygons which overlap each cell, going along the
+    /// rows from the bottom left
+    cells: Vec<Vec<usize>>,
+}
+
+impl SectorIndex {
+    /// Build the polygons of every sector in the map, and index them
+    pub fn new(map: &UDMFMap) -> SectorIndex {
+        let polygons = level::build_sector_polygons(map);
+        SectorIndex::from_polygons(&polygons, &level::vertex_positions(map))
+    }
+
+    /// Index polygons which have already been built. `polygons` are the
+    /// polygons of each sector, as returned by
+    /// `level::build_sector_polygons`, and `vertices` are the positions of
+    /// the vertices they use.
+    pub fn from_polygons(polygons: &[PolygonBuildResult], vertices: &[Vec2]) -> SectorIndex {
+        let polygons: Vec<IndexedPolygon> = polygons.iter().enumerate()
+            .flat_map(|(sector, result)| result.polygons.iter().filter_map(move |polygon| {
+                let contour: Vec<Vec2> = polygon.vertices.iter()
+                    .map(|&index| vertices[index]).collect();
+                let bounds = BoundingBox::from_points(contour.iter().copied())?;
+                Some(IndexedPolygon { sector, contour, bounds })
+            }))
+            .collect();
+        let Some(bounds) = polygons.iter().map(|polygon| polygon.bounds)
+            .reduce(|a, b| a.union(&b)) else {
+            return SectorIndex::default();
+        };
+        // Aim for about one polygon per cell
+        let size = bounds.size();
+        let across = (polygons.len() as f32).sqrt().ceil();
+        // Long sides of the grid get more cells than short ones
+        let cells_along = |length: f32, other: f32| -> usize {
+            let share = if length + other > 0. { length / (length + other) * 2. } else { 1. };
+            ((across * share).ceil() as usize).clamp(1, MAX_CELLS_ACROSS)
+        };
+        let columns = cells_along(size.x, size.y);
+        let rows = cells_along(size.y, size.x);
+        let mut index = SectorIndex {
+            cell_size: (size / Vec2::new(columns as f32, rows as f32)).max(Vec2::splat(f32::EPSILON)),
+            bounds,
+            columns,
+            rows,
+            cells: vec![Vec::new(); columns * rows],
+            polygons: Vec::new(),
+        };
+        polygons.iter().enumerate().for_each(|(polygon, data)| {
+            let (left, bottom) = index.cell(data.bounds.min());
+            let (right, top) = index.cell(data.bounds.max());
+            (bottom..=top).for_each(|row| (left..=right).for_each(|column| {
+                index.cells[row * columns + column].push(polygon);
+            }));
+        });
+        index.polygons = polygons;
+        index
+    }
+
+    /// Get the column and row of the cell the point is in, clamped to the
+    /// grid
+    fn cell(&self, point: Vec2) -> (usize, usize) {
+        let offset = (point - self.bounds.min()) / self.cell_size;
+        // Casting saturates, so points below the grid are in the first cell
+        let column = (offset.x as usize).min(self.columns - 1);
+        let row = (offset.y as usize).min(self.rows - 1);
+        (column, row)
+    }
+
+    /// Get the index of the sector which the point is inside of. If sectors
+    /// overlap, the first one is returned.
+    pub fn sector_at(&self, point: Vec2) -> Option<usize> {
+        if self.cells.is_empty() || !self.bounds.is_inside(point) {
+            return None;
+        }
+        let (column, row) = self.cell(point);
+        // The polygons in each cell are in order of their sectors, so the
+        // polygons of each sector are next to each other.
+        let mut current: Option<(usize, bool)> = None;
+        for &polygon in &self.cells[row * self.columns + column] {
+            let data = &self.polygons[polygon];
+            match current {
+                Some((sector, true)) if sector != data.sector => return Some(sector),
+                Some((sector, _)) if sector == data.sector => (),
+                _ => current = Some((data.sector, false)),
+            }
+            if data.bounds.is_inside(point) && point_in_polygon(point, &data.contour) {
+                current = current.map(|(sector, inside)| (sector, !inside));
+            }
+        }
+        current.and_then(|(sector, inside)| inside.then_some(sector))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::edit::{self, testmap::make_map};
+
+    // A 256x256 sector, with a hole in it which is another sector, and an
+    // island of the first sector inside of the hole
+    const TEXTMAP: &str = r#"namespace = "zdoom";
+vertex { x = 0.0; y = 0.0; }
+vertex { x = 0.0; y = 256.0; }
+vertex { x = 256.0; y = 256.0; }
+vertex { x = 256.0; y = 0.0; }
+vertex { x = 64.0; y = 64.0; }
+vertex { x = 64.0; y = 192.0; }
+vertex { x = 192.0; y = 192.0; }
+vertex { x = 192.0; y = 64.0; }
+vertex { x = 96.0; y = 96.0; }
+vertex { x = 96.0; y = 160.0; }
+vertex { x = 160.0; y = 160.0; }
+vertex { x = 160.0; y = 96.0; }
+linedef { v1 = 0; v2 = 1; sidefront = 0; }
+linedef { v1 = 1; v2 = 2; sidefront = 0; }
+linedef { v1 = 2; v2 = 3; sidefront = 0; }
+linedef { v1 = 3; v2 = 0; sidefront = 0; }
+linedef { v1 = 4; v2 = 5; sidefront = 1; sideback = 0; }
+linedef { v1 = 5; v2 = 6; sidefront = 1; sideback = 0; }
+linedef { v1 = 6; v2 = 7; sidefront = 1; sideback = 0; }
+linedef { v1 = 7; v2 = 4; sidefront = 1; sideback = 0; }
+linedef { v1 = 8; v2 = 9; sidefront = 0; sideback = 1; }
+linedef { v1 = 9; v2 = 10; sidefront = 0; sideback = 1; }
+linedef { v1 = 10; v2 = 11; sidefront = 0; sideback = 1; }
+linedef { v1 = 11; v2 = 8; sidefront = 0; sideback = 1; }
+sidedef { sector = 0; }
+sidedef { sector = 1; }
+sector { texturefloor = "FLAT1"; textureceiling = "FLAT1"; }
+sector { texturefloor = "FLAT1"; textureceiling = "FLAT1"; }
+"#;
+
+    #[test]
+    fn holes_and_islands() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let index = SectorIndex::new(&map);
+        assert_eq!(index.sector_at(Vec2::new(32., 32.)), Some(0));
+        assert_eq!(index.sector_at(Vec2::new(80., 128.)), Some(1));
+        assert_eq!(index.sector_at(Vec2::new(128., 128.)), Some(0));
+        assert_eq!(index.sector_at(Vec2::new(300., 128.)), None);
+        assert_eq!(index.sector_at(Vec2::new(-1., 128.)), None);
+        assert_eq!(SectorIndex::default().sector_at(Vec2::ZERO), None);
+    }
+
+    #[test]
+    fn same_as_testing_every_polygon() {
+        // A grid of 8x8 square sectors, with a gap in the middle
+        let size = 64.;
+        let across = 8;
+        let mut vertices = Vec::new();
+        let mut lines = Vec::new();
+        (0..across * across).filter(|&square| square != 27).for_each(|square| {
+            let corner = Vec2::new((square % across) as f32, (square / across) as f32) * size;
+            let first = vertices.len();
+            vertices.extend([(0., 0.), (0., size), (size, size), (size, 0.)]
+                .map(|(x, y)| (corner.x + x, corner.y + y)));
+            let sector = lines.len() / 4;
+            lines.extend((0..4).map(|i| (first + i, first + (i + 1) % 4, sector, None)));
+        });
+        let map = make_map(&vertices, &lines);
+        let index = SectorIndex::new(&map);
+        (0..64).for_each(|x| (0..64).for_each(|y| {
+            let point = Vec2::new(x as f32 * 9. - 20., y as f32 * 9. - 20.);
+            assert_eq!(index.sector_at(point), edit::sector_at(&map, point),
+                "{point}");
+        }));
+    }
+}
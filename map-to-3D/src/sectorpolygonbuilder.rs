@@ -13,10 +13,15 @@ mod tests;
 mod vertex;
 mod util;
 mod angle;
+mod preprocess;
+mod graph;
+mod containment;
 
+pub use preprocess::{preprocess, Preprocessed};
+pub use containment::ContainmentTree;
 use util::*;
 use angle::Angle;
-use vertex::MapVertex;
+use graph::EdgeGraph;
 
 // Ported from https://github.com/pineapplemachine/jsdoom/blob/6dbc5540b8c7fd4a2c61dac9323fe0e77a51ddc6/src/convert/3DMapBuilder.ts#L117
 
@@ -33,6 +38,36 @@ pub struct SectorPolygon {
     pub hole_of: Option<usize>
 }
 
+impl SectorPolygon {
+    /// Is the given point inside this polygon's contour?
+    /// 
+    /// This only checks the contour itself, so it does not take any holes of
+    /// this polygon into account.
+    /// 
+    /// # Example
+    /// 
+    /// ```
+    /// use glam::Vec2;
+    /// use map_to_3D::sectorpolygonbuilder::SectorPolygon;
+    /// 
+    /// let vertices = vec![
+    ///     Vec2::new(1.0, 1.0),
+    ///     Vec2::new(1.0, 0.0),
+    ///     Vec2::new(0.0, 0.0),
+    ///     Vec2::new(0.0, 1.0),
+    /// ];
+    /// let square = SectorPolygon { vertices: vec![0, 1, 2, 3], hole_of: None };
+    /// assert!(square.contains(Vec2::new(0.5, 0.5), &vertices));
+    /// assert!(!square.contains(Vec2::new(1.5, 0.5), &vertices));
+    /// ```
+    pub fn contains(&self, point: Vec2, vertices: &[Vec2]) -> bool {
+        let contour: Vec<Vec2> = self.vertices.iter()
+            .map(|&index| vertices[index])
+            .collect();
+        point_in_polygon(point, &contour)
+    }
+}
+
 /// Build polygon contours from a set of lines and vertices.
 /// 
 /// Returns the polygon contours as a vector of vectors of contour vertex
@@ -40,6 +75,14 @@ pub struct SectorPolygon {
 /// optional indices of the first vector.
 /// These can be used by a triangulator such as `earcut`.
 /// 
+/// The lines are only the outline of an area, so they don't say which side of
+/// them the area is on. Lines which have the area on both sides of them must
+/// be left out, and the lines can't divide the area into parts: where a line
+/// meets the outline, making a vertex with an odd number of lines, the
+/// contours going through that vertex are left incomplete. Callers which
+/// divide areas, like `edit::split_sector`, have to build each part from its
+/// own outline.
+/// 
 /// # Examples
 /// 
 /// A square:
@@ -169,27 +212,154 @@ pub struct SectorPolygon {
 /// it serves as an example of the kind of data you'll be passing into
 /// `build_polygons`
 /// 
-/// # Panics
+/// # Overlapping lines
 /// 
-/// If there are two lines/edges overlapping each other, they could cause
-/// angle_between to panic because the angle between them is -0
+/// Lines which overlap or cross each other, or which end in the middle of
+/// another line, will not make sensible polygons. Use `preprocess` to clean
+/// them up first. Edges using vertices which don't exist are ignored.
 pub fn build_polygons(
     lines: &[Edge],
     vertices: &[Vec2]
 ) -> Vec<SectorPolygon> {
+    build_polygons_detailed(lines, vertices).polygons
+}
+
+/// A chain of edges which could not be closed into a polygon
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct IncompleteChain {
+    /// The indices of the vertices along the chain, in the order they were
+    /// traced
+    pub vertices: Vec<EdgeVertexIndex>,
+    /// The vertex where tracing stopped, because there were no more edges to
+    /// follow
+    pub stopped_at: EdgeVertexIndex,
+}
+
+/// The polygons built from a set of lines, along with the problems found
+/// while building them
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct PolygonBuildResult {
+    /// The complete polygons, the same as the ones `build_polygons` returns
+    pub polygons: Vec<SectorPolygon>,
+    /// The chains of edges which could not be closed
+    pub incomplete: Vec<IncompleteChain>,
+    /// Edges which lead to a dead end, so they can't be part of any closed
+    /// polygon
+    pub dangling_edges: Vec<Edge>,
+    /// Edges which were given more than once
+    pub duplicate_edges: Vec<Edge>,
+    /// How the complete polygons are nested inside of each other
+    pub containment: ContainmentTree,
+}
+
+impl PolygonBuildResult {
+    /// Are all of the lines part of closed polygons, with no duplicates?
+    pub fn is_closed(&self) -> bool {
+        self.incomplete.is_empty() &&
+        self.dangling_edges.is_empty() &&
+        self.duplicate_edges.is_empty()
+    }
+}
+
+/// Build polygon contours from a set of lines and vertices, like
+/// `build_polygons`, and also find out where the lines don't form closed
+/// polygons.
+///
+/// # Example
+///
+/// ```
+/// use glam::Vec2;
+/// use map_to_3D::edge::Edge;
+/// use map_to_3D::sectorpolygonbuilder as spb;
+///
+/// // 1--2
+/// // |
+/// // 0
+///
+/// let vertices = vec![
+///     Vec2::new(0.0, 0.0),
+///     Vec2::new(0.0, 1.0),
+///     Vec2::new(1.0, 1.0),
+/// ];
+/// let lines = vec![Edge::new(0, 1), Edge::new(1, 2), Edge::new(1, 2)];
+/// let result = spb::build_polygons_detailed(&lines, &vertices);
+/// assert!(result.polygons.is_empty());
+/// assert!(!result.is_closed());
+/// assert_eq!(result.dangling_edges, vec![Edge::new(0, 1), Edge::new(1, 2)]);
+/// assert_eq!(result.duplicate_edges, vec![Edge::new(1, 2)]);
+/// ```
+pub fn build_polygons_detailed(
+    lines: &[Edge],
+    vertices: &[Vec2]
+) -> PolygonBuildResult {
+    let (mut polygons, incomplete) = trace_polygons(lines, vertices);
+    // The holes found while tracing are only a first guess, and can be wrong
+    // when holes and islands are nested more than one level deep.
+    let containment = ContainmentTree::new(&polygons, vertices);
+    polygons.iter_mut().enumerate().for_each(|(index, polygon)| {
+        polygon.hole_of = containment.hole_of(index);
+    });
+    let incomplete = incomplete.into_iter().map(|polygon| IncompleteChain {
+        stopped_at: *polygon.vertices.last()
+            .expect("A polygon should have at least one edge (two vertices)"),
+        vertices: polygon.vertices,
+    }).collect();
+    let (dangling_edges, duplicate_edges) = find_bad_edges(lines);
+    PolygonBuildResult {
+        polygons, incomplete, dangling_edges, duplicate_edges, containment
+    }
+}
+
+/// Find the edges which lead to dead ends, and the edges which were given more
+/// than once.
+fn find_bad_edges(lines: &[Edge]) -> (Vec<Edge>, Vec<Edge>) {
+    let mut duplicates: Vec<Edge> = Vec::new();
+    let mut unique: HashSet<Edge, RandomState> = HashSet::default();
+    lines.iter().for_each(|&line| {
+        if !unique.insert(line) && !duplicates.contains(&line) {
+            duplicates.push(line);
+        }
+    });
+    let mut degree: HashMap<EdgeVertexIndex, usize, RandomState> = HashMap::default();
+    unique.iter().flat_map(|edge| edge.iter()).for_each(|vertex| {
+        *degree.entry(vertex).or_default() += 1;
+    });
+    // Keep removing edges with an unconnected end, so that the whole dead end
+    // is found, and not just its last edge.
+    let mut dangling: Vec<Edge> = Vec::new();
+    loop {
+        let ends: Vec<Edge> = unique.iter().copied()
+            .filter(|edge| edge.iter().any(|vertex| degree[&vertex] < 2))
+            .collect();
+        if ends.is_empty() { break; }
+        ends.into_iter().for_each(|edge| {
+            unique.remove(&edge);
+            edge.iter().for_each(|vertex| {
+                *degree.get_mut(&vertex).unwrap() -= 1;
+            });
+            dangling.push(edge);
+        });
+    }
+    dangling.sort_unstable();
+    (dangling, duplicates)
+}
+
+/// Trace the polygon contours, and return the complete polygons, and the
+/// polygons which could not be closed.
+fn trace_polygons(
+    lines: &[Edge],
+    vertices: &[Vec2]
+) -> (Vec<SectorPolygon>, Vec<SectorPolygon>) {
     // jsdoom's SectorPolygonBuilder takes care of duplicate vertices and
     // edges in its constructor. For this project, duplicate vertices and
     // edges should be taken care of when the level is being pre-processed.
-    let mut edges_used: HashMap<Edge, bool, RandomState> = HashMap::default();
-    lines.iter().for_each(|&line| {
-        edges_used.insert(line, false);
-    });
-    let first_edge = match find_next_start_edge(false, &edges_used, vertices) {
+    let mut edges = EdgeGraph::new(lines, vertices);
+    let first_edge = match find_next_start_edge(false, &mut edges, vertices) {
         Some(edge) => edge,
-        None => return vec![]
+        None => return (vec![], vec![])
     };
     // let edge_count = edges_used.len();
-    edges_used.insert(Edge::from(first_edge), true);
+    edges.use_edge(Edge::from(first_edge));
     let mut polygons: Vec<SectorPolygon> = Vec::with_capacity(16);
     polygons.push(SectorPolygon {
         vertices: {
@@ -214,13 +384,13 @@ pub fn build_polygons(
             .expect("A polygon should have at least one edge (two vertices)");
         let next_vertex = find_next_vertex(
             &current_vertex, &previous_vertex,
-            clockwise, &edges_used, vertices
+            clockwise, &edges, vertices
         );
         let mut new_polygon = false;
         match next_vertex {
             Some(vertex) => {
                 let edge = Edge::new(current_vertex, vertex);
-                edges_used.insert(edge, true);
+                edges.use_edge(edge);
                 if is_polygon_complete(&polygons.last().unwrap().vertices, vertex) {
                     new_polygon = true;
                     bounding_boxes.push({
@@ -265,9 +435,9 @@ pub fn build_polygons(
         };
         if new_polygon {
             if let Some(first_edge) =
-                find_next_start_edge(clockwise, &edges_used, vertices) {
+                find_next_start_edge(clockwise, &mut edges, vertices) {
                 let edge = Edge::from(first_edge);
-                edges_used.insert(edge, true);
+                edges.use_edge(edge);
                 let mut inside_polygon_index: Option<usize> = None;
                 clockwise = false;
                 polygons.iter().zip(bounding_boxes.iter()).enumerate()
@@ -299,31 +469,18 @@ pub fn build_polygons(
             }
         }
     }
-    polygons
+    (polygons, incomplete_polygons)
 }
 
 fn find_next_start_edge(
     clockwise: bool,  // Polygon's interior angles should be clockwise or not?
-    edges: &HashMap<Edge, bool, RandomState>,
+    edges: &mut EdgeGraph,
     vertices: &[Vec2]
 ) -> Option<(EdgeVertexIndex, EdgeVertexIndex)> {
-    // Filter out used edges
-    let usable_edges: HashMap<&Edge, &bool> = edges.iter()
-        .filter(|(&_key, &val)| val == false)
-        .collect();
-    let rightmost_vertex_index = usable_edges.keys()
-        // Find usable vertices by destructuring the edges
-        .fold(HashSet::<EdgeVertexIndex, RandomState>::default(), |mut set, &edge| {
-            edge.iter().for_each(|vertex_index| {
-                set.insert(vertex_index);
-            });
-            set
-        // Convert indices to vertices
-        }).into_iter().map(|i| MapVertex {p: vertices[i], i}).max()?.i;
+    let rightmost_vertex_index = edges.rightmost_vertex()?;
     let rightmost_vertex = vertices[rightmost_vertex_index];
-    let other_vertex = usable_edges.keys()
-        .filter(|&key| key.contains(rightmost_vertex_index))
-        .map(|&edge| edge.other_unchecked(rightmost_vertex_index))
+    let other_vertex = edges.neighbours(rightmost_vertex_index).iter()
+        .copied()
         .reduce(|current_index, other_index| {
             // To ensure the interior angle is counterclockwise, pick the
             // connected vertex with the lowest angle. Necessary for proper
@@ -350,7 +507,7 @@ fn find_next_vertex(
     from: &EdgeVertexIndex,
     previous: &EdgeVertexIndex,
     clockwise: bool,
-    edges: &HashMap<Edge, bool, RandomState>,
+    edges: &EdgeGraph,
     vertices: &[Vec2]
 ) -> Option<EdgeVertexIndex> {
     let from = from.clone();
@@ -359,14 +516,10 @@ fn find_next_vertex(
     // - Have not been added to a polygon
     // - Are attached to the "from" vertex
     // - Are not the "previous" vertex
-    let usable_vertices: Vec<EdgeVertexIndex> = edges.iter()
-        .filter_map(|(&key, &val)|
-            if val == false && key.contains(from) && !key.contains(previous) {
-                Some(key.other_unchecked(from))
-            } else {
-                None
-            }
-        ).collect();
+    let usable_vertices: Vec<EdgeVertexIndex> = edges.neighbours(from).iter()
+        .copied()
+        .filter(|&vertex| vertex != previous)
+        .collect();
     if usable_vertices.len() == 0 { return None; }
     if usable_vertices.len() == 1 { return Some(usable_vertices[0]); }
     // Find the vertex with the lowest angle in comparison to "from"
@@ -423,9 +576,8 @@ pub fn triangulate(
     let mut cur_hole = polygon.vertices.len();
     let hole_indices: Vec<usize> = iter::once(cur_hole)
         .chain(holes.iter().map(|h| {
-            let rv = h.vertices.len() + cur_hole;
-            cur_hole += rv;
-            rv
+            cur_hole += h.vertices.len();
+            cur_hole
         })).take(holes.len()).collect();
     earcutr::earcut(&vertex_pos, &hole_indices, 2)
         .unwrap_or_default()
@@ -436,20 +588,22 @@ pub fn triangulate(
 /// Triangulate all of the polygons in the list
 /// 
 /// Returns a vector containing either a vector of triangle vertex indices for
-/// each polygon, or nothing (None) if the polygon is a hole
+/// each polygon, or nothing (None) if the polygon is a hole. Which polygons
+/// are holes is worked out using a `ContainmentTree`, so islands inside of
+/// holes, and holes inside of those islands, are triangulated correctly.
 pub fn auto_triangulate(
     polygons: &[SectorPolygon],
     vertices: &[Vec2]
 ) -> Vec<Option<Vec<EdgeVertexIndex>>> {
+    let containment = ContainmentTree::new(polygons, vertices);
     polygons.iter().enumerate()
     .map(|(i, pl)| {
-        if pl.hole_of.is_none() {
-            let holes: Vec<&SectorPolygon> = polygons.iter().filter(|pl| {
-                pl.hole_of == Some(i)
-            }).collect();
-            Some(triangulate(pl, &holes, vertices))
-        } else {
+        if containment.is_hole(i) {
             None
+        } else {
+            let holes: Vec<&SectorPolygon> = containment.holes(i).iter()
+                .map(|&hole| &polygons[hole]).collect();
+            Some(triangulate(pl, &holes, vertices))
         }
     }).collect()
 }
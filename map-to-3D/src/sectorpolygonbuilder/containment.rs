@@ -0,0 +1,128 @@
+//! # Containment tree
+//!
+//! Works out which polygons are inside of which other polygons, so that
+//! holes, islands inside of holes, holes inside of those islands, and so on,
+//! can be told apart.
+use glam::Vec2;
+use crate::boundingbox::BoundingBox;
+use super::SectorPolygon;
+use super::util::point_in_polygon;
+
+/// How polygons are nested inside of each other.
+///
+/// Polygons which are inside of an even number of other polygons (including
+/// zero) are solid, and the polygons directly inside of them are their holes.
+/// Polygons inside of an odd number of other polygons are holes.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ContainmentTree {
+    /// The polygon which each polygon is directly inside of, if any
+    pub parents: Vec<Option<usize>>,
+    /// The polygons which are directly inside of each polygon
+    pub children: Vec<Vec<usize>>,
+    /// How many other polygons each polygon is inside of
+    pub depths: Vec<usize>,
+}
+
+impl ContainmentTree {
+    /// Work out how the given polygons are nested inside of each other. The
+    /// `hole_of` field of the polygons is not used.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use glam::Vec2;
+    /// use map_to_3D::sectorpolygonbuilder::{ContainmentTree, SectorPolygon};
+    ///
+    /// // Three squares inside of each other
+    /// let vertices: Vec<Vec2> = [3., 2., 1.].into_iter().flat_map(|size| [
+    ///     Vec2::new(size, size),
+    ///     Vec2::new(size, -size),
+    ///     Vec2::new(-size, -size),
+    ///     Vec2::new(-size, size),
+    /// ]).collect();
+    /// let polygons: Vec<SectorPolygon> = (0..3).map(|square| SectorPolygon {
+    ///     vertices: (square * 4..square * 4 + 4).collect(),
+    ///     hole_of: None,
+    /// }).collect();
+    /// let tree = ContainmentTree::new(&polygons, &vertices);
+    /// assert_eq!(tree.parents, vec![None, Some(0), Some(1)]);
+    /// assert!(!tree.is_hole(0));
+    /// assert!(tree.is_hole(1));
+    /// assert!(!tree.is_hole(2));
+    /// assert_eq!(tree.hole_of(1), Some(0));
+    /// assert_eq!(tree.hole_of(2), None);
+    /// ```
+    pub fn new(polygons: &[SectorPolygon], vertices: &[Vec2]) -> ContainmentTree {
+        let contours: Vec<Vec<Vec2>> = polygons.iter().map(|polygon| {
+            polygon.vertices.iter().map(|&index| vertices[index]).collect()
+        }).collect();
+        let areas: Vec<f32> = contours.iter()
+            .map(|contour| signed_area(contour).abs()).collect();
+        let boxes: Vec<BoundingBox> = contours.iter()
+            .map(|contour| BoundingBox::from_points(contour.iter().copied())
+                .unwrap_or_default())
+            .collect();
+        let parents: Vec<Option<usize>> = contours.iter().enumerate()
+            .map(|(index, contour)| {
+            let point = interior_point(contour)?;
+            (0..contours.len())
+                .filter(|&other| other != index && areas[other] > areas[index])
+                .filter(|&other| boxes[other].is_inside(point))
+                .filter(|&other| point_in_polygon(point, &contours[other]))
+                .min_by(|&a, &b| areas[a].total_cmp(&areas[b]))
+        }).collect();
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); polygons.len()];
+        parents.iter().enumerate().for_each(|(index, parent)| {
+            if let Some(parent) = parent {
+                children[*parent].push(index);
+            }
+        });
+        // Parents are always bigger than their children, so there can't be
+        // any loops.
+        let depths = (0..polygons.len()).map(|index| {
+            std::iter::successors(parents[index], |&parent| parents[parent])
+                .count()
+        }).collect();
+        ContainmentTree { parents, children, depths }
+    }
+
+    /// Is the polygon a hole?
+    pub fn is_hole(&self, polygon: usize) -> bool {
+        self.depths[polygon] % 2 == 1
+    }
+
+    /// Get the polygon which the given polygon is a hole of, if it is a hole.
+    pub fn hole_of(&self, polygon: usize) -> Option<usize> {
+        self.parents[polygon].filter(|_| self.is_hole(polygon))
+    }
+
+    /// Get the holes of the given polygon, if it is solid.
+    pub fn holes(&self, polygon: usize) -> &[usize] {
+        if self.is_hole(polygon) { &[] } else { &self.children[polygon] }
+    }
+}
+
+/// Twice the signed area of the contour. Counterclockwise contours have a
+/// positive area.
+fn signed_area(contour: &[Vec2]) -> f32 {
+    contour.iter().zip(contour.iter().cycle().skip(1))
+        .map(|(a, b)| a.perp_dot(*b)).sum()
+}
+
+/// Find a point which is inside of the contour, and not on its edge. Points
+/// just beside the middle of each edge are tried, since polygons can share
+/// vertices and edges with the polygons they are inside of.
+fn interior_point(contour: &[Vec2]) -> Option<Vec2> {
+    let area = signed_area(contour);
+    if area == 0. || !area.is_finite() {
+        return None;
+    }
+    contour.iter().zip(contour.iter().cycle().skip(1)).find_map(|(&a, &b)| {
+        // The left side of a counterclockwise contour is inside of it
+        let inward = (b - a).perp() * area.signum();
+        let middle = (a + b) / 2.;
+        [1. / 64., 1. / 1024.].into_iter()
+            .map(|distance| middle + inward * distance)
+            .find(|&point| point_in_polygon(point, contour))
+    })
+}
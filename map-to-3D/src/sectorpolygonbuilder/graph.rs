@@ -0,0 +1,100 @@
+//! # Edge graph
+//!
+//! Keeps track of which edges haven't been added to a polygon yet, so that
+//! the edges connected to a vertex, and the rightmost vertex which still has
+//! unused edges, can be found without going through all of the edges.
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use glam::Vec2;
+use ahash::RandomState;
+use crate::edge::{Edge, EdgeVertexIndex};
+use super::vertex::MapVertex;
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct EdgeGraph {
+    /// The vertices connected to each vertex by unused edges, in the order
+    /// the edges were given
+    adjacent: HashMap<EdgeVertexIndex, Vec<EdgeVertexIndex>, RandomState>,
+    /// The vertices which had unused edges, with the rightmost vertex at the
+    /// top. Vertices whose edges have all been used are removed when they
+    /// reach the top.
+    rightmost: BinaryHeap<MapVertex>,
+}
+
+impl EdgeGraph {
+    /// Make a graph of the given edges. Duplicate edges, and edges using
+    /// vertices which don't exist, are left out.
+    pub fn new(edges: &[Edge], vertices: &[Vec2]) -> EdgeGraph {
+        let mut graph = EdgeGraph::default();
+        let mut seen: HashSet<Edge, RandomState> = HashSet::default();
+        edges.iter()
+            .filter(|edge| edge.hi() < vertices.len() && seen.insert(**edge))
+            .for_each(|edge| {
+            graph.adjacent.entry(edge.lo()).or_default().push(edge.hi());
+            graph.adjacent.entry(edge.hi()).or_default().push(edge.lo());
+        });
+        graph.rightmost = graph.adjacent.keys()
+            .map(|&i| MapVertex { p: vertices[i], i })
+            .collect();
+        graph
+    }
+
+    /// Get the vertices connected to the given vertex by unused edges
+    pub fn neighbours(&self, vertex: EdgeVertexIndex) -> &[EdgeVertexIndex] {
+        self.adjacent.get(&vertex).map_or(&[], Vec::as_slice)
+    }
+
+    /// Mark the edge as used
+    pub fn use_edge(&mut self, edge: Edge) {
+        let mut remove = |from: EdgeVertexIndex, to: EdgeVertexIndex| {
+            if let Some(neighbours) = self.adjacent.get_mut(&from) {
+                if let Some(index) = neighbours.iter().position(|&n| n == to) {
+                    neighbours.remove(index);
+                }
+            }
+        };
+        remove(edge.lo(), edge.hi());
+        remove(edge.hi(), edge.lo());
+    }
+
+    /// Get the rightmost vertex which has unused edges
+    pub fn rightmost_vertex(&mut self) -> Option<EdgeVertexIndex> {
+        while let Some(vertex) = self.rightmost.peek() {
+            if !self.neighbours(vertex.i).is_empty() {
+                return Some(vertex.i);
+            }
+            self.rightmost.pop();
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn use_edges() {
+        let vertices = vec![
+            Vec2::new(0., 0.),
+            Vec2::new(64., 0.),
+            Vec2::new(32., 32.),
+        ];
+        let edges = vec![
+            Edge::new(0, 1),
+            Edge::new(1, 2),
+            Edge::new(2, 0),
+            Edge::new(1, 0),
+            Edge::new(2, 5),
+        ];
+        let mut graph = EdgeGraph::new(&edges, &vertices);
+        assert_eq!(graph.neighbours(0), &[1, 2]);
+        assert!(graph.neighbours(5).is_empty());
+        assert_eq!(graph.rightmost_vertex(), Some(1));
+        graph.use_edge(Edge::new(0, 1));
+        graph.use_edge(Edge::new(1, 2));
+        assert!(graph.neighbours(1).is_empty());
+        assert_eq!(graph.rightmost_vertex(), Some(2));
+        graph.use_edge(Edge::new(0, 2));
+        assert_eq!(graph.rightmost_vertex(), None);
+    }
+}
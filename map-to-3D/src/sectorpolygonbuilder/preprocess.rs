@@ -0,0 +1,245 @@
+//! # Edge preprocessing
+//!
+//! Lines in real maps overlap each other, cross each other, end in the middle
+//! of other lines, and have vertices which are almost, but not quite, in the
+//! same place. The polygon builder can't make sense of these, so they are
+//! cleaned up first.
+use glam::Vec2;
+use crate::edge::{Edge, EdgeVertexIndex};
+use crate::segment::{Segment, Intersection};
+use std::collections::HashMap;
+use ahash::RandomState;
+use super::{SectorPolygon, PolygonBuildResult};
+
+/// Edges which have been cleaned up, so that polygons can be built from them
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Preprocessed {
+    /// The cleaned up edges
+    pub edges: Vec<Edge>,
+    /// The original vertices, followed by the vertices which were added where
+    /// edges were split. The original vertices keep their indices.
+    pub vertices: Vec<Vec2>,
+}
+
+impl Preprocessed {
+    /// Build the polygons from the cleaned up edges
+    pub fn build_polygons(&self) -> Vec<SectorPolygon> {
+        super::build_polygons(&self.edges, &self.vertices)
+    }
+
+    /// Build the polygons from the cleaned up edges, along with information
+    /// about where the edges don't form closed polygons.
+    pub fn build_polygons_detailed(&self) -> PolygonBuildResult {
+        super::build_polygons_detailed(&self.edges, &self.vertices)
+    }
+}
+
+/// Finds vertices close to a point, by putting them into a grid of cells
+/// which are as big as the weld distance.
+struct Welder {
+    distance: f32,
+    cell_size: f32,
+    cells: HashMap<(i64, i64), Vec<EdgeVertexIndex>, RandomState>,
+}
+
+impl Welder {
+    fn new(distance: f32) -> Welder {
+        Welder {
+            distance,
+            cell_size: distance.max(f32::EPSILON),
+            cells: HashMap::default(),
+        }
+    }
+
+    fn cell(&self, point: Vec2) -> (i64, i64) {
+        let cell = (point / self.cell_size).floor();
+        (cell.x as i64, cell.y as i64)
+    }
+
+    /// Find the vertex with the lowest index within the weld distance of the
+    /// point
+    fn find(&self, point: Vec2, vertices: &[Vec2]) -> Option<EdgeVertexIndex> {
+        let (x, y) = self.cell(point);
+        (x.saturating_sub(1)..=x.saturating_add(1))
+            .flat_map(|x| (y.saturating_sub(1)..=y.saturating_add(1))
+                .map(move |y| (x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(|&index| vertices[index].distance(point) <= self.distance)
+            .min()
+    }
+
+    fn insert(&mut self, index: EdgeVertexIndex, point: Vec2) {
+        self.cells.entry(self.cell(point)).or_default().push(index);
+    }
+
+    /// Find a vertex within the weld distance of the point, or add a new one.
+    fn find_or_add(&mut self, point: Vec2, vertices: &mut Vec<Vec2>) -> EdgeVertexIndex {
+        self.find(point, vertices).unwrap_or_else(|| {
+            vertices.push(point);
+            self.insert(vertices.len() - 1, point);
+            vertices.len() - 1
+        })
+    }
+}
+
+/// Is the point on the segment (no further than `tolerance` away from it),
+/// but further than `tolerance` away from either end?
+fn on_segment_interior(point: Vec2, segment: Segment, tolerance: f32) -> bool {
+    let Segment(a, b) = segment;
+    let ab = b - a;
+    let length = ab.length();
+    if length <= tolerance * 2. {
+        return false;
+    }
+    let along = (point - a).dot(ab) / length;
+    let across = (point - a).perp_dot(ab) / length;
+    across.abs() <= tolerance && along > tolerance && along < length - tolerance
+}
+
+/// Clean up the edges, so that `build_polygons` can build polygons from them
+/// without getting confused.
+///
+/// - Edges using vertices which don't exist, or which have coordinates which
+///   aren't finite, are removed.
+/// - Vertices closer together than `weld_distance` are welded together, using
+///   the vertex with the lowest index.
+/// - Edges which end up with no length, and duplicate edges, are removed.
+/// - Edges which cross each other, overlap each other, or have another edge
+///   ending in the middle of them, are split. New vertices are added where
+///   edges cross each other.
+///
+/// # Example
+///
+/// ```
+/// use glam::Vec2;
+/// use map_to_3D::edge::Edge;
+/// use map_to_3D::sectorpolygonbuilder as spb;
+///
+/// // 3--2--5
+/// // |  |  |
+/// // 0--1--4
+/// // The middle line (1, 2) is missing, and line (0, 4) overlaps the two
+/// // bottom lines of the squares.
+///
+/// let vertices = vec![
+///     Vec2::new(0., 0.),
+///     Vec2::new(64., 0.),
+///     Vec2::new(64., 64.),
+///     Vec2::new(0., 64.),
+///     Vec2::new(128., 0.),
+///     Vec2::new(128., 64.),
+/// ];
+/// let lines = vec![
+///     Edge::new(0, 4),
+///     Edge::new(4, 5),
+///     Edge::new(5, 2),
+///     Edge::new(2, 3),
+///     Edge::new(3, 0),
+///     Edge::new(0, 1),
+/// ];
+/// let preprocessed = spb::preprocess(&lines, &vertices, 1. / 128.);
+/// assert_eq!(preprocessed.vertices, vertices);
+/// assert_eq!(preprocessed.edges.len(), 6);
+/// assert!(preprocessed.edges.contains(&Edge::new(1, 4)));
+/// assert_eq!(preprocessed.build_polygons().len(), 1);
+/// ```
+pub fn preprocess(
+    lines: &[Edge],
+    vertices: &[Vec2],
+    weld_distance: f32
+) -> Preprocessed {
+    let mut vertices = vertices.to_vec();
+    let usable = |index: EdgeVertexIndex| {
+        vertices.get(index).is_some_and(|v| v.is_finite())
+    };
+    let lines: Vec<Edge> = lines.iter().copied()
+        .filter(|edge| edge.iter().all(usable))
+        .collect();
+
+    // Weld the vertices used by the edges
+    let mut welder = Welder::new(weld_distance);
+    let mut used: Vec<EdgeVertexIndex> = lines.iter()
+        .flat_map(|edge| edge.iter()).collect();
+    used.sort_unstable();
+    used.dedup();
+    let welded: HashMap<EdgeVertexIndex, EdgeVertexIndex, RandomState> =
+        used.into_iter().map(|index| {
+            let point = vertices[index];
+            let weld_to = welder.find(point, &vertices).unwrap_or_else(|| {
+                welder.insert(index, point);
+                index
+            });
+            (index, weld_to)
+        }).collect();
+    let mut edges: Vec<Edge> = lines.iter().filter_map(|edge| {
+        Edge::try_new(welded[&edge.lo()], welded[&edge.hi()])
+    }).collect();
+    edges.sort_unstable();
+    edges.dedup();
+
+    // Find out where the edges need to be split. Sort the edges from left to
+    // right, so that only the edges which overlap horizontally need to be
+    // compared.
+    let segment = |edge: &Edge| Segment(vertices[edge.lo()], vertices[edge.hi()]);
+    let segments: Vec<Segment> = edges.iter().map(segment).collect();
+    let left = |index: usize| segments[index].0.x.min(segments[index].1.x);
+    let right = |index: usize| segments[index].0.x.max(segments[index].1.x);
+    let mut order: Vec<usize> = (0..edges.len()).collect();
+    order.sort_by(|&a, &b| left(a).total_cmp(&left(b)));
+    let mut splits: Vec<Vec<Vec2>> = vec![Vec::new(); edges.len()];
+    order.iter().enumerate().for_each(|(position, &first)| {
+        let a = segments[first];
+        order[position + 1..].iter()
+            .take_while(|&&second| left(second) <= right(first) + weld_distance)
+            .for_each(|&second| {
+            let b = segments[second];
+            // Split where either edge ends in the middle of the other
+            let mut points = vec![a.0, a.1, b.0, b.1];
+            // Split where the edges cross or overlap each other. Edges which
+            // share a vertex can only overlap, not cross.
+            let shared = edges[first].iter().any(|v| edges[second].contains(v));
+            match a.intersection(b) {
+                Some(Intersection::Normal(_)) if shared => (),
+                Some(intersection @ (
+                    Intersection::Normal(_) | Intersection::Collinear
+                )) => {
+                    points.extend(intersection.split(a, b).into_iter()
+                        .flat_map(|Segment(p, q)| [p, q]));
+                },
+                _ => ()
+            }
+            points.into_iter().for_each(|point| {
+                if on_segment_interior(point, a, weld_distance) {
+                    splits[first].push(point);
+                }
+                if on_segment_interior(point, b, weld_distance) {
+                    splits[second].push(point);
+                }
+            });
+        });
+    });
+
+    // Split the edges
+    let mut split_edges: Vec<Edge> = Vec::with_capacity(edges.len());
+    edges.iter().zip(splits.iter_mut()).for_each(|(&edge, points)| {
+        let start = vertices[edge.lo()];
+        points.sort_by(|a, b| {
+            a.distance_squared(start).total_cmp(&b.distance_squared(start))
+        });
+        let mut chain: Vec<EdgeVertexIndex> = vec![edge.lo()];
+        points.iter().for_each(|&point| {
+            let vertex = welder.find_or_add(point, &mut vertices);
+            if !chain.contains(&vertex) && vertex != edge.hi() {
+                chain.push(vertex);
+            }
+        });
+        chain.push(edge.hi());
+        split_edges.extend(chain.windows(2)
+            .filter_map(|pair| Edge::try_new(pair[0], pair[1])));
+    });
+    split_edges.sort_unstable();
+    split_edges.dedup();
+    Preprocessed { edges: split_edges, vertices }
+}
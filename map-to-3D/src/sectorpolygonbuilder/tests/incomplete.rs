@@ -31,3 +31,31 @@ fn correct_polygons() {
     let actual_polygons = build_polygons(&edges, &verts);
     assert_eq!(expected_polygons, actual_polygons);
 }
+
+#[test]
+fn incomplete_chains() {
+    let (verts, edges) = test_case_incomplete();
+    let result = build_polygons_detailed(&edges, &verts);
+    assert_eq!(result.polygons, build_polygons(&edges, &verts));
+    assert_eq!(result.incomplete.len(), 1);
+    let chain = &result.incomplete[0];
+    assert_eq!(chain.stopped_at, *chain.vertices.last().unwrap());
+    let mut chain_vertices = chain.vertices.clone();
+    chain_vertices.sort_unstable();
+    assert_eq!(chain_vertices, vec![0, 1, 2]);
+    assert_eq!(result.dangling_edges, vec![Edge::new(0, 1), Edge::new(1, 2)]);
+    assert!(result.duplicate_edges.is_empty());
+    assert!(!result.is_closed());
+}
+
+#[test]
+fn closed_polygons() {
+    let (verts, mut edges) = test_case_incomplete();
+    edges.drain(0..2);
+    let result = build_polygons_detailed(&edges, &verts);
+    assert!(result.is_closed());
+    edges.push(Edge::new(4, 3));
+    let result = build_polygons_detailed(&edges, &verts);
+    assert_eq!(result.duplicate_edges, vec![Edge::new(3, 4)]);
+    assert!(result.dangling_edges.is_empty());
+}
@@ -6,3 +6,5 @@ mod harder;
 mod insides;
 mod incomplete;
 mod holey;
+mod nested;
+mod preprocess;
@@ -0,0 +1,89 @@
+use super::*;
+
+// Rectangles nested five levels deep on the left, with another hole on the
+// right:
+// - An outer square (depth 0)
+//   - A hole on the left (depth 1)
+//     - An island inside of the hole (depth 2)
+//       - A hole inside of the island (depth 3)
+//         - An island inside of that hole (depth 4)
+//   - A hole on the right (depth 1)
+fn test_case() -> (Vec<Vec2>, Vec<Edge>) {
+    let rectangles = [
+        (-100., -100., 100., 100.),
+        (-90., -80., -10., 80.),
+        (-80., -60., -20., 60.),
+        (-70., -40., -30., 40.),
+        (-60., -20., -40., 20.),
+        (10., -80., 90., 80.),
+    ];
+    let verts: Vec<Vec2> = rectangles.iter()
+        .flat_map(|&(left, bottom, right, top)| [
+            Vec2::new(left, bottom),
+            Vec2::new(right, bottom),
+            Vec2::new(right, top),
+            Vec2::new(left, top),
+        ]).collect();
+    let edges: Vec<Edge> = (0..rectangles.len()).flat_map(|rectangle| {
+        let first = rectangle * 4;
+        (0..4).map(move |corner| {
+            Edge::new(first + corner, first + (corner + 1) % 4)
+        })
+    }).collect();
+    (verts, edges)
+}
+
+fn area(triangles: &[EdgeVertexIndex], verts: &[Vec2]) -> f32 {
+    triangles.chunks(3).map(|triangle| {
+        let a = verts[triangle[0]];
+        let b = verts[triangle[1]];
+        let c = verts[triangle[2]];
+        (b - a).perp_dot(c - a).abs() / 2.
+    }).sum()
+}
+
+#[test]
+fn nested_depths() {
+    let (verts, edges) = test_case();
+    let result = build_polygons_detailed(&edges, &verts);
+    assert_eq!(result.polygons.len(), 6);
+    // Find the polygon made from each rectangle
+    let polygon_of = |rectangle: usize| result.polygons.iter()
+        .position(|polygon| polygon.vertices.contains(&(rectangle * 4)))
+        .unwrap();
+    let depths: Vec<usize> = (0..6)
+        .map(|rectangle| result.containment.depths[polygon_of(rectangle)])
+        .collect();
+    assert_eq!(depths, vec![0, 1, 2, 3, 4, 1]);
+    let hole_of: Vec<Option<usize>> = (0..6)
+        .map(|rectangle| result.polygons[polygon_of(rectangle)].hole_of)
+        .collect();
+    assert_eq!(hole_of, vec![
+        None,
+        Some(polygon_of(0)),
+        None,
+        Some(polygon_of(2)),
+        None,
+        Some(polygon_of(0)),
+    ]);
+    let mut outer_holes = result.containment.holes(polygon_of(0)).to_vec();
+    outer_holes.sort_unstable();
+    let mut expected_holes = vec![polygon_of(1), polygon_of(5)];
+    expected_holes.sort_unstable();
+    assert_eq!(outer_holes, expected_holes);
+}
+
+#[test]
+fn nested_triangulation() {
+    let (verts, edges) = test_case();
+    let polygons = build_polygons(&edges, &verts);
+    let triangles = auto_triangulate(&polygons, &verts);
+    assert_eq!(triangles.iter().filter(|t| t.is_none()).count(), 3);
+    let total: f32 = triangles.iter().flatten()
+        .map(|triangles| area(triangles, &verts))
+        .sum();
+    // Outer square, minus the holes, plus the islands
+    let expected = 200. * 200. - 80. * 160. * 2. + 60. * 120. - 40. * 80.
+        + 20. * 40.;
+    assert_eq!(total, expected);
+}
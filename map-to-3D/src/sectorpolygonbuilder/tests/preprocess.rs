@@ -0,0 +1,174 @@
+use super::*;
+
+const WELD: f32 = 1. / 128.;
+
+// Two squares next to each other, with the middle line split in two
+fn test_case_squares() -> (Vec<Vec2>, Vec<Edge>) {
+    let verts: Vec<Vec2> = vec![
+        Vec2::new(0., 0.),
+        Vec2::new(64., 0.),
+        Vec2::new(64., 64.),
+        Vec2::new(0., 64.),
+        Vec2::new(128., 0.),     // 4
+        Vec2::new(128., 64.),
+        Vec2::new(64., 32.),
+    ];
+    let edges: Vec<Edge> = vec![
+        Edge::new(0, 1),
+        Edge::new(1, 6),
+        Edge::new(6, 2),
+        Edge::new(2, 3),
+        Edge::new(3, 0),
+        Edge::new(1, 4),
+        Edge::new(4, 5),
+        Edge::new(5, 2),
+    ];
+    (verts, edges)
+}
+
+#[test]
+fn clean_input_is_unchanged() {
+    let (verts, edges) = test_case_squares();
+    let preprocessed = preprocess(&edges, &verts, WELD);
+    assert_eq!(preprocessed.vertices, verts);
+    let mut sorted = edges.clone();
+    sorted.sort_unstable();
+    assert_eq!(preprocessed.edges, sorted);
+}
+
+#[test]
+fn weld_and_remove_degenerate() {
+    let (mut verts, mut edges) = test_case_squares();
+    // A vertex almost on top of vertex 3, and a line between them
+    verts.push(Vec2::new(0.001, 64.));
+    edges.push(Edge::new(7, 3));
+    edges.push(Edge::new(7, 0));
+    // A line using a vertex which doesn't exist
+    edges.push(Edge::new(0, 100));
+    let preprocessed = preprocess(&edges, &verts, WELD);
+    let mut expected = test_case_squares().1;
+    expected.sort_unstable();
+    assert_eq!(preprocessed.edges, expected);
+}
+
+#[test]
+fn split_t_junction() {
+    let (verts, mut edges) = test_case_squares();
+    // Replace the split middle line with one line going through vertex 6
+    edges.drain(1..3);
+    edges.push(Edge::new(1, 2));
+    // Another line ending in the middle of the right square's bottom line
+    let mut verts = verts;
+    verts.push(Vec2::new(96., 0.));
+    verts.push(Vec2::new(96., 32.));
+    edges.push(Edge::new(7, 8));
+    edges.push(Edge::new(8, 6));
+    let preprocessed = preprocess(&edges, &verts, WELD);
+    assert_eq!(preprocessed.vertices, verts);
+    assert!(preprocessed.edges.contains(&Edge::new(1, 6)));
+    assert!(preprocessed.edges.contains(&Edge::new(6, 2)));
+    assert!(preprocessed.edges.contains(&Edge::new(1, 7)));
+    assert!(preprocessed.edges.contains(&Edge::new(7, 4)));
+    assert!(!preprocessed.edges.contains(&Edge::new(1, 4)));
+    // The same polygons as if the lines had been split properly
+    let mut clean = test_case_squares().1;
+    clean.retain(|&edge| edge != Edge::new(1, 4));
+    clean.extend([Edge::new(1, 7), Edge::new(7, 4), Edge::new(7, 8), Edge::new(8, 6)]);
+    assert_eq!(preprocessed.build_polygons(), build_polygons(&clean, &verts));
+}
+
+#[test]
+fn split_crossing_and_overlap() {
+    let verts: Vec<Vec2> = vec![
+        Vec2::new(0., 0.),
+        Vec2::new(64., 64.),
+        Vec2::new(0., 64.),
+        Vec2::new(64., 0.),
+        Vec2::new(32., 0.),      // 4
+        Vec2::new(96., 0.),
+    ];
+    let edges: Vec<Edge> = vec![
+        Edge::new(0, 1),
+        Edge::new(2, 3),
+        Edge::new(0, 3),
+        Edge::new(4, 5),
+    ];
+    let preprocessed = preprocess(&edges, &verts, WELD);
+    // A new vertex where the diagonal lines cross
+    assert_eq!(preprocessed.vertices.len(), 7);
+    assert_eq!(preprocessed.vertices[6], Vec2::new(32., 32.));
+    let mut expected = vec![
+        Edge::new(0, 6),
+        Edge::new(6, 1),
+        Edge::new(2, 6),
+        Edge::new(6, 3),
+        Edge::new(0, 4),
+        Edge::new(4, 3),
+        Edge::new(3, 5),
+    ];
+    expected.sort_unstable();
+    assert_eq!(preprocessed.edges, expected);
+}
+
+/// A small xorshift random number generator, so that the stress test gives
+/// the same results every time.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    fn below(&mut self, max: usize) -> usize {
+        (self.next() % max as u64) as usize
+    }
+    fn float(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[test]
+fn random_input_does_not_panic() {
+    let mut random = Random(0x2545_f491_4f6c_dd1d);
+    for _ in 0..500 {
+        let vertex_count = 3 + random.below(20);
+        let verts: Vec<Vec2> = (0..vertex_count).map(|_| {
+            match random.below(10) {
+                // On a coarse grid, so that there are lots of collinear and
+                // overlapping lines
+                0..=5 => Vec2::new(
+                    random.below(5) as f32 * 16., random.below(5) as f32 * 16.),
+                // Almost on the grid
+                6 => Vec2::new(
+                    random.below(5) as f32 * 16. + random.float() * WELD,
+                    random.below(5) as f32 * 16.),
+                7 => Vec2::new(f32::NAN, random.float()),
+                _ => Vec2::new(random.float() * 64., random.float() * 64.),
+            }
+        }).collect();
+        let edges: Vec<Edge> = (0..random.below(30)).filter_map(|_| {
+            // Sometimes use vertices which don't exist
+            Edge::try_new(
+                random.below(vertex_count + 2), random.below(vertex_count + 2))
+        }).collect();
+        let preprocessed = preprocess(&edges, &verts, WELD);
+        assert!(preprocessed.vertices.len() >= verts.len());
+        preprocessed.edges.iter().for_each(|edge| {
+            assert!(edge.hi() < preprocessed.vertices.len());
+            assert!(preprocessed.vertices[edge.lo()].is_finite());
+        });
+        let mut deduped = preprocessed.edges.clone();
+        deduped.dedup();
+        assert_eq!(deduped, preprocessed.edges);
+        let result = preprocessed.build_polygons_detailed();
+        result.polygons.iter().for_each(|polygon| {
+            assert!(polygon.vertices.iter()
+                .all(|&v| v < preprocessed.vertices.len()));
+        });
+        auto_triangulate(&result.polygons, &preprocessed.vertices);
+        // Building polygons from the original edges doesn't panic either
+        build_polygons_detailed(&edges, &verts);
+    }
+}
@@ -27,26 +27,23 @@ fn test_case_simple() -> (Vec<Vec2>, Vec<Edge>) {
 #[test]
 fn correct_first_edge_ccw() {
     let (verts, edges) = test_case_simple();
-    let edges: HashMap<Edge, bool, RandomState> = 
-        edges.into_iter().map(|e| (e, false)).collect();
-    let first_edge = find_next_start_edge(false, &edges, &verts);
+    let mut edges = EdgeGraph::new(&edges, &verts);
+    let first_edge = find_next_start_edge(false, &mut edges, &verts);
     assert_eq!(first_edge, Some((1, 2)));
 }
 
 #[test]
 fn correct_first_edge_cw() {
     let (verts, edges) = test_case_simple();
-    let edges: HashMap<Edge, bool, RandomState> = 
-        edges.into_iter().map(|e| (e, false)).collect();
-    let first_edge = find_next_start_edge(true, &edges, &verts);
+    let mut edges = EdgeGraph::new(&edges, &verts);
+    let first_edge = find_next_start_edge(true, &mut edges, &verts);
     assert_eq!(first_edge, Some((3, 2)));
 }
 
 #[test]
 fn correct_next_vertex() {
     let (verts, edges) = test_case_simple();
-    let edges: HashMap<Edge, bool, RandomState> = 
-        edges.into_iter().map(|e| (e, false)).collect();
+    let edges = EdgeGraph::new(&edges, &verts);
     let from = 2;
     let previous = 3;
 
@@ -59,8 +56,7 @@ fn correct_next_vertex() {
 fn correct_next_vertex_with_multiple_connected_edges_ccw() {
     let clockwise = false;
     let (verts, edges) = test_case_simple();
-    let edges: HashMap<Edge, bool, RandomState> = 
-        edges.into_iter().map(|e| (e, false)).collect();
+    let edges = EdgeGraph::new(&edges, &verts);
 
     // Inside lower right polygon
     let previous = 3;
@@ -91,8 +87,7 @@ fn correct_next_vertex_with_multiple_connected_edges_ccw() {
 fn correct_next_vertex_with_multiple_connected_edges_cw() {
     let clockwise = true;
     let (verts, edges) = test_case_simple();
-    let edges: HashMap<Edge, bool, RandomState> = 
-        edges.into_iter().map(|e| (e, false)).collect();
+    let edges = EdgeGraph::new(&edges, &verts);
 
     // Inside lower right polygon
     let from = 0;
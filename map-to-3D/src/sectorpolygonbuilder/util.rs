@@ -2,22 +2,7 @@ use glam::Vec2;
 use crate::edge::{Edge, EdgeVertexIndex};
 use super::Angle;
 
-fn point_in_polygon(point: Vec2, polygon: &Vec<Vec2>) -> bool {
-    // Based on https://wrf.ecse.rpi.edu/Research/Short_Notes/pnpoly.html
-    let mut inside = false;
-    for i in 0..polygon.len() {
-        let j = if i == 0 { polygon.len() - 1 } else { i - 1 };
-        let vi = polygon[i];
-        let vj = polygon[j];
-        if (
-            (vi.y > point.y) != (vj.y > point.y)) && (
-            point.x < (vj.x - vi.x) * (point.y - vi.y) / (vj.y - vi.y) + vi.x
-        ) {
-            inside = !inside;
-        }
-    }
-    inside
-}
+pub(super) use crate::predicates::point_in_polygon;
 
 pub(super) fn edge_in_polygon(
     edge: &Edge,
@@ -1,5 +1,9 @@
 //! Line segments and intersection calculation
+use std::cmp::Ordering;
 use glam::Vec2;
+use crate::predicates::{
+    Coord, Point, Product, fraction_in_unit, point_orientation, sign
+};
 
 // A line segment
 #[derive(Debug, Clone, Copy)]
@@ -9,9 +13,13 @@ impl Segment {
     pub fn intersection(&self, other: Segment) -> Option<Intersection> {
         // Thanks to https://replit.com/@thehappycheese/linetools#LineTools/line_tools.py
         // and his YouTube video: https://youtu.be/5FkOO1Wwb8w
+        // The points are converted for the predicates, so that the result is
+        // exact when the fixed-point feature is enabled.
+        let (a, b) = (Point::new(self.0), Point::new(self.1));
+        let (c, d) = (Point::new(other.0), Point::new(other.1));
         { // If any of the four points are equal, the segments are connected.
-            let ab = [self.0, self.1];
-            let cd = [other.0, other.1];
+            let ab = [a, b];
+            let cd = [c, d];
             // Both points of the first segment equal both points of the second
             if ab.iter().all(|av| cd.iter().any(|bv| av == bv)) {
                 return Some(Intersection::Same);
@@ -20,80 +28,40 @@ impl Segment {
                 return Some(Intersection::Connected);
             }
         }
-        let ab = self.1 - self.0;
-        let cd = other.1 - other.0;
-        let ac = other.0 - self.0;
-        let ab_cross_cd = ab.perp_dot(cd);
-        if ab_cross_cd == 0. { // Lines are parallel
-            let ab_slope = self.slope();
-            let cd_slope = other.slope();
-            // Are points c or d between a and b? If so, there is a collinear
-            // intersection.
-            if ab_slope.is_some() && cd_slope.is_some() && ab_slope == cd_slope {
-                let ad = other.1 - self.0;
-                let ca = self.0 - other.0;
-                let cb = self.1 - other.0;
-                let vectors = [(ac, ab), (ad, ab), (ca, cd), (cb, cd)];
-                vectors.into_iter()
-                    .map(|(pt, div)| {
-                        let (pt, div) = match ab_slope.unwrap() {
-                            Slope::Vertical { x: _x } => (pt.y, div.y),
-                            Slope::Horizontal { y: _y } => (pt.x, div.x),
-                            // It is unlikely that v.x will not equal v.y, since
-                            // the dividend and divisor are on the same line
-                            Slope::Sloped { m: _m, b: _b } => (pt.x, div.x),
-                        };
-                        pt / div
-                    })
-                    .any(|f| (0.0..1.0).contains(&f))
-                    .then_some(Intersection::Collinear)
-            } else {
+        let ab = b.sub(a);
+        let cd = d.sub(c);
+        let ac = c.sub(a);
+        let ab_cross_cd = ab.cross(cd);
+        if sign(ab_cross_cd) == Ordering::Equal { // Lines are parallel
+            if a == b || c == d ||
+                point_orientation(a, b, c) != Ordering::Equal {
                 // Lines are parallel with different intercepts
-                None
+                return None;
             }
+            // Are points c or d between a and b? If so, there is a collinear
+            // intersection. Vertical lines are measured along the Y axis, and
+            // other lines are measured along the X axis.
+            let along = |p: Point| Product::from(
+                if ab.x == Coord::default() { p.y } else { p.x });
+            let ad = d.sub(a);
+            let ca = a.sub(c);
+            let cb = b.sub(c);
+            let vectors = [(ac, ab), (ad, ab), (ca, cd), (cb, cd)];
+            vectors.into_iter()
+                .any(|(pt, div)| fraction_in_unit(along(pt), along(div)))
+                .then_some(Intersection::Collinear)
         } else {
             // Lines are perpendicular
-            let ab_factor = ac.perp_dot(cd) / ab_cross_cd;
-            let cd_factor = -ab.perp_dot(ac) / ab_cross_cd;
-            ((0.0..1.0).contains(&ab_factor) &&
-             (0.0..1.0).contains(&cd_factor))
-            .then_some(Intersection::Normal(self.0 + ab * ab_factor))
-        }
-    }
-    fn slope(&self) -> Option<Slope> {
-        if self.0 == self.1 {
-            // Highly unlikely
-            None
-        } else if self.0.x == self.1.x {
-            Some(Slope::Vertical { x: self.0.x })
-        } else if self.0.y == self.1.y {
-            Some(Slope::Horizontal { y: self.0.y })
-        } else {
-            let m = {
-                let (a, b) = match self {
-                    Segment(a, b) if a.x < b.x => (*a, *b),
-                    Segment(a, b) => (*b, *a)
-                };
-                let diff = b - a;
-                diff.y / diff.x
-            };
-            // y = mx + b
-            // y - b = mx
-            // -b = mx - y
-            // b = -(mx - y)
-            let b = -(m * self.0.x - self.0.y);
-            Some(Slope::Sloped { m, b })
+            let ab_factor = ac.cross(cd);
+            let cd_factor = ac.cross(ab);
+            (fraction_in_unit(ab_factor, ab_cross_cd) &&
+             fraction_in_unit(cd_factor, ab_cross_cd))
+            .then(|| Intersection::Normal(
+                a.lerp(b, ab_factor, ab_cross_cd).to_vec2()))
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Slope {
-    Vertical {x: f32},
-    Horizontal {y: f32},
-    Sloped {m: f32, b: f32}
-}
-
 /// An intersection between two line segments
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Intersection {
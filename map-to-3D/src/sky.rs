@@ -0,0 +1,110 @@
+//! # Skies
+//!
+//! Floors and ceilings which use the sky flat (`F_SKY1` by default) aren't
+//! drawn like other flats. Instead, they mark where the sky can be seen, so
+//! that renderers can draw the sky there, e.g. by drawing a sky box which
+//! can only be seen through them. `buffer` and `export` keep them apart from
+//! the other flats, and give them the sky texture as their material.
+//!
+//! The walls between two sectors which both have sky ceilings are left out
+//! when the walls are built (see `wall::LineQuads`), so the sky can be seen
+//! above them, like it can in Doom.
+//!
+//! The sky flat and texture are set with `WallOptions`, which can get them
+//! from a MAPINFO lump using `WallOptions::from_mapinfo`.
+use glam::{Vec2, Vec3, Vec4};
+use parsers::udmf::input::UDMFMap;
+use crate::attributes::FlatVertex;
+use crate::buffer;
+use crate::plane::SectorPlanes;
+use crate::uv::FlatTexturing;
+use crate::wall::WallOptions;
+
+/// Whether the floor or ceiling of the sector shows the sky
+pub fn is_sky(map: &UDMFMap, sector: usize, ceiling: bool, options: &WallOptions) -> bool {
+    let data = &map.sectors[sector];
+    options.is_sky(if ceiling { &data.textureceiling } else { &data.texturefloor })
+}
+
+/// Get the vertices and triangles of a floor or ceiling which shows the sky.
+/// `triangles` are the triangulated polygons of the sector, as indices into
+/// `positions`, which are the positions of the vertices of the map.
+///
+/// The sky isn't affected by the light level or fog of the sector, so the
+/// vertices are white, and have no fog. The texture coordinates place the
+/// sky texture, which is `sky_size` pixels in size, on the surface as if it
+/// was a flat, which is good enough for previewing it. Renderers will
+/// usually use the direction the surface is seen from instead.
+pub fn sky_geometry(
+    sector: usize,
+    ceiling: bool,
+    triangles: &[usize],
+    positions: &[Vec2],
+    planes: &[SectorPlanes],
+    sky_size: Vec2
+) -> (Vec<FlatVertex>, Vec<u32>) {
+    let plane = if ceiling { planes[sector].ceiling } else { planes[sector].floor };
+    let texturing = FlatTexturing {
+        panning: Vec2::ZERO,
+        scale: Vec2::ONE,
+        rotation: 0.,
+        size: sky_size,
+    };
+    buffer::flat_vertices(FlatVertex {
+        colour: Vec3::ONE,
+        fog: Vec4::ZERO,
+        sector,
+        ..Default::default()
+    }, triangles, positions, plane, ceiling, &texturing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use parsers::mapinfo::MapInfo;
+    use parsers::udmf::input::LightLevel;
+    use crate::level;
+
+    // The room from the export tests, with the sky on its ceiling
+    fn sky_room() -> UDMFMap {
+        let mut map = UDMFMap::from_str(crate::export::tests::TEXTMAP).unwrap();
+        map.sectors[0].textureceiling = String::from("F_SKY1");
+        map.sectors[0].lightlevel = LightLevel(96);
+        map
+    }
+
+    #[test]
+    fn sky_options() {
+        let map = sky_room();
+        let options = WallOptions::default();
+        assert!(is_sky(&map, 0, true, &options));
+        assert!(!is_sky(&map, 0, false, &options));
+        let mapinfo = MapInfo::from_str(r#"
+gameinfo { SkyFlatName = "FLAT1" }
+map MAP01 "Entryway" { sky1 = "SKY3", 0 }
+"#).unwrap();
+        let options = WallOptions::from_mapinfo(&mapinfo, "MAP01");
+        assert_eq!(options.sky_texture, "SKY3");
+        assert!(is_sky(&map, 0, false, &options));
+        assert!(!is_sky(&map, 0, true, &options));
+        // Maps without a sky of their own use the default
+        let options = WallOptions::from_mapinfo(&mapinfo, "MAP02");
+        assert_eq!(options.sky_texture, "SKY1");
+    }
+
+    #[test]
+    fn sky_surface() {
+        let map = sky_room();
+        let planes = level::sector_planes(&map);
+        let positions = level::vertex_positions(&map);
+        let (vertices, indices) = sky_geometry(
+            0, true, &[0, 1, 2, 0, 2, 3], &positions, &planes,
+            Vec2::new(256., 128.));
+        assert_eq!(indices.len(), 6);
+        // The sky is fullbright, even though the sector is dark
+        assert!(vertices.iter().all(|vertex| vertex.colour == Vec3::ONE &&
+            vertex.fog == Vec4::ZERO && vertex.position.z == 128.));
+        assert_eq!(vertices[2].uv, Vec2::new(0.25, -0.5));
+    }
+}
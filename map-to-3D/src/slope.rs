@@ -0,0 +1,542 @@
+//! # Slopes
+//!
+//! Slopes the floors and ceilings of sectors the same way GZDoom does, using
+//! the line specials, things and vertex heights which it supports.
+//!
+//! The slopes are applied in the same order as GZDoom applies them:
+//!
+//! 1. `Plane_Align` (181) line specials
+//! 2. Slope things (1500, 1501 and 9500 to 9503), in the order they are in
+//!    the map
+//! 3. Vertex heights, from the `zfloor` and `zceiling` properties of
+//!    vertices, and vertex height things (1504 and 1505). These only slope
+//!    triangular sectors.
+//! 4. Plane copy things (9510 and 9511)
+//! 5. `Plane_Copy` (118) line specials
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use glam::{Vec2, Vec3};
+use parsers::udmf::input::{UDMFMap, UDMFSector, UDMFThing};
+use crate::level::{self, LineSide};
+use crate::plane::{Plane, SectorPlanes};
+use crate::predicates;
+use crate::sectorindex::SectorIndex;
+use crate::segment::Segment;
+
+/// `Plane_Copy`, which copies planes from tagged sectors
+pub const PLANE_COPY: u32 = 118;
+/// `Plane_Align`, which slopes a plane so that it meets the plane on the
+/// other side of the line
+pub const PLANE_ALIGN: u32 = 181;
+
+/// Slopes the floor from a line to the thing (Vavoom style)
+pub const VAVOOM_FLOOR: u32 = 1500;
+/// Slopes the ceiling from a line to the thing (Vavoom style)
+pub const VAVOOM_CEILING: u32 = 1501;
+/// Sets the floor height of the vertex the thing is on
+pub const VERTEX_FLOOR_Z: u32 = 1504;
+/// Sets the ceiling height of the vertex the thing is on
+pub const VERTEX_CEILING_Z: u32 = 1505;
+/// Slopes the floor from a line to the thing
+pub const SLOPE_FLOOR_POINT_LINE: u32 = 9500;
+/// Slopes the ceiling from a line to the thing
+pub const SLOPE_CEILING_POINT_LINE: u32 = 9501;
+/// Slopes the floor in the direction the thing is facing
+pub const SET_FLOOR_SLOPE: u32 = 9502;
+/// Slopes the ceiling in the direction the thing is facing
+pub const SET_CEILING_SLOPE: u32 = 9503;
+/// Copies the floor of a tagged sector to the sector the thing is in
+pub const COPY_FLOOR_PLANE: u32 = 9510;
+/// Copies the ceiling of a tagged sector to the sector the thing is in
+pub const COPY_CEILING_PLANE: u32 = 9511;
+
+fn plane_mut(planes: &mut SectorPlanes, ceiling: bool) -> &mut Plane {
+    if ceiling {
+        &mut planes.ceiling
+    } else {
+        &mut planes.floor
+    }
+}
+
+fn plane(planes: &SectorPlanes, ceiling: bool) -> Plane {
+    if ceiling {
+        planes.ceiling
+    } else {
+        planes.floor
+    }
+}
+
+/// The height of the floor or ceiling of a sector, ignoring any slopes
+fn sector_height(sector: &UDMFSector, ceiling: bool) -> f32 {
+    if ceiling {
+        sector.heightceiling as f32
+    } else {
+        sector.heightfloor as f32
+    }
+}
+
+/// Get the index of the first sector with the given tag
+fn tagged_sector(map: &UDMFMap, tag: i32) -> Option<usize> {
+    if tag == 0 {
+        return None;
+    }
+    map.sectors.iter().position(|sector| sector.id as i32 == tag)
+}
+
+/// Get the plane going through the points at the ends of the line and the
+/// given point. Returns `None` if the point is on the line.
+fn plane_through_line(line: Segment, a_z: f32, b_z: f32, point: Vec3) -> Option<Plane> {
+    let Segment(a, b) = line;
+    if predicates::orientation(a, b, point.truncate()) == Ordering::Equal {
+        return None;
+    }
+    Some(Plane::from_triangle(a.extend(a_z), b.extend(b_z), point))
+}
+
+/// Slope the given plane of the sector so that it meets the same plane of
+/// the sector on the other side of the line. The plane goes through the line
+/// at the height of the other sector, and through the vertex of the sector
+/// which is furthest from the line at the height of the sector.
+fn align_plane(
+    map: &UDMFMap,
+    sector_lines: &[Vec<usize>],
+    line: usize,
+    sector: usize,
+    other: usize,
+    ceiling: bool
+) -> Option<Plane> {
+    let segment = level::line_segment(map, line);
+    let Segment(a, b) = segment;
+    let furthest = sector_lines[sector].iter()
+        .flat_map(|&line| {
+            let line = &map.linedefs[line];
+            [line.v1, line.v2]
+        })
+        .map(|vertex| level::vertex_position(map, vertex as usize))
+        .fold((0., None), |(distance, furthest), vertex| {
+            let vertex_distance = (vertex - a).perp_dot(b - a).abs();
+            if vertex_distance > distance {
+                (vertex_distance, Some(vertex))
+            } else {
+                (distance, furthest)
+            }
+        }).1?;
+    let height = sector_height(&map.sectors[other], ceiling);
+    plane_through_line(segment, height, height,
+        furthest.extend(sector_height(&map.sectors[sector], ceiling)))
+}
+
+fn align_planes(map: &UDMFMap, sector_lines: &[Vec<usize>], planes: &mut [SectorPlanes]) {
+    map.linedefs.iter().enumerate()
+        .filter(|(_, linedef)| level::line_special(linedef) == PLANE_ALIGN)
+        .for_each(|(line, linedef)| {
+        let (Some(front), Some(back)) = (
+            level::side_sector(map, line, LineSide::Front),
+            level::side_sector(map, line, LineSide::Back)
+        ) else {
+            return;
+        };
+        let args = level::prop_args(&linedef.props);
+        [false, true].into_iter().for_each(|ceiling| {
+            // arg0 is for the floor, and arg1 is for the ceiling, unless it
+            // is 0, in which case bits 2 and 3 of arg0 are for the ceiling.
+            let bits = match (ceiling, args[1] & 3) {
+                (false, _) => args[0] & 3,
+                (true, 0) => (args[0] >> 2) & 3,
+                (true, bits) => bits,
+            };
+            let (sector, other) = match bits {
+                1 => (front, back),
+                2 => (back, front),
+                _ => return,
+            };
+            if let Some(plane) = align_plane(map, sector_lines, line, sector, other, ceiling) {
+                *plane_mut(&mut planes[sector], ceiling) = plane;
+            }
+        });
+    });
+}
+
+/// Slope a plane of the sector the thing is in, from the first line of the
+/// sector whose first argument is the thing's ID, to the thing. The thing's
+/// height is absolute, and the plane keeps its current height at the ends of
+/// the line.
+fn vavoom_slope(
+    map: &UDMFMap,
+    sector_lines: &[Vec<usize>],
+    planes: &mut [SectorPlanes],
+    thing: &UDMFThing,
+    sector: usize,
+    ceiling: bool
+) {
+    let line = sector_lines[sector].iter().copied().find(|&line| {
+        level::prop_args(&map.linedefs[line].props)[0] == thing.id as i32
+    });
+    let Some(line) = line else { return; };
+    let point = Vec3::new(thing.x, thing.y, thing.height);
+    // Same as P_VavoomSlope in GZDoom
+    let current = plane(&planes[sector], ceiling);
+    let Segment(a, b) = level::line_segment(map, line);
+    let to_b = point - b.extend(current.z_at(b));
+    let to_a = point - a.extend(current.z_at(a));
+    let normal = to_b.cross(to_a).normalize_or_zero();
+    if normal == Vec3::ZERO {
+        return;
+    }
+    // Floors face up and ceilings face down
+    let normal = if (normal.z < 0.) != ceiling { -normal } else { normal };
+    *plane_mut(&mut planes[sector], ceiling) = Plane::Sloped(
+        normal.x, normal.y, normal.z, -normal.dot(point));
+}
+
+/// Slope a plane of the sector on the same side of each line with the given
+/// ID as the point, from the line to the point.
+fn slope_line_to_point(
+    map: &UDMFMap,
+    planes: &mut [SectorPlanes],
+    id: i32,
+    point: Vec3,
+    ceiling: bool
+) {
+    map.linedefs.iter().enumerate()
+        .filter(|(_, linedef)| linedef.id as i32 == id)
+        .for_each(|(line, _)| {
+        let segment = level::line_segment(map, line);
+        let side = match predicates::orientation(
+            segment.0, segment.1, point.truncate()) {
+            Ordering::Greater => LineSide::Back,
+            _ => LineSide::Front,
+        };
+        let Some(sector) = level::side_sector(map, line, side) else {
+            return;
+        };
+        let current = plane(&planes[sector], ceiling);
+        if let Some(plane) = plane_through_line(segment,
+            current.z_at(segment.0), current.z_at(segment.1), point) {
+            *plane_mut(&mut planes[sector], ceiling) = plane;
+        }
+    });
+}
+
+/// Get the plane which goes through the point, facing in the given
+/// direction, at the given angle from the horizontal (in degrees).
+fn angled_plane(point: Vec3, direction: f32, angle: i32, ceiling: bool) -> Plane {
+    let mut angle = (angle.clamp(1, 179) as f32).to_radians();
+    if ceiling {
+        angle += std::f32::consts::PI;
+    }
+    let direction = direction.to_radians();
+    let normal = Vec3::new(
+        angle.cos() * direction.cos(),
+        angle.cos() * direction.sin(),
+        angle.sin()
+    ).normalize();
+    Plane::Sloped(normal.x, normal.y, normal.z, -normal.dot(point))
+}
+
+fn slope_things(
+    map: &UDMFMap,
+    sector_lines: &[Vec<usize>],
+    planes: &mut [SectorPlanes],
+    finder: &SectorIndex
+) {
+    map.things.iter().for_each(|thing| {
+        let position = Vec2::new(thing.x, thing.y);
+        let ceiling = match thing.ednum {
+            VAVOOM_FLOOR | SLOPE_FLOOR_POINT_LINE | SET_FLOOR_SLOPE => false,
+            VAVOOM_CEILING | SLOPE_CEILING_POINT_LINE | SET_CEILING_SLOPE => true,
+            _ => return,
+        };
+        let Some(sector) = finder.sector_at(position) else { return; };
+        let args = level::prop_args(&thing.props);
+        // The height of the thing is relative to the plane it is on, except
+        // for Vavoom slope things.
+        let point = position.extend(
+            plane(&planes[sector], ceiling).z_at(position) + thing.height);
+        match thing.ednum {
+            VAVOOM_FLOOR | VAVOOM_CEILING => {
+                vavoom_slope(map, sector_lines, planes, thing, sector, ceiling);
+            },
+            SLOPE_FLOOR_POINT_LINE | SLOPE_CEILING_POINT_LINE => {
+                slope_line_to_point(map, planes, args[0], point, ceiling);
+            },
+            _ => {
+                *plane_mut(&mut planes[sector], ceiling) = angled_plane(
+                    point, thing.angle as f32, args[0], ceiling);
+            },
+        }
+    });
+}
+
+/// Slope triangular sectors using the heights of their vertices
+fn vertex_heights(map: &UDMFMap, sector_lines: &[Vec<usize>], planes: &mut [SectorPlanes]) {
+    // Floor and ceiling heights of each vertex, if they are set
+    let mut heights: HashMap<usize, [Option<f32>; 2]> = HashMap::new();
+    map.vertices.iter().enumerate().for_each(|(index, vertex)| {
+        let floor = level::prop_number(&vertex.props, "zfloor");
+        let ceiling = level::prop_number(&vertex.props, "zceiling");
+        if floor.is_some() || ceiling.is_some() {
+            heights.insert(index, [floor, ceiling]);
+        }
+    });
+    map.things.iter().for_each(|thing| {
+        let ceiling = match thing.ednum {
+            VERTEX_FLOOR_Z => false,
+            VERTEX_CEILING_Z => true,
+            _ => return,
+        };
+        let position = Vec2::new(thing.x, thing.y);
+        map.vertices.iter().enumerate()
+            .filter(|(_, vertex)| Vec2::new(vertex.x, vertex.y) == position)
+            .for_each(|(index, _)| {
+            heights.entry(index).or_default()[ceiling as usize] = Some(thing.height);
+        });
+    });
+    if heights.is_empty() {
+        return;
+    }
+    map.sectors.iter().enumerate().for_each(|(sector, data)| {
+        let lines = &sector_lines[sector];
+        if lines.len() != 3 {
+            return;
+        }
+        let mut corners: Vec<usize> = lines.iter()
+            .flat_map(|&line| {
+                let line = &map.linedefs[line];
+                [line.v1 as usize, line.v2 as usize]
+            }).collect();
+        corners.sort_unstable();
+        corners.dedup();
+        if corners.len() != 3 {
+            return;
+        }
+        [false, true].into_iter().for_each(|ceiling| {
+            let corner_heights = corners.iter().map(|corner| {
+                heights.get(corner).and_then(|heights| heights[ceiling as usize])
+            }).collect::<Vec<_>>();
+            if corner_heights.iter().all(Option::is_none) {
+                return;
+            }
+            let [a, b, c] = [0, 1, 2].map(|i| {
+                level::vertex_position(map, corners[i]).extend(corner_heights[i]
+                    .unwrap_or_else(|| sector_height(data, ceiling)))
+            });
+            *plane_mut(&mut planes[sector], ceiling) = Plane::from_triangle(a, b, c);
+        });
+    });
+}
+
+fn copy_plane_things(map: &UDMFMap, planes: &mut [SectorPlanes], finder: &SectorIndex) {
+    map.things.iter().for_each(|thing| {
+        let ceiling = match thing.ednum {
+            COPY_FLOOR_PLANE => false,
+            COPY_CEILING_PLANE => true,
+            _ => return,
+        };
+        let args = level::prop_args(&thing.props);
+        let (Some(source), Some(sector)) = (
+            tagged_sector(map, args[0]),
+            finder.sector_at(Vec2::new(thing.x, thing.y))
+        ) else {
+            return;
+        };
+        *plane_mut(&mut planes[sector], ceiling) = plane(&planes[source], ceiling);
+    });
+}
+
+fn copy_planes(map: &UDMFMap, planes: &mut [SectorPlanes]) {
+    map.linedefs.iter().enumerate()
+        .filter(|(_, linedef)| level::line_special(linedef) == PLANE_COPY)
+        .for_each(|(line, linedef)| {
+        let args = level::prop_args(&linedef.props);
+        let front = level::side_sector(map, line, LineSide::Front);
+        let back = level::side_sector(map, line, LineSide::Back);
+        // arg0 to arg3 are the tags of the sectors to copy the front floor,
+        // front ceiling, back floor and back ceiling from.
+        [(front, false), (front, true), (back, false), (back, true)]
+            .into_iter().zip(args).for_each(|((sector, ceiling), tag)| {
+            if let (Some(sector), Some(source)) = (sector, tagged_sector(map, tag)) {
+                *plane_mut(&mut planes[sector], ceiling) = plane(&planes[source], ceiling);
+            }
+        });
+        // arg4 copies the planes from one side of the line to the other
+        let (Some(front), Some(back)) = (front, back) else { return; };
+        match args[4] & 3 {
+            1 => planes[back].floor = planes[front].floor,
+            2 => planes[front].floor = planes[back].floor,
+            _ => (),
+        }
+        match args[4] & 12 {
+            4 => planes[back].ceiling = planes[front].ceiling,
+            8 => planes[front].ceiling = planes[back].ceiling,
+            _ => (),
+        }
+    });
+}
+
+/// Slope the planes of the sectors in the map, which are indexed by sector.
+pub fn apply_slopes(map: &UDMFMap, planes: &mut [SectorPlanes]) {
+    let sector_lines = level::lines_of_sectors(map);
+    align_planes(map, &sector_lines, planes);
+    // Finding which sector a thing is in needs the sector polygons, so they
+    // are only built if there are things which need them.
+    let needs_finder = map.things.iter().any(|thing| matches!(thing.ednum,
+        VAVOOM_FLOOR | VAVOOM_CEILING | SLOPE_FLOOR_POINT_LINE |
+        SLOPE_CEILING_POINT_LINE | SET_FLOOR_SLOPE | SET_CEILING_SLOPE |
+        COPY_FLOOR_PLANE | COPY_CEILING_PLANE));
+    let finder = needs_finder.then(|| SectorIndex::new(map));
+    if let Some(finder) = &finder {
+        slope_things(map, &sector_lines, planes, finder);
+    }
+    vertex_heights(map, &sector_lines, planes);
+    if let Some(finder) = &finder {
+        copy_plane_things(map, planes, finder);
+    }
+    copy_planes(map, planes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// Two 64x64 squares side by side, with sector 0 on the left
+    const SQUARES: &str = r#"namespace = "zdoom";
+vertex { x = 0.0; y = 0.0; }
+vertex { x = 0.0; y = 64.0; }
+vertex { x = 64.0; y = 64.0; }
+vertex { x = 64.0; y = 0.0; }
+vertex { x = 128.0; y = 64.0; }
+vertex { x = 128.0; y = 0.0; }
+linedef { v1 = 0; v2 = 1; sidefront = 0; id = 7; }
+linedef { v1 = 1; v2 = 2; sidefront = 0; }
+linedef { v1 = 3; v2 = 2; sidefront = 1; sideback = 0; }
+linedef { v1 = 3; v2 = 0; sidefront = 0; }
+linedef { v1 = 2; v2 = 4; sidefront = 1; }
+linedef { v1 = 4; v2 = 5; sidefront = 1; }
+linedef { v1 = 5; v2 = 3; sidefront = 1; }
+sidedef { sector = 0; }
+sidedef { sector = 1; }
+sector { texturefloor = "FLAT1"; textureceiling = "CEIL1_1";
+    heightceiling = 128; }
+sector { texturefloor = "FLAT1"; textureceiling = "CEIL1_1";
+    heightfloor = 32; heightceiling = 128; id = 5; }
+"#;
+
+    fn with_line_special(line: usize, special: &str) -> UDMFMap {
+        let mut map = UDMFMap::from_str(SQUARES).unwrap();
+        special.split(' ').filter_map(|prop| prop.split_once('='))
+            .for_each(|(name, value)| {
+            map.linedefs[line].props.insert(name.into(), value.into());
+        });
+        map
+    }
+
+    fn assert_z(plane: Plane, x: f32, y: f32, z: f32) {
+        let actual = plane.z_at(Vec2::new(x, y));
+        assert!((actual - z).abs() < 0.001, "{actual} != {z} at ({x}, {y})");
+    }
+
+    #[test]
+    fn vertex_heights() {
+        let map = UDMFMap::from_str(r#"namespace = "zdoom";
+vertex { x = 0.0; y = 0.0; }
+vertex { x = 0.0; y = 64.0; }
+vertex { x = 64.0; y = 0.0; zfloor = 64.0; }
+linedef { v1 = 0; v2 = 1; sidefront = 0; }
+linedef { v1 = 1; v2 = 2; sidefront = 0; }
+linedef { v1 = 2; v2 = 0; sidefront = 0; }
+sidedef { sector = 0; }
+sector { texturefloor = "FLAT1"; textureceiling = "CEIL1_1";
+    heightceiling = 128; }
+thing { x = 0.0; y = 64.0; height = 96.0; type = 1505; }
+"#).unwrap();
+        let planes = level::sector_planes(&map);
+        assert_z(planes[0].floor, 32., 0., 32.);
+        assert_z(planes[0].floor, 0., 32., 0.);
+        assert_z(planes[0].ceiling, 32., 0., 128.);
+        assert_z(planes[0].ceiling, 0., 32., 112.);
+    }
+
+    #[test]
+    fn plane_align() {
+        // The front of line 2 is the right square, which is aligned to the
+        // left square
+        let map = with_line_special(2, "special=181 arg0=1");
+        let planes = level::sector_planes(&map);
+        assert_z(planes[1].floor, 64., 32., 0.);
+        assert_z(planes[1].floor, 96., 32., 16.);
+        assert_z(planes[1].floor, 128., 0., 32.);
+        assert_eq!(planes[1].ceiling, Plane::Flat(128.));
+        assert_eq!(planes[0].floor, Plane::Flat(0.));
+        // Align the ceiling of the left square using bits 2 and 3 of arg0
+        let map = with_line_special(2, "special=181 arg0=8");
+        let planes = level::sector_planes(&map);
+        assert_eq!(planes[1].floor, Plane::Flat(32.));
+        assert_eq!(planes[0].ceiling, Plane::Flat(128.));
+    }
+
+    #[test]
+    fn plane_copy() {
+        // Align the right square, and copy its floor to the left square
+        let mut map = with_line_special(2, "special=181 arg0=1");
+        map.linedefs[0].props.insert("special".into(), "118".into());
+        map.linedefs[0].props.insert("arg0".into(), "5".into());
+        let planes = level::sector_planes(&map);
+        assert_z(planes[0].floor, 32., 32., -16.);
+        assert_eq!(planes[0].floor, planes[1].floor);
+        // Copy the ceiling of the right square across the line
+        let map = with_line_special(2, "special=118 arg4=8");
+        let planes = level::sector_planes(&map);
+        assert_eq!(planes[1].floor, Plane::Flat(32.));
+        assert_eq!(planes[1].ceiling, planes[0].ceiling);
+    }
+
+    #[test]
+    fn vavoom_slope() {
+        // Align the floor of the left square to the right square, so that
+        // it is at 0 on the left and 32 on the right, then slope it from
+        // line 3 at the bottom to the thing.
+        let mut map = with_line_special(2, "special=181 arg0=2");
+        map.linedefs[3].props.insert("arg0".into(), "3".into());
+        map.things = UDMFMap::from_str(r#"namespace = "zdoom";
+thing { x = 32.0; y = 32.0; height = 64.0; type = 1500; id = 3; }
+"#).unwrap().things;
+        let planes = level::sector_planes(&map);
+        // The coefficients P_VavoomSlope gives for this sector, which keep
+        // the heights of the aligned floor at the ends of line 3.
+        let length = 14f32.sqrt();
+        let expected = [-1. / length, -3. / length, 2. / length, 0.];
+        let Plane::Sloped(a, b, c, d) = planes[0].floor else {
+            panic!("{:?} is not sloped", planes[0].floor);
+        };
+        [a, b, c, d].into_iter().zip(expected).for_each(|(actual, expected)| {
+            assert!((actual - expected).abs() < 0.0001, "{actual} != {expected}");
+        });
+        assert_z(planes[0].floor, 64., 0., 32.);
+        assert_z(planes[0].floor, 0., 0., 0.);
+    }
+
+    #[test]
+    fn slope_things() {
+        let mut map = UDMFMap::from_str(SQUARES).unwrap();
+        map.things = UDMFMap::from_str(r#"namespace = "zdoom";
+thing { x = 32.0; y = 32.0; height = 32.0; type = 9500; arg0 = 7; }
+thing { x = 32.0; y = 32.0; angle = 90; type = 9503; arg0 = 45; }
+thing { x = 96.0; y = 32.0; type = 9502; arg0 = 45; }
+thing { x = 96.0; y = 32.0; type = 9511; arg0 = 5; }
+"#).unwrap().things;
+        let planes = level::sector_planes(&map);
+        // Sloped up from line 7 at x = 0 to the thing
+        assert_z(planes[0].floor, 0., 48., 0.);
+        assert_z(planes[0].floor, 64., 16., 64.);
+        // Sloped down by 45 degrees to the north
+        assert_z(planes[0].ceiling, 32., 32., 128.);
+        assert_z(planes[0].ceiling, 0., 64., 96.);
+        // Sloped down by 45 degrees to the east
+        assert_z(planes[1].floor, 96., 0., 32.);
+        assert_z(planes[1].floor, 128., 32., 0.);
+        // Copied from the sector the thing is in, so it isn't changed
+        assert_eq!(planes[1].ceiling, Plane::Flat(128.));
+    }
+}
@@ -0,0 +1,258 @@
+//! # Things
+//!
+//! Places things in 3D, on the floor or hanging from the ceiling of the
+//! sector they are in, and builds quads to draw them with. Things can be
+//! drawn as billboards, which are upright quads facing the viewer with the
+//! thing's sprite on them, or as markers, which are flat squares under the
+//! thing, turned the way the thing faces.
+//!
+//! How big things are, and what sprites they use, comes from a table of
+//! thing definitions. Things which aren't in the table use the `zthing`
+//! placeholder sprite, and things which can't be seen in the game, like map
+//! spots, use `TNT1A0`.
+use std::collections::HashMap;
+use ahash::RandomState;
+use glam::{Vec2, Vec3};
+use parsers::udmf::input::UDMFMap;
+use crate::attributes::ThingVertex;
+use crate::light;
+use crate::plane::SectorPlanes;
+use crate::sectorindex::SectorIndex;
+
+/// The sprite used for things which aren't in the definition table
+pub const UNKNOWN_SPRITE: &str = "zthing";
+/// The sprite used for things which can't be seen in the game
+pub const INVISIBLE_SPRITE: &str = "TNT1A0";
+
+/// The indices of the two triangles of a thing's quad. Like the walls, they
+/// go clockwise when seen from the side the quad faces.
+pub const QUAD_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+/// How big a type of thing is, and how it is drawn
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThingDef {
+    pub radius: f32,
+    pub height: f32,
+    /// The name of the sprite, e.g. `POSSA1`
+    pub sprite: String,
+    /// Whether the thing hangs from the ceiling, rather than standing on
+    /// the floor
+    pub hanging: bool,
+}
+
+impl ThingDef {
+    pub fn new(radius: f32, height: f32, sprite: &str, hanging: bool) -> ThingDef {
+        ThingDef { radius, height, sprite: String::from(sprite), hanging }
+    }
+
+    /// The definition used for things which aren't in the table
+    pub fn unknown() -> ThingDef {
+        ThingDef::new(8., 16., UNKNOWN_SPRITE, false)
+    }
+}
+
+/// Thing definitions, by editor number
+pub type ThingDefs = HashMap<u32, ThingDef, RandomState>;
+
+/// Get the definitions of some common Doom things, and of the editor things
+/// used by ZDoom, which can't be seen in the game
+pub fn doom_thing_defs() -> ThingDefs {
+    let things = [
+        // Player starts
+        (1, 16., 56., "PLAYA1", false),
+        (2, 16., 56., "PLAYA1", false),
+        (3, 16., 56., "PLAYA1", false),
+        (4, 16., 56., "PLAYA1", false),
+        (11, 16., 56., "PLAYF1", false),
+        // Monsters
+        (3004, 20., 56., "POSSA1", false),
+        (9, 20., 56., "SPOSA1", false),
+        (3001, 20., 56., "TROOA1", false),
+        (3002, 30., 56., "SARGA1", false),
+        (3003, 24., 64., "BOSSA1", false),
+        // Pickups
+        (2001, 20., 16., "SHOTA0", false),
+        (2011, 20., 16., "STIMA0", false),
+        (2012, 20., 16., "MEDIA0", false),
+        // Decorations
+        (2035, 10., 42., "BAR1A0", false),
+        (2028, 16., 48., "COLUA0", false),
+        (34, 20., 16., "CANDA0", false),
+        (44, 16., 68., "TBLUA0", false),
+        (63, 16., 68., "GOR1A0", true),
+        (59, 20., 84., "GOR2A0", true),
+    ];
+    // Teleport destinations, map spots, skybox viewpoints, polyobject
+    // things and slope things
+    let invisible = [
+        14, 9001, 9080, 9081, 9083, 9300, 9301, 9302, 9303, 1500, 1501, 1504,
+        1505, 9500, 9501, 9502, 9503, 9510, 9511,
+    ];
+    things.into_iter()
+        .map(|(ednum, radius, height, sprite, hanging)| {
+            (ednum, ThingDef::new(radius, height, sprite, hanging))
+        })
+        .chain(invisible.into_iter()
+            .map(|ednum| (ednum, ThingDef::new(8., 16., INVISIBLE_SPRITE, false))))
+        .collect()
+}
+
+/// A thing, placed in 3D
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedThing {
+    /// The index of the thing
+    pub thing: usize,
+    /// The index of the sector the thing is in, if it is in one
+    pub sector: Option<usize>,
+    /// The position of the bottom of the thing
+    pub position: Vec3,
+    /// The direction the thing faces, in radians, anticlockwise from east
+    pub angle: f32,
+    pub def: ThingDef,
+}
+
+impl PlacedThing {
+    /// Get the vertices for the given corners, with the light and fog of the
+    /// thing's sector
+    fn vertices(&self, map: &UDMFMap, corners: [Vec3; 4], uvs: [Vec2; 4]) -> [ThingVertex; 4] {
+        let (colour, fog) = self.sector.map_or((Vec3::ONE, Default::default()), |sector| {
+            let data = &map.sectors[sector];
+            (light::sprite_colour(data), light::sector_fog(data))
+        });
+        [0, 1, 2, 3].map(|corner| ThingVertex {
+            position: corners[corner],
+            colour,
+            fog,
+            uv: uvs[corner],
+            thing: self.thing,
+        })
+    }
+
+    /// Get an upright quad with the thing's sprite on it, which faces the
+    /// viewer. `view` is the direction the viewer is looking in; only its X
+    /// and Y matter. The quad is as wide as the thing, and as tall as it.
+    /// Use `QUAD_INDICES` for the triangles.
+    pub fn billboard(&self, map: &UDMFMap, view: Vec3) -> [ThingVertex; 4] {
+        let view = view.truncate().try_normalize().unwrap_or(Vec2::Y);
+        // To the right of the viewer
+        let right = Vec2::new(view.y, -view.x).extend(0.) * self.def.radius;
+        let up = Vec3::Z * self.def.height;
+        let bottom = self.position;
+        self.vertices(map, [
+            bottom - right + up,
+            bottom + right + up,
+            bottom + right,
+            bottom - right,
+        ], [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y])
+    }
+
+    /// Get a flat square under the thing, which faces up, and is as wide as
+    /// the thing. The top of the texture points the way the thing faces.
+    /// Use `QUAD_INDICES` for the triangles.
+    pub fn marker(&self, map: &UDMFMap) -> [ThingVertex; 4] {
+        let forward = Vec2::from_angle(self.angle).extend(0.) * self.def.radius;
+        let right = Vec3::new(forward.y, -forward.x, 0.);
+        let middle = self.position;
+        self.vertices(map, [
+            middle + forward - right,
+            middle + forward + right,
+            middle - forward + right,
+            middle - forward - right,
+        ], [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y])
+    }
+}
+
+/// Place every thing in the map in the sector it is in. Things stand on the
+/// floor, or hang from the ceiling if their definition says so, and their
+/// `height` is how far they are above the floor, or below the ceiling.
+/// Things outside of the map are placed at their `height` above 0.
+///
+/// `planes` are the floor and ceiling planes of each sector in the map, as
+/// returned by `level::sector_planes`.
+pub fn place_things(map: &UDMFMap, planes: &[SectorPlanes], defs: &ThingDefs) -> Vec<PlacedThing> {
+    let finder = SectorIndex::new(map);
+    map.things.iter().enumerate().map(|(index, thing)| {
+        let point = Vec2::new(thing.x, thing.y);
+        let sector = finder.sector_at(point);
+        let def = defs.get(&thing.ednum).cloned().unwrap_or_else(ThingDef::unknown);
+        let z = match sector {
+            Some(sector) if def.hanging => {
+                planes[sector].ceiling.z_at(point) - def.height - thing.height
+            },
+            Some(sector) => planes[sector].floor.z_at(point) + thing.height,
+            None => thing.height,
+        };
+        PlacedThing {
+            thing: index,
+            sector,
+            position: point.extend(z),
+            angle: (thing.angle as f32).to_radians(),
+            def,
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parsers::udmf::input::{
+        LightLevel, MultiplicativeColour, PropMap, UDMFThing
+    };
+    use crate::edit::testmap::square;
+    use crate::level;
+
+    // A 128x128 room with a raised floor and red sprites, with a trooper, a
+    // hanging thing, and an unknown thing outside of the room
+    fn test_map() -> UDMFMap {
+        let mut map = square();
+        let sector = &mut map.sectors[0];
+        sector.heightfloor = 16;
+        sector.lightlevel = LightLevel(128);
+        sector.color_sprites = MultiplicativeColour { r: 255, g: 0, b: 0 };
+        let thing = |x, y, height, angle, ednum| UDMFThing {
+            x, y, height, angle, ednum, id: 0, props: PropMap::default()
+        };
+        map.things = vec![
+            thing(64., 64., 8., 90, 3004),
+            thing(96., 96., 0., 0, 63),
+            thing(512., 512., 0., 0, 12345),
+        ];
+        map
+    }
+
+    #[test]
+    fn placement() {
+        let map = test_map();
+        let planes = level::sector_planes(&map);
+        let things = place_things(&map, &planes, &doom_thing_defs());
+        assert_eq!(things[0].sector, Some(0));
+        assert_eq!(things[0].position, Vec3::new(64., 64., 24.));
+        assert_eq!(things[0].def.sprite, "POSSA1");
+        // Hanging things are just below the ceiling
+        assert_eq!(things[1].position.z, 128. - 68.);
+        // Unknown things outside of the map
+        assert_eq!(things[2].sector, None);
+        assert_eq!(things[2].def, ThingDef::unknown());
+        assert_eq!(things[2].position.z, 0.);
+    }
+
+    #[test]
+    fn quads() {
+        let map = test_map();
+        let planes = level::sector_planes(&map);
+        let things = place_things(&map, &planes, &doom_thing_defs());
+        // Looking north, the billboard faces south
+        let billboard = things[0].billboard(&map, Vec3::Y);
+        assert_eq!(billboard[0].position, Vec3::new(44., 64., 80.));
+        assert_eq!(billboard[2].position, Vec3::new(84., 64., 24.));
+        assert_eq!(billboard[0].colour, Vec3::new(128. / 255., 0., 0.));
+        assert_eq!(billboard[3].thing, 0);
+        // The thing faces north, so the top of the marker is to the north
+        let marker = things[0].marker(&map);
+        assert!(marker[0].position.abs_diff_eq(Vec3::new(44., 84., 24.), 0.001));
+        assert!(marker[1].position.abs_diff_eq(Vec3::new(84., 84., 24.), 0.001));
+        let [a, b, c] = [0, 1, 2].map(|i| marker[QUAD_INDICES[i] as usize].position);
+        // Clockwise when seen from above
+        assert!((b - a).cross(c - a).z < 0.);
+    }
+}
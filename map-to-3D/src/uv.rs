@@ -0,0 +1,439 @@
+//! # Texture coordinates
+//!
+//! Works out where textures go on walls, the same way the Doom engine (and
+//! ZDoom's extensions to it) does.
+use glam::{Vec2, Vec3Swizzles};
+use parsers::udmf::input::{UDMFMap, UDMFSector, PropMap};
+use crate::level::{self, LineSide, WallPart};
+use crate::plane::{Plane, SectorPlanes};
+use crate::segment::Segment;
+use crate::wall::{self, LineQuad, Height};
+
+/// How a texture is placed on one part of one side of a line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WallTexturing {
+    /// The height which the top of the texture is pegged to, before the
+    /// vertical offset is applied
+    pub top: f32,
+    /// How far the texture is moved, in texture pixels. A positive X offset
+    /// moves the texture to the left, and a positive Y offset moves the
+    /// texture up.
+    pub offset: Vec2,
+    /// How many texture pixels there are for each map unit
+    pub scale: Vec2,
+    /// The size of the texture, in pixels
+    pub size: Vec2,
+}
+
+/// Get the ZDoom UDMF property name for the given part of a sidedef, e.g.
+/// `offsetx_mid`
+fn part_property(name: &str, part: WallPart) -> String {
+    let suffix = match part {
+        WallPart::Upper => "top",
+        WallPart::Middle => "mid",
+        WallPart::Lower => "bottom",
+    };
+    format!("{name}_{suffix}")
+}
+
+/// Get a scale property, which is 1 if it isn't set, or isn't usable
+fn scale_property(props: &PropMap, name: &str) -> f32 {
+    level::prop_number(props, name)
+        .filter(|scale| scale.is_finite() && *scale != 0.)
+        .unwrap_or(1.)
+}
+
+impl WallTexturing {
+    /// Work out how the texture is placed on the given part of one side of a
+    /// line, given the size of the texture in pixels.
+    ///
+    /// This uses the sidedef's offsets, ZDoom's offsets and scales for each
+    /// part (`offsetx_top`, `scaley_mid`, etc.), and the line's unpegged
+    /// flags. Textures are pegged to the heights of the sectors, rather than
+    /// to their planes, so sloped planes don't move the textures.
+    ///
+    /// Returns `None` if the side of the line doesn't have a sector.
+    pub fn new(
+        map: &UDMFMap,
+        line: usize,
+        side: LineSide,
+        part: WallPart,
+        size: Vec2
+    ) -> Option<WallTexturing> {
+        let linedef = &map.linedefs[line];
+        let sidedef = map.sidedefs.get(level::line_sidedef(linedef, side)?)?;
+        let this = &map.sectors[level::side_sector(map, line, side)?];
+        let other = level::side_sector(map, line, side.opposite())
+            .map(|sector| &map.sectors[sector]);
+        let props = &sidedef.props;
+        let offset = Vec2::new(
+            sidedef.offsetx as f32 + level::prop_number(
+                props, &part_property("offsetx", part)).unwrap_or(0.),
+            sidedef.offsety as f32 + level::prop_number(
+                props, &part_property("offsety", part)).unwrap_or(0.),
+        );
+        let scale = Vec2::new(
+            scale_property(props, &part_property("scalex", part)),
+            scale_property(props, &part_property("scaley", part)),
+        );
+        let peg_top = level::prop_flag(&linedef.props, "dontpegtop");
+        let peg_bottom = level::prop_flag(&linedef.props, "dontpegbottom");
+        // The height of the texture, in map units
+        let height = size.y / scale.y;
+        let (this_floor, this_ceiling) =
+            (this.heightfloor as f32, this.heightceiling as f32);
+        let top = match (part, other) {
+            // Middle textures on one-sided lines start at the ceiling, or end
+            // at the floor if the line is lower unpegged.
+            (_, None) => if peg_bottom {
+                this_floor + height
+            } else {
+                this_ceiling
+            },
+            (WallPart::Middle, Some(other)) => if peg_bottom {
+                this_floor.max(other.heightfloor as f32) + height
+            } else {
+                this_ceiling.min(other.heightceiling as f32)
+            },
+            // Upper textures end at the ceiling on the other side of the line,
+            // or start at the ceiling if the line is upper unpegged.
+            (WallPart::Upper, Some(other)) => if peg_top {
+                this_ceiling
+            } else {
+                other.heightceiling as f32 + height
+            },
+            // Lower textures start at the floor on the other side of the line,
+            // or at the ceiling if the line is lower unpegged.
+            (WallPart::Lower, Some(other)) => if peg_bottom {
+                this_ceiling
+            } else {
+                other.heightfloor as f32
+            },
+        };
+        Some(WallTexturing { top, offset, scale, size })
+    }
+
+    /// Get the texture coordinates for a point on the wall, given how far it
+    /// is from the left end of the line, and its height.
+    pub fn uv(&self, along: f32, z: f32) -> Vec2 {
+        Vec2::new(
+            along * self.scale.x + self.offset.x,
+            (self.top - z) * self.scale.y + self.offset.y,
+        ) / self.size
+    }
+
+    /// Get the heights of the top and bottom of the texture, if it is only
+    /// drawn once rather than being repeated vertically.
+    pub fn extent(&self) -> (f32, f32) {
+        let top = self.top + self.offset.y / self.scale.y;
+        (top, top - self.size.y / self.scale.y)
+    }
+}
+
+/// Get the texture coordinates for each vertex of the wall, given the size
+/// of its texture in pixels. Returns `None` if the side of the line the wall
+/// is on doesn't have a sector.
+///
+/// # Example
+///
+/// ```
+/// use std::str::FromStr;
+/// use glam::Vec2;
+/// use parsers::udmf::input::UDMFMap;
+/// use map_to_3D::level;
+/// use map_to_3D::uv;
+/// use map_to_3D::wall::{LineQuads, WallOptions};
+///
+/// let map = UDMFMap::from_str(r#"namespace = "zdoom";
+/// vertex { x = 0.0; y = 0.0; }
+/// vertex { x = 64.0; y = 0.0; }
+/// linedef { v1 = 0; v2 = 1; sidefront = 0; }
+/// sidedef { sector = 0; texturemiddle = "STARTAN2"; }
+/// sector { texturefloor = "FLAT1"; textureceiling = "FLAT1";
+///     heightceiling = 128; }
+/// "#).unwrap();
+/// let planes = level::sector_planes(&map);
+/// let quads = LineQuads::for_line(&map, 0, &planes, &WallOptions::default());
+/// let uvs = uv::wall_uvs(&map, &quads[0], Vec2::new(128., 128.)).unwrap();
+/// assert_eq!(uvs, [
+///     Vec2::new(0., 0.),
+///     Vec2::new(0.5, 0.),
+///     Vec2::new(0.5, 1.),
+///     Vec2::new(0., 1.),
+/// ]);
+/// ```
+pub fn wall_uvs(map: &UDMFMap, quad: &LineQuad, size: Vec2) -> Option<[Vec2; 4]> {
+    let texturing = WallTexturing::new(
+        map, quad.line, quad.side, quad.part, size)?;
+    let Segment(start, _) = level::side_segment(map, quad.line, quad.side);
+    Some(quad.vertices.map(|vertex| {
+        texturing.uv(vertex.xy().distance(start), vertex.z)
+    }))
+}
+
+/// Clip the middle part of a two-sided line so that it only covers its
+/// texture, since middle textures on two-sided lines aren't repeated
+/// vertically, unless the line or sidedef has the `wrapmidtex` flag set.
+///
+/// Middle textures are always clipped to the gap between the sectors on
+/// either side of the line, since anything outside of the gap would be
+/// hidden by the upper and lower walls. Because of this, the `clipmidtex`
+/// flag doesn't change anything.
+///
+/// Returns the parts of the wall which are inside of the gap, which is none
+/// of it if none of the texture is inside of the gap. Other walls are
+/// returned unchanged.
+pub fn clip_middle_wall(
+    map: &UDMFMap,
+    quad: &LineQuad,
+    planes: &[SectorPlanes],
+    size: Vec2
+) -> Vec<LineQuad> {
+    let line = &map.linedefs[quad.line];
+    let (Some(this), Some(other)) = (
+        level::side_sector(map, quad.line, quad.side),
+        level::side_sector(map, quad.line, quad.side.opposite())
+    ) else {
+        return vec![quad.clone()];
+    };
+    let wraps = level::prop_flag(&line.props, "wrapmidtex") ||
+        level::line_sidedef(line, quad.side)
+            .and_then(|sidedef| map.sidedefs.get(sidedef))
+            .is_some_and(|sidedef| level::prop_flag(&sidedef.props, "wrapmidtex"));
+    if quad.part != WallPart::Middle || wraps {
+        return vec![quad.clone()];
+    }
+    let Some(texturing) = WallTexturing::new(
+        map, quad.line, quad.side, quad.part, size) else {
+        return Vec::new();
+    };
+    let (top, bottom) = texturing.extent();
+    let (this, other) = (planes[this], planes[other]);
+    // The wall may only be part of the line, if it was split where the planes
+    // cross each other
+    let (start, end) = (quad.vertices[0].truncate(), quad.vertices[1].truncate());
+    wall::wall_vertices(
+        start, end,
+        Height::Lowest(vec![this.ceiling, other.ceiling, Plane::Flat(top)]),
+        Height::Highest(vec![this.floor, other.floor, Plane::Flat(bottom)]),
+    ).into_iter().map(|vertices| LineQuad { vertices, ..quad.clone() }).collect()
+}
+
+/// How a texture is placed on a floor or ceiling
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlatTexturing {
+    /// How far the texture is moved, in texture pixels
+    pub panning: Vec2,
+    /// How many times bigger the texture coordinates are than usual
+    pub scale: Vec2,
+    /// How far the texture is rotated, in radians
+    pub rotation: f32,
+    /// The size of the texture, in pixels
+    pub size: Vec2,
+}
+
+impl FlatTexturing {
+    /// Work out how the texture is placed on the floor or ceiling of the
+    /// sector, given the size of the texture in pixels.
+    ///
+    /// This uses ZDoom's panning, scale and rotation properties for floors
+    /// and ceilings (`xpanningfloor`, `yscaleceiling`, `rotationfloor`, etc.)
+    pub fn new(sector: &UDMFSector, ceiling: bool, size: Vec2) -> FlatTexturing {
+        let props = &sector.props;
+        let plane = if ceiling { "ceiling" } else { "floor" };
+        let number = |name: &str| {
+            level::prop_number(props, &format!("{name}{plane}")).unwrap_or(0.)
+        };
+        FlatTexturing {
+            panning: Vec2::new(number("xpanning"), number("ypanning")),
+            scale: Vec2::new(
+                scale_property(props, &format!("xscale{plane}")),
+                scale_property(props, &format!("yscale{plane}")),
+            ),
+            rotation: number("rotation").to_radians(),
+            size,
+        }
+    }
+
+    /// Get the texture coordinates for a point on the floor or ceiling.
+    ///
+    /// Like in GZDoom, flats are aligned to the map grid, and sloped floors
+    /// and ceilings are textured as if the texture was projected straight
+    /// down onto them, so only the X and Y coordinates of the point matter.
+    pub fn uv(&self, point: Vec2) -> Vec2 {
+        // The Y axis goes down in textures, and up in maps. The rotation is
+        // clockwise.
+        let point = Vec2::new(point.x, -point.y);
+        let rotated = Vec2::from_angle(-self.rotation).rotate(point);
+        (rotated + self.panning) * self.scale / self.size
+    }
+}
+
+/// Get the texture coordinates for the floor or ceiling of the sector at each
+/// of the given vertex positions, given the size of the texture in pixels.
+///
+/// # Example
+///
+/// ```
+/// use std::str::FromStr;
+/// use glam::Vec2;
+/// use parsers::udmf::input::UDMFMap;
+/// use map_to_3D::uv;
+///
+/// let map = UDMFMap::from_str(r#"namespace = "zdoom";
+/// sector { texturefloor = "FLAT1"; textureceiling = "FLAT1";
+///     xpanningfloor = 32.0; }
+/// "#).unwrap();
+/// let vertices = [Vec2::new(0., 0.), Vec2::new(64., 128.)];
+/// let uvs = uv::flat_uvs(&map, 0, false, &vertices, Vec2::new(64., 64.));
+/// assert_eq!(uvs, vec![Vec2::new(0.5, 0.), Vec2::new(1.5, -2.)]);
+/// ```
+pub fn flat_uvs(
+    map: &UDMFMap,
+    sector: usize,
+    ceiling: bool,
+    vertices: &[Vec2],
+    size: Vec2
+) -> Vec<Vec2> {
+    let texturing = FlatTexturing::new(&map.sectors[sector], ceiling, size);
+    vertices.iter().map(|&vertex| texturing.uv(vertex)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use glam::Vec3;
+    use crate::wall::{LineQuads, WallOptions};
+
+    // A 64 unit long line, with a 128 unit tall sector in front of it, and a
+    // sector from 16 to 96 behind it.
+    const TEXTMAP: &str = r#"namespace = "zdoom";
+vertex { x = 0.0; y = 0.0; }
+vertex { x = 64.0; y = 0.0; }
+linedef { v1 = 0; v2 = 1; sidefront = 0; sideback = 1; }
+sidedef { sector = 0; texturetop = "UPPER"; texturemiddle = "MIDBARS";
+    texturebottom = "LOWER"; }
+sidedef { sector = 1; }
+sector { texturefloor = "FLAT1"; textureceiling = "FLAT1";
+    heightceiling = 128; }
+sector { texturefloor = "FLAT1"; textureceiling = "FLAT1";
+    heightfloor = 16; heightceiling = 96; }
+"#;
+
+    const SIZE: Vec2 = Vec2::new(64., 64.);
+
+    fn front_quad(map: &UDMFMap, part: WallPart) -> LineQuad {
+        let planes = level::sector_planes(map);
+        LineQuads::for_line(map, 0, &planes, &WallOptions::default()).iter()
+            .find(|quad| quad.side == LineSide::Front && quad.part == part)
+            .unwrap().clone()
+    }
+
+    fn v(map: &UDMFMap, part: WallPart) -> [f32; 4] {
+        wall_uvs(map, &front_quad(map, part), SIZE).unwrap().map(|uv| uv.y)
+    }
+
+    fn set_line_flag(map: &mut UDMFMap, flag: &str) {
+        map.linedefs[0].props.insert(String::from(flag), String::from("true"));
+    }
+
+    #[test]
+    fn one_sided_pegging() {
+        let mut map = UDMFMap::from_str(TEXTMAP).unwrap();
+        map.linedefs[0].sideback = parsers::udmf::input::SidedefIndex(-1);
+        let uvs = wall_uvs(&map, &front_quad(&map, WallPart::Middle), SIZE);
+        assert_eq!(uvs, Some([
+            Vec2::new(0., 0.),
+            Vec2::new(1., 0.),
+            Vec2::new(1., 2.),
+            Vec2::new(0., 2.),
+        ]));
+        // Lower unpegged: the bottom of the texture is at the floor
+        set_line_flag(&mut map, "dontpegbottom");
+        let texturing = WallTexturing::new(
+            &map, 0, LineSide::Front, WallPart::Middle, Vec2::new(64., 72.));
+        assert_eq!(texturing.unwrap().uv(0., 0.), Vec2::new(0., 1.));
+    }
+
+    #[test]
+    fn upper_and_lower_pegging() {
+        let mut map = UDMFMap::from_str(TEXTMAP).unwrap();
+        // The upper texture ends at the back ceiling, and the lower texture
+        // starts at the back floor.
+        assert_eq!(v(&map, WallPart::Upper), [0.5, 0.5, 1., 1.]);
+        assert_eq!(v(&map, WallPart::Lower), [0., 0., 0.25, 0.25]);
+        // Unpegged upper textures start at the front ceiling, and unpegged
+        // lower textures are pegged to the front ceiling.
+        set_line_flag(&mut map, "dontpegtop");
+        set_line_flag(&mut map, "dontpegbottom");
+        assert_eq!(v(&map, WallPart::Upper), [0., 0., 0.5, 0.5]);
+        assert_eq!(v(&map, WallPart::Lower), [1.75, 1.75, 2., 2.]);
+    }
+
+    #[test]
+    fn offsets_and_scales() {
+        let mut map = UDMFMap::from_str(TEXTMAP).unwrap();
+        map.sidedefs[0].offsetx = 8;
+        map.sidedefs[0].offsety = 16;
+        let props = &mut map.sidedefs[0].props;
+        props.insert(String::from("offsetx_mid"), String::from("8.0"));
+        props.insert(String::from("scalex_mid"), String::from("2.0"));
+        props.insert(String::from("scaley_top"), String::from("0.5"));
+        props.insert(String::from("wrapmidtex"), String::from("true"));
+        let uvs = wall_uvs(&map, &front_quad(&map, WallPart::Middle), SIZE)
+            .unwrap();
+        assert_eq!(uvs[0], Vec2::new(0.25, 0.25));
+        assert_eq!(uvs[1], Vec2::new(2.25, 0.25));
+        // The upper texture is 128 units tall, so it starts 128 units above
+        // the back ceiling, and the Y offset moves it up by a quarter.
+        assert_eq!(v(&map, WallPart::Upper), [1., 1., 1.25, 1.25]);
+    }
+
+    #[test]
+    fn middle_texture_clipping() {
+        let mut map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let planes = level::sector_planes(&map);
+        let size = Vec2::new(64., 32.);
+        let heights = |map: &UDMFMap| {
+            clip_middle_wall(map, &front_quad(map, WallPart::Middle), &planes, size)
+                .first().map(|quad| (quad.vertices[0].z, quad.vertices[3].z))
+        };
+        // Pegged to the lower ceiling
+        assert_eq!(heights(&map), Some((96., 64.)));
+        map.sidedefs[0].offsety = 16;
+        assert_eq!(heights(&map), Some((96., 80.)));
+        map.sidedefs[0].offsety = 64;
+        assert_eq!(heights(&map), None);
+        // Pegged to the higher floor
+        map.sidedefs[0].offsety = 0;
+        set_line_flag(&mut map, "dontpegbottom");
+        assert_eq!(heights(&map), Some((48., 16.)));
+        // Wrapped middle textures fill the whole gap
+        set_line_flag(&mut map, "wrapmidtex");
+        assert_eq!(heights(&map), Some((96., 16.)));
+        let quads = clip_middle_wall(
+            &map, &front_quad(&map, WallPart::Middle), &planes, size);
+        assert_eq!(quads.len(), 1);
+        assert_eq!(quads[0].vertices[1], Vec3::new(64., 0., 96.));
+    }
+
+    #[test]
+    fn flat_texturing() {
+        let mut map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let sector = &mut map.sectors[0];
+        let size = Vec2::new(64., 64.);
+        let point = Vec2::new(64., 32.);
+        let uv = |sector: &UDMFSector, ceiling| {
+            FlatTexturing::new(sector, ceiling, size).uv(point)
+        };
+        assert_eq!(uv(sector, false), Vec2::new(1., -0.5));
+        sector.props.insert(String::from("xpanningfloor"), String::from("16"));
+        sector.props.insert(String::from("yscalefloor"), String::from("2"));
+        assert_eq!(uv(sector, false), Vec2::new(1.25, -1.));
+        // The ceiling isn't affected by the floor's properties
+        assert_eq!(uv(sector, true), Vec2::new(1., -0.5));
+        sector.props.insert(String::from("rotationceiling"), String::from("90"));
+        assert!(uv(sector, true).abs_diff_eq(Vec2::new(-0.5, -1.), 0.0001));
+    }
+}
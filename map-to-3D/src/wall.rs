@@ -1,13 +1,480 @@
+//! # Walls
+//!
+//! Builds the quads for the upper, middle and lower parts of each side of
+//! each line, using the floor and ceiling planes of the sectors on either
+//! side of the line.
 use glam::{Vec2, Vec3};
+use derive_deref::{Deref, DerefMut};
+use parsers::mapinfo::MapInfo;
+use parsers::udmf::input::UDMFMap;
+use crate::level::{self, LineSide, WallPart};
+use crate::plane::{Plane, SectorPlanes};
+use crate::segment::Segment;
 
-#[derive(Debug, Clone)]
+/// Options for building walls, and for finding out which surfaces show the
+/// sky
+#[derive(Debug, Clone, PartialEq)]
+pub struct WallOptions {
+    /// The flat which makes a floor or ceiling show the sky
+    pub sky_flat: String,
+    /// The texture which is shown where the sky can be seen
+    pub sky_texture: String,
+}
+
+impl Default for WallOptions {
+    fn default() -> Self {
+        WallOptions {
+            sky_flat: String::from("F_SKY1"),
+            sky_texture: String::from("SKY1"),
+        }
+    }
+}
+
+impl WallOptions {
+    /// Get the options for the map with the given lump name, using the sky
+    /// flat from the `gameinfo` block and the sky texture from the map's
+    /// definition, if they are set.
+    pub fn from_mapinfo(mapinfo: &MapInfo, lump: &str) -> WallOptions {
+        let mut options = WallOptions::default();
+        if let Some(flat) = mapinfo.gameinfo_property("skyflatname")
+            .and_then(|values| values.first()) {
+            options.sky_flat.clone_from(flat);
+        }
+        if let Some(texture) = mapinfo.sky1(lump) {
+            options.sky_texture = String::from(texture);
+        }
+        options
+    }
+
+    /// Whether the given flat shows the sky
+    pub fn is_sky(&self, flat: &str) -> bool {
+        flat.eq_ignore_ascii_case(&self.sky_flat)
+    }
+}
+
+/// One part of one side of a line
+///
+/// The vertices are the top left, top right, bottom right, and bottom left
+/// corners of the wall, when looking at it from the side it belongs to. If
+/// the planes at the top and bottom of the wall cross each other along the
+/// line, the wall is a triangle, and two of the vertices are in the same
+/// place.
+#[derive(Debug, Clone, PartialEq)]
 pub struct LineQuad {
-    pub vertices: [Vec3; 4]
+    pub vertices: [Vec3; 4],
+    /// The index of the line
+    pub line: usize,
+    /// Which side of the line the wall is on
+    pub side: LineSide,
+    /// Which part of the side the wall is
+    pub part: WallPart,
 }
 
-#[derive(Debug, Clone)]
-pub struct LineQuads (Vec<LineQuad>);
+impl LineQuad {
+    /// Get the triangles which make up the wall, as indices into `vertices`.
+    /// The triangles go clockwise when looking at the wall from the side it
+    /// belongs to. Triangles with no area are left out.
+    pub fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        [[0, 1, 2], [0, 2, 3]].into_iter().filter(|triangle| {
+            let [a, b, c] = triangle.map(|index| self.vertices[index]);
+            (b - a).cross(c - a) != Vec3::ZERO
+        })
+    }
+}
+
+/// The walls of some or all of the lines in a map
+#[derive(Debug, Clone, PartialEq, Default, Deref, DerefMut)]
+pub struct LineQuads(Vec<LineQuad>);
 
 impl LineQuads {
+    pub fn new() -> LineQuads {
+        LineQuads::default()
+    }
+
+    /// Build the walls for both sides of the given line. `planes` are the
+    /// floor and ceiling planes of each sector in the map, as returned by
+    /// `level::sector_planes`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::str::FromStr;
+    /// use parsers::udmf::input::UDMFMap;
+    /// use map_to_3D::level::{self, WallPart};
+    /// use map_to_3D::wall::{LineQuads, WallOptions};
+    ///
+    /// let map = UDMFMap::from_str(r#"namespace = "zdoom";
+    /// vertex { x = 0.0; y = 0.0; }
+    /// vertex { x = 64.0; y = 0.0; }
+    /// linedef { v1 = 0; v2 = 1; sidefront = 0; sideback = 1; }
+    /// sidedef { sector = 0; texturetop = "STARTAN2"; texturebottom = "STARTAN2"; }
+    /// sidedef { sector = 1; }
+    /// sector { texturefloor = "FLAT1"; textureceiling = "FLAT1";
+    ///     heightceiling = 128; }
+    /// sector { texturefloor = "FLAT1"; textureceiling = "FLAT1";
+    ///     heightfloor = 16; heightceiling = 96; }
+    /// "#).unwrap();
+    /// let planes = level::sector_planes(&map);
+    /// let quads = LineQuads::for_line(&map, 0, &planes, &WallOptions::default());
+    /// // The front side has an upper and a lower wall, and the back side has
+    /// // nothing, since its sector is inside of the front side's sector.
+    /// let parts: Vec<WallPart> = quads.iter().map(|quad| quad.part).collect();
+    /// assert_eq!(parts, vec![WallPart::Upper, WallPart::Lower]);
+    /// ```
+    pub fn for_line(
+        map: &UDMFMap,
+        line: usize,
+        planes: &[SectorPlanes],
+        options: &WallOptions
+    ) -> LineQuads {
+        let mut quads = LineQuads::new();
+        [LineSide::Front, LineSide::Back].into_iter().for_each(|side| {
+            side_quads(map, line, side, planes, options, &mut quads);
+        });
+        quads
+    }
+
+    /// Build the walls for every line in the map. `walls` is the map from
+    /// `polyobject::walls_map`, with the polyobjects moved to their start
+    /// spots, and the same map should be given to
+    /// `buffer::BufferBuilder::add_walls` to texture and light the walls.
+    pub fn for_map(
+        walls: &UDMFMap,
+        planes: &[SectorPlanes],
+        options: &WallOptions
+    ) -> LineQuads {
+        let mut quads = LineQuads::new();
+        (0..walls.linedefs.len()).for_each(|line| {
+            quads.extend(LineQuads::for_line(walls, line, planes, options).0);
+        });
+        quads
+    }
+}
+
+/// The height of the lowest or highest of some planes, along a line
+#[derive(Debug, Clone)]
+pub(crate) enum Height {
+    Lowest(Vec<Plane>),
+    Highest(Vec<Plane>),
+}
+
+impl Height {
+    fn plane(plane: Plane) -> Height {
+        Height::Lowest(vec![plane])
+    }
+
+    /// Get the lowest or highest plane at the given point
+    fn plane_at(&self, point: Vec2) -> Plane {
+        let height = |plane: &&Plane| plane.z_at(point);
+        match self {
+            Height::Lowest(planes) => planes.iter()
+                .min_by(|a, b| height(a).total_cmp(&height(b))),
+            Height::Highest(planes) => planes.iter()
+                .max_by(|a, b| height(a).total_cmp(&height(b))),
+        }.copied().unwrap_or_default()
+    }
+
+    /// Get the points where the planes cross each other along the line, as
+    /// fractions of the way from `start` to `end`. The lowest or highest
+    /// plane can only change at these points.
+    fn crossings(&self, start: Vec2, end: Vec2) -> Vec<f32> {
+        let (Height::Lowest(planes) | Height::Highest(planes)) = self;
+        let length = start.distance(end);
+        planes.iter().enumerate().flat_map(|(index, plane)| {
+            planes[index + 1..].iter()
+                .filter_map(move |other| plane.intersection(start, end, other))
+        }).map(|point| point.truncate().distance(start) / length)
+            .filter(|&along| along < 1.)
+            .collect()
+    }
+}
+
+fn side_quads(
+    map: &UDMFMap,
+    line: usize,
+    side: LineSide,
+    planes: &[SectorPlanes],
+    options: &WallOptions,
+    quads: &mut LineQuads
+) {
+    let Some(sector) = level::side_sector(map, line, side) else {
+        return;
+    };
+    let Some(sidedef) = level::line_sidedef(&map.linedefs[line], side)
+        .and_then(|sidedef| map.sidedefs.get(sidedef)) else {
+        return;
+    };
+    let vertex_count = map.vertices.len() as u32;
+    let linedef = &map.linedefs[line];
+    if linedef.v1 >= vertex_count || linedef.v2 >= vertex_count {
+        return;
+    }
+    let Segment(start, end) = level::side_segment(map, line, side);
+    // Parts with no texture aren't drawn
+    let mut add = |part: WallPart, top: Height, bottom: Height| {
+        if part.texture(sidedef).0 == "-" {
+            return;
+        }
+        wall_vertices(start, end, top, bottom).into_iter().for_each(|vertices| {
+            quads.push(LineQuad { vertices, line, side, part });
+        });
+    };
+    let this = planes[sector];
+    let Some(other) = level::side_sector(map, line, side.opposite()) else {
+        add(WallPart::Middle, Height::plane(this.ceiling),
+            Height::plane(this.floor));
+        return;
+    };
+    let that = planes[other];
+    let (this_sector, other_sector) = (&map.sectors[sector], &map.sectors[other]);
+    // The sky hack: if both sides of the line have a sky ceiling, the upper
+    // wall is left out, so that the sky can be seen above it. Lower walls
+    // are always drawn, even between sky floors.
+    if !(options.is_sky(&this_sector.textureceiling) &&
+        options.is_sky(&other_sector.textureceiling)) {
+        add(WallPart::Upper, Height::plane(this.ceiling),
+            Height::plane(that.ceiling));
+    }
+    add(WallPart::Lower, Height::plane(that.floor), Height::plane(this.floor));
+    // The middle part of a two-sided line fills the gap between the two
+    // sectors. Use `uv::clip_middle_wall` to make it only cover the texture.
+    add(WallPart::Middle,
+        Height::Lowest(vec![this.ceiling, that.ceiling]),
+        Height::Highest(vec![this.floor, that.floor]));
+}
+
+/// Get the vertices of a wall between the given heights, going from `start`
+/// to `end`.
+///
+/// Where the lowest or highest of the planes at the top or bottom of the wall
+/// changes part of the way along the line, the wall is split in two, so that
+/// it follows both planes. Parts where the top of the wall isn't above the
+/// bottom are left out.
+pub(crate) fn wall_vertices(
+    start: Vec2,
+    end: Vec2,
+    top: Height,
+    bottom: Height
+) -> Vec<[Vec3; 4]> {
+    let mut splits: Vec<f32> = top.crossings(start, end).into_iter()
+        .chain(bottom.crossings(start, end)).collect();
+    splits.sort_by(f32::total_cmp);
+    let splits: Vec<f32> = std::iter::once(0.).chain(splits)
+        .chain(std::iter::once(1.)).collect();
+    splits.windows(2).filter(|pair| pair[1] > pair[0]).filter_map(|pair| {
+        let (a, b) = (start.lerp(end, pair[0]), start.lerp(end, pair[1]));
+        let middle = (a + b) / 2.;
+        piece_vertices(a, b, top.plane_at(middle), bottom.plane_at(middle))
+    }).collect()
+}
+
+/// Get the vertices of a wall between two planes, going from `start` to
+/// `end`. Returns `None` if the top of the wall isn't above the bottom
+/// anywhere along the line.
+fn piece_vertices(start: Vec2, end: Vec2, top: Plane, bottom: Plane) -> Option<[Vec3; 4]> {
+    let (top_start, top_end) = (top.z_at(start), top.z_at(end));
+    let (bottom_start, bottom_end) = (bottom.z_at(start), bottom.z_at(end));
+    let gap_start = top_start - bottom_start;
+    let gap_end = top_end - bottom_end;
+    if gap_start <= 0. && gap_end <= 0. {
+        return None;
+    }
+    let vertices = [
+        start.extend(top_start),
+        end.extend(top_end),
+        end.extend(bottom_end),
+        start.extend(bottom_start),
+    ];
+    if gap_start >= 0. && gap_end >= 0. {
+        return Some(vertices);
+    }
+    // The top and bottom planes cross each other along the line, so only
+    // the triangle where the top is above the bottom is used.
+    let crossing = top.intersection(start, end, &bottom)?;
+    Some(if gap_start > 0. {
+        [vertices[0], crossing, crossing, vertices[3]]
+    } else {
+        [crossing, vertices[1], vertices[2], crossing]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // Two 64x64 squares next to each other. The line between them goes from
+    // (64, 64) to (64, 0), with the left square in front of it.
+    const TEXTMAP: &str = r#"namespace = "zdoom";
+vertex { x = 0.0; y = 0.0; }
+vertex { x = 64.0; y = 0.0; }
+vertex { x = 64.0; y = 64.0; }
+vertex { x = 0.0; y = 64.0; }
+vertex { x = 128.0; y = 0.0; }
+vertex { x = 128.0; y = 64.0; }
+linedef { v1 = 0; v2 = 3; sidefront = 0; }
+linedef { v1 = 3; v2 = 2; sidefront = 0; }
+linedef { v1 = 1; v2 = 0; sidefront = 0; }
+linedef { v1 = 2; v2 = 1; sidefront = 0; sideback = 1; }
+linedef { v1 = 2; v2 = 5; sidefront = 1; }
+linedef { v1 = 5; v2 = 4; sidefront = 1; }
+linedef { v1 = 4; v2 = 1; sidefront = 1; }
+sidedef { sector = 0; texturetop = "STARTAN2"; texturemiddle = "STARTAN2";
+    texturebottom = "STARTAN2"; }
+sidedef { sector = 1; texturetop = "STARTAN2"; texturemiddle = "STARTAN2";
+    texturebottom = "STARTAN2"; }
+sector { texturefloor = "FLAT1"; textureceiling = "F_SKY1";
+    heightceiling = 128; }
+sector { texturefloor = "FLAT1"; textureceiling = "F_SKY1";
+    heightfloor = 16; heightceiling = 96; }
+"#;
+
+    fn parts(quads: &LineQuads) -> Vec<(LineSide, WallPart)> {
+        quads.iter().map(|quad| (quad.side, quad.part)).collect()
+    }
+
+    #[test]
+    fn one_sided() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let planes = level::sector_planes(&map);
+        let quads = LineQuads::for_line(&map, 0, &planes, &WallOptions::default());
+        assert_eq!(parts(&quads), vec![(LineSide::Front, WallPart::Middle)]);
+        assert_eq!(quads[0].vertices, [
+            Vec3::new(0., 0., 128.),
+            Vec3::new(0., 64., 128.),
+            Vec3::new(0., 64., 0.),
+            Vec3::new(0., 0., 0.),
+        ]);
+        assert_eq!(quads[0].triangles().count(), 2);
+        let quads = LineQuads::for_map(&map, &planes, &WallOptions::default());
+        assert_eq!(quads.iter().filter(|q| q.part == WallPart::Middle).count(), 8);
+    }
+
+    #[test]
+    fn two_sided_sky_hack() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let planes = level::sector_planes(&map);
+        let quads = LineQuads::for_line(&map, 3, &planes, &WallOptions::default());
+        // Both ceilings are sky, so there is no upper wall. The middle
+        // texture fills the gap between the sectors on both sides.
+        assert_eq!(parts(&quads), vec![
+            (LineSide::Front, WallPart::Lower),
+            (LineSide::Front, WallPart::Middle),
+            (LineSide::Back, WallPart::Middle),
+        ]);
+        assert_eq!(quads[0].vertices, [
+            Vec3::new(64., 64., 16.),
+            Vec3::new(64., 0., 16.),
+            Vec3::new(64., 0., 0.),
+            Vec3::new(64., 64., 0.),
+        ]);
+        assert_eq!(quads[2].vertices, [
+            Vec3::new(64., 0., 96.),
+            Vec3::new(64., 64., 96.),
+            Vec3::new(64., 64., 16.),
+            Vec3::new(64., 0., 16.),
+        ]);
+        let options = WallOptions {
+            sky_flat: String::from("F_SKY2"),
+            ..Default::default()
+        };
+        let quads = LineQuads::for_line(&map, 3, &planes, &options);
+        assert_eq!(quads[0].part, WallPart::Upper);
+    }
+
+    #[test]
+    fn untextured_parts() {
+        let mut map = UDMFMap::from_str(TEXTMAP).unwrap();
+        map.sidedefs.iter_mut().for_each(|sidedef| {
+            sidedef.texturetop.0 = String::from("-");
+            sidedef.texturebottom.0 = String::from("-");
+        });
+        let planes = level::sector_planes(&map);
+        // Without the sky hack, the step would have an upper and a lower
+        // wall, but they have no textures
+        let options = WallOptions {
+            sky_flat: String::from("F_SKY2"),
+            ..Default::default()
+        };
+        let quads = LineQuads::for_line(&map, 3, &planes, &options);
+        assert_eq!(parts(&quads), vec![
+            (LineSide::Front, WallPart::Middle),
+            (LineSide::Back, WallPart::Middle),
+        ]);
+    }
+
+    #[test]
+    fn sky_floors_keep_lower_walls() {
+        let textmap = TEXTMAP.replace(
+            "texturefloor = \"FLAT1\"", "texturefloor = \"F_SKY1\"");
+        let map = UDMFMap::from_str(&textmap).unwrap();
+        let planes = level::sector_planes(&map);
+        let quads = LineQuads::for_line(&map, 3, &planes, &WallOptions::default());
+        // Only the ceilings are affected by the sky hack
+        assert_eq!(parts(&quads), vec![
+            (LineSide::Front, WallPart::Lower),
+            (LineSide::Front, WallPart::Middle),
+            (LineSide::Back, WallPart::Middle),
+        ]);
+    }
+
+    #[test]
+    fn middle_wall_follows_both_ceilings() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let mut planes = level::sector_planes(&map);
+        // The ceiling on the right goes from 160 at the top of the line to 96
+        // at the bottom, so it crosses the ceiling on the left, at 128, half
+        // way along the line.
+        planes[1].ceiling = Plane::from_triangle(
+            Vec3::new(64., 64., 160.),
+            Vec3::new(128., 0., 96.),
+            Vec3::new(64., 0., 96.),
+        );
+        let quads = LineQuads::for_line(&map, 3, &planes, &WallOptions::default());
+        let middle: Vec<&LineQuad> = quads.iter()
+            .filter(|quad| quad.side == LineSide::Front && quad.part == WallPart::Middle)
+            .collect();
+        assert_eq!(middle.len(), 2);
+        let expected = [
+            [(64., 128.), (32., 128.), (32., 16.), (64., 16.)],
+            [(32., 128.), (0., 96.), (0., 16.), (32., 16.)],
+        ];
+        middle.iter().zip(expected).for_each(|(quad, expected)| {
+            quad.vertices.iter().zip(expected).for_each(|(vertex, (y, z))| {
+                assert!(vertex.abs_diff_eq(Vec3::new(64., y, z), 0.001), "{vertex}");
+            });
+        });
+    }
 
+    #[test]
+    fn sloped_floors_cross() {
+        let map = UDMFMap::from_str(TEXTMAP).unwrap();
+        let mut planes = level::sector_planes(&map);
+        // The floor on the right goes from 32 at the top of the line to -32
+        // at the bottom, so it crosses the floor on the left, at 0, half way
+        // along the line.
+        planes[1].floor = Plane::from_triangle(
+            Vec3::new(64., 64., 32.),
+            Vec3::new(64., 0., -32.),
+            Vec3::new(128., 0., -32.),
+        );
+        let quads = LineQuads::for_line(&map, 3, &planes, &WallOptions::default());
+        let lower: Vec<&LineQuad> = quads.iter()
+            .filter(|quad| quad.part == WallPart::Lower).collect();
+        assert_eq!(lower.len(), 2);
+        // The front side sees the part where the right floor is higher
+        let front = lower[0];
+        assert_eq!(front.side, LineSide::Front);
+        assert_eq!(front.triangles().count(), 1);
+        assert!(front.vertices[0].abs_diff_eq(Vec3::new(64., 64., 32.), 0.001));
+        assert!(front.vertices[1].abs_diff_eq(Vec3::new(64., 32., 0.), 0.001));
+        assert!(front.vertices[3].abs_diff_eq(Vec3::new(64., 64., 0.), 0.001));
+        // The back side sees the part where the left floor is higher
+        let back = lower[1];
+        assert_eq!(back.side, LineSide::Back);
+        assert_eq!(back.triangles().count(), 1);
+        assert!(back.vertices[0].abs_diff_eq(Vec3::new(64., 0., 0.), 0.001));
+        assert!(back.vertices[1].abs_diff_eq(Vec3::new(64., 32., 0.), 0.001));
+        assert!(back.vertices[3].abs_diff_eq(Vec3::new(64., 0., -32.), 0.001));
+    }
 }
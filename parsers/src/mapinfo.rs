@@ -0,0 +1,180 @@
+/// The parser for MAPINFO lumps
+pub mod parser {
+    use pest_derive::Parser;
+    #[derive(Debug, Parser)]
+    #[grammar = "pest-grammars/common.pest"]
+    #[grammar = "pest-grammars/mapinfo.pest"]
+    pub struct MapInfoParser;
+}
+
+use std::{str::FromStr, error::Error};
+use pest::{Parser, iterators::Pair};
+use thiserror::Error;
+use parser::{Rule, MapInfoParser};
+
+#[derive(Debug, Error)]
+pub enum MapInfoError {
+    #[error("Could not parse the MAPINFO\n{orig_error}")]
+    MapInfoParseError { orig_error: Box<dyn Error> },
+}
+
+/// A property of a MAPINFO block, like `sky1 = "SKY1", 0`. Flags, like
+/// `nointermission`, have no values.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MapInfoProperty {
+    pub key: String,
+    pub values: Vec<String>,
+}
+
+/// A MAPINFO block, like `map MAP01 "Entryway" { ... }`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MapInfoBlock {
+    /// What kind of block this is, like "map" or "gameinfo"
+    pub kind: String,
+    /// The values between the kind and the opening brace, like the lump and
+    /// name of a map
+    pub header: Vec<String>,
+    pub properties: Vec<MapInfoProperty>,
+    /// Blocks inside of this block
+    pub blocks: Vec<MapInfoBlock>,
+}
+
+impl MapInfoBlock {
+    /// Get the values of the last property with the given key, ignoring case
+    pub fn property(&self, key: &str) -> Option<&[String]> {
+        self.properties.iter().rev()
+            .find(|property| property.key.eq_ignore_ascii_case(key))
+            .map(|property| property.values.as_slice())
+    }
+
+    fn is(&self, kind: &str) -> bool {
+        self.kind.eq_ignore_ascii_case(kind)
+    }
+}
+
+/// The blocks in a MAPINFO lump, in the "new" format which ZDoom and its
+/// descendants use. The older, line-based format isn't supported.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MapInfo {
+    pub blocks: Vec<MapInfoBlock>,
+    /// The files included using `include`, which have to be parsed
+    /// separately
+    pub includes: Vec<String>,
+}
+
+impl MapInfo {
+    /// Get the definition of the map with the given lump name, like "MAP01"
+    pub fn map(&self, lump: &str) -> Option<&MapInfoBlock> {
+        self.blocks.iter().rev().find(|block| block.is("map") &&
+            block.header.first().is_some_and(|name| name.eq_ignore_ascii_case(lump)))
+    }
+
+    /// Get the values of a property of the map with the given lump name. If
+    /// the map doesn't have the property, the defaults set by the
+    /// `defaultmap` and `adddefaultmap` blocks before it are used.
+    pub fn map_property(&self, lump: &str, key: &str) -> Option<&[String]> {
+        let mut defaults: Vec<&MapInfoProperty> = Vec::new();
+        let mut found = None;
+        self.blocks.iter().for_each(|block| {
+            if block.is("defaultmap") {
+                defaults = block.properties.iter().collect();
+            } else if block.is("adddefaultmap") {
+                defaults.extend(&block.properties);
+            } else if block.is("map") &&
+                block.header.first().is_some_and(|name| name.eq_ignore_ascii_case(lump)) {
+                found = block.property(key).or_else(|| defaults.iter().rev()
+                    .find(|property| property.key.eq_ignore_ascii_case(key))
+                    .map(|property| property.values.as_slice()));
+            }
+        });
+        found
+    }
+
+    /// Get the values of a property of the `gameinfo` block
+    pub fn gameinfo_property(&self, key: &str) -> Option<&[String]> {
+        self.blocks.iter().rev().filter(|block| block.is("gameinfo"))
+            .find_map(|block| block.property(key))
+    }
+
+    /// Get the name of the sky texture of the map with the given lump name
+    pub fn sky1(&self, lump: &str) -> Option<&str> {
+        self.map_property(lump, "sky1")
+            .and_then(|values| values.first()).map(String::as_str)
+    }
+}
+
+fn parse_value(token: Pair<'_, Rule>) -> String {
+    let token = token.into_inner().next().unwrap();
+    let text = token.as_str();
+    match token.as_rule() {
+        // Remove quotation marks at start/end
+        Rule::text_piece => text[1..text.len()-1].replace("\\n", "\n"),
+        Rule::integer => String::from(text.trim_end_matches(['u', 'U', 'l', 'L'])),
+        Rule::decimal => String::from(text.trim_end_matches(['f', 'F'])),
+        Rule::identifier => String::from(text),
+        unknown => unreachable!("Rule: {unknown:?}"),
+    }
+}
+
+fn parse_block(token: Pair<'_, Rule>) -> MapInfoBlock {
+    let mut block = MapInfoBlock::default();
+    token.into_inner().for_each(|token| {
+        match token.as_rule() {
+            Rule::identifier => {
+                block.kind.push_str(token.as_str());
+            },
+            Rule::mapinfo_value => {
+                block.header.push(parse_value(token));
+            },
+            Rule::mapinfo_property => {
+                let mut property = MapInfoProperty::default();
+                token.into_inner().for_each(|token| {
+                    match token.as_rule() {
+                        Rule::identifier => {
+                            property.key.push_str(token.as_str());
+                        },
+                        Rule::mapinfo_value => {
+                            property.values.push(parse_value(token));
+                        },
+                        unknown => unreachable!("Rule: {unknown:?}"),
+                    }
+                });
+                block.properties.push(property);
+            },
+            Rule::mapinfo_block => {
+                block.blocks.push(parse_block(token));
+            },
+            unknown => unreachable!("Rule: {unknown:?}"),
+        }
+    });
+    block
+}
+
+impl FromStr for MapInfo {
+    type Err = MapInfoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let root = MapInfoParser::parse(Rule::mapinfo, s).map_err(|e| {
+            let orig_error = Box::from(e);
+            MapInfoError::MapInfoParseError { orig_error }
+        })?;
+        let mut mapinfo = MapInfo::default();
+        root.for_each(|token| {
+            match token.as_rule() {
+                Rule::mapinfo_block => {
+                    mapinfo.blocks.push(parse_block(token));
+                },
+                Rule::mapinfo_directive => {
+                    // `clearepisodes` and `clearskills` don't matter here
+                    if let Some(file) = token.into_inner().next() {
+                        let text = file.as_str();
+                        mapinfo.includes.push(String::from(&text[1..text.len()-1]));
+                    }
+                },
+                Rule::EOI => (),
+                unknown => unreachable!("Rule: {unknown:?}"),
+            }
+        });
+        Ok(mapinfo)
+    }
+}
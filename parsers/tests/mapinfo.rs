@@ -0,0 +1,52 @@
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, str::FromStr};
+    use parsers::mapinfo::MapInfo;
+
+    const MAPINFO: &str = r#"
+include "mapinfo/common.txt"
+clearepisodes
+gameinfo
+{
+    SkyFlatName = "F_SKY2"
+}
+
+defaultmap
+{
+    sky1 = "SKY1", 0
+    nointermission
+}
+
+// MAP02 uses the default sky
+map MAP01 lookup "HUSTR_1"
+{
+    levelnum = 1
+    next = MAP02
+    sky1 = "SKY3", 0.5
+}
+
+map MAP02 "Underhalls" { levelnum = 2 }
+
+intermission Inter_Cast
+{
+    Image { Background = "BOSSBACK" }
+}
+"#;
+
+    #[test]
+    fn can_read_mapinfo() -> Result<(), Box<dyn Error>> {
+        let mapinfo = MapInfo::from_str(MAPINFO)?;
+        assert_eq!(mapinfo.includes, vec!["mapinfo/common.txt"]);
+        assert_eq!(mapinfo.blocks.len(), 5);
+        let map01 = mapinfo.map("map01").unwrap();
+        assert_eq!(map01.header, vec!["MAP01", "lookup", "HUSTR_1"]);
+        assert_eq!(map01.property("next").unwrap(), ["MAP02"]);
+        assert_eq!(mapinfo.map_property("MAP01", "sky1").unwrap(), ["SKY3", "0.5"]);
+        assert_eq!(mapinfo.sky1("MAP02"), Some("SKY1"));
+        assert_eq!(mapinfo.map_property("MAP02", "nointermission").unwrap(), [] as [String; 0]);
+        assert_eq!(mapinfo.sky1("MAP03"), None);
+        assert_eq!(mapinfo.gameinfo_property("skyflatname").unwrap(), ["F_SKY2"]);
+        assert_eq!(mapinfo.blocks[4].blocks[0].property("background").unwrap(), ["BOSSBACK"]);
+        Ok(())
+    }
+}